@@ -0,0 +1,222 @@
+// Alt-Svc 响应头解析 (RFC 7838)
+use serde::{Deserialize, Serialize};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// 未携带 ma 参数时的默认有效期 (24 小时)
+pub const DEFAULT_MAX_AGE: u64 = 86400;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AltSvcEntry {
+    pub protocol_id: String,  // 已做百分号解码的 ALPN，如 "h3"、"h3-29"
+    pub host: Option<String>, // alt-authority 中的主机，省略时与源站相同
+    pub port: u16,
+    pub max_age: u64,
+    pub persist: bool, // persist=1: 网络切换后仍然有效
+}
+
+impl AltSvcEntry {
+    pub fn is_h3(&self) -> bool {
+        self.protocol_id == "h3" || self.protocol_id.starts_with("h3-")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AltSvc {
+    Clear, // 源站要求清除此前缓存的所有替代服务
+    Alternatives(Vec<AltSvcEntry>),
+}
+
+impl AltSvc {
+    pub fn entries(&self) -> &[AltSvcEntry] {
+        match self {
+            AltSvc::Clear => &[],
+            AltSvc::Alternatives(entries) => entries,
+        }
+    }
+
+    // 优先选择正式版 h3，其次按出现顺序选择草案版本
+    pub fn preferred_h3(&self) -> Option<&AltSvcEntry> {
+        let entries = self.entries();
+        entries
+            .iter()
+            .find(|e| e.protocol_id == "h3")
+            .or_else(|| entries.iter().find(|e| e.is_h3()))
+    }
+}
+
+// 解析一个或多个 (以逗号拼接的) Alt-Svc 头字段值
+pub fn parse_alt_svc(value: &str) -> Result<AltSvc, String> {
+    if value.trim() == "clear" {
+        return Ok(AltSvc::Clear);
+    }
+
+    let mut scanner = Scanner { chars: value.chars().peekable() };
+    let mut entries = Vec::new();
+    loop {
+        scanner.skip_ows();
+        match scanner.chars.peek() {
+            None => break,
+            // 列表允许空元素
+            Some(',') => {
+                scanner.chars.next();
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let protocol_id = scanner.token().ok_or("缺少 protocol-id")?;
+        let protocol_id = urlencoding::decode(&protocol_id)
+            .map_err(|_| format!("无效的 protocol-id: {}", protocol_id))?
+            .into_owned();
+        scanner.expect('=')?;
+        let authority = scanner.quoted_string()?;
+        let (host, port) = parse_alt_authority(&authority)?;
+
+        let mut entry = AltSvcEntry {
+            protocol_id,
+            host,
+            port,
+            max_age: DEFAULT_MAX_AGE,
+            persist: false,
+        };
+
+        loop {
+            scanner.skip_ows();
+            if scanner.chars.peek() != Some(&';') {
+                break;
+            }
+            scanner.chars.next();
+            scanner.skip_ows();
+            let name = scanner.token().ok_or("缺少参数名")?;
+            scanner.expect('=')?;
+            let value = match scanner.chars.peek() {
+                Some('"') => scanner.quoted_string()?,
+                _ => scanner.token().ok_or_else(|| format!("参数 {} 缺少取值", name))?,
+            };
+            // 未知参数按 RFC 7838 忽略
+            match name.to_ascii_lowercase().as_str() {
+                "ma" => entry.max_age = value.parse().map_err(|_| format!("无效的 ma: {}", value))?,
+                "persist" => entry.persist = value == "1",
+                _ => {}
+            }
+        }
+        entries.push(entry);
+
+        scanner.skip_ows();
+        match scanner.chars.next() {
+            None => break,
+            Some(',') => {}
+            Some(c) => return Err(format!("意外的字符: {:?}", c)),
+        }
+    }
+
+    if entries.is_empty() {
+        return Err("Alt-Svc 为空".to_string());
+    }
+    Ok(AltSvc::Alternatives(entries))
+}
+
+// alt-authority = [ uri-host ] ":" port
+fn parse_alt_authority(authority: &str) -> Result<(Option<String>, u16), String> {
+    let (host, port) = authority
+        .rsplit_once(':')
+        .ok_or_else(|| format!("alt-authority 缺少端口: {}", authority))?;
+    let port = port.parse().map_err(|_| format!("无效的端口: {}", authority))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok(((!host.is_empty()).then(|| host.to_string()), port))
+}
+
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Scanner<'_> {
+    fn skip_ows(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\t')) {
+            self.chars.next();
+        }
+    }
+
+    fn token(&mut self) -> Option<String> {
+        let mut token = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)) {
+                break;
+            }
+            token.push(c);
+            self.chars.next();
+        }
+        (!token.is_empty()).then_some(token)
+    }
+
+    fn quoted_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(self.chars.next().ok_or("引号字符串未结束")?),
+                Some(c) => value.push(c),
+                None => return Err("引号字符串未结束".to_string()),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("应为 {:?}，实际为 {:?}", expected, c)),
+            None => Err(format!("应为 {:?}，实际已结束", expected)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cloudflare_alt_svc() {
+        let alt_svc = parse_alt_svc(r#"h3=":443"; ma=86400, h3-29=":443"; ma=86400"#).unwrap();
+        let entries = alt_svc.entries();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].protocol_id, "h3");
+        assert_eq!(entries[0].host, None);
+        assert_eq!(entries[0].port, 443);
+        assert_eq!(entries[0].max_age, 86400);
+        assert_eq!(entries[1].protocol_id, "h3-29");
+        assert_eq!(alt_svc.preferred_h3().unwrap().protocol_id, "h3");
+    }
+
+    #[test]
+    fn test_parse_alt_authority_and_parameters() {
+        let alt_svc = parse_alt_svc(
+            r#"h2="alt.example.com:8443"; persist=1; foo="a;b", w%3Dx="[2001:db8::1]:443",h3-34=":8443""#,
+        )
+        .unwrap();
+        let entries = alt_svc.entries();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].host.as_deref(), Some("alt.example.com"));
+        assert_eq!(entries[0].port, 8443);
+        assert!(entries[0].persist);
+        assert_eq!(entries[0].max_age, DEFAULT_MAX_AGE);
+        assert_eq!(entries[1].protocol_id, "w=x");
+        assert_eq!(entries[1].host.as_deref(), Some("2001:db8::1"));
+        assert_eq!(alt_svc.preferred_h3().unwrap().protocol_id, "h3-34");
+    }
+
+    #[test]
+    fn test_parse_clear_and_invalid() {
+        assert_eq!(parse_alt_svc("clear"), Ok(AltSvc::Clear));
+        assert_eq!(parse_alt_svc(r#"h2=":443""#).unwrap().preferred_h3(), None);
+        assert!(parse_alt_svc("").is_err());
+        assert!(parse_alt_svc(r#"h3=":443"#).is_err());
+        assert!(parse_alt_svc(r#"h3="example.com""#).is_err());
+        assert!(parse_alt_svc(r#"h3=":443"; ma=soon"#).is_err());
+        assert!(parse_alt_svc(r#"h3=":443" h2=":443""#).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// ECH (Encrypted Client Hello) - 解析 HTTPS 记录中的 ECHConfigList，HPKE 由 rustls 的 aws-lc-rs 后端提供 (pq 特性)
+use anyhow::{Context, Result};
+use h3_quinn::quinn;
+use rustls::client::EchConfig;
+use rustls::crypto::hpke::Hpke;
+use rustls::pki_types::EchConfigListBytes;
+use rustls::{AlertDescription, PeerIncompatible};
+use serde::{Deserialize, Serialize};
+
+// --- 1. HPKE 套件 ---
+// ring 后端不带 HPKE，ECH 与后量子密钥交换共用 aws-lc-rs 后端 (pq 特性)
+#[cfg(feature = "pq")]
+fn hpke_suites() -> Result<&'static [&'static dyn Hpke]> {
+    Ok(rustls::crypto::aws_lc_rs::hpke::ALL_SUPPORTED_SUITES)
+}
+
+#[cfg(not(feature = "pq"))]
+fn hpke_suites() -> Result<&'static [&'static dyn Hpke]> {
+    anyhow::bail!("当前构建不支持 ECH 所需的 HPKE，请使用 --features pq 重新编译")
+}
+
+// --- 2. HTTPS 记录中的 ECHConfigList ---
+// trust-dns 解码 ech SvcParam 时会去掉 ECHConfigList 自身的 2 字节长度前缀，交给 rustls 前需要补回
+pub fn config_list_from_svc_param(value: &[u8]) -> Vec<u8> {
+    let mut list = Vec::with_capacity(value.len() + 2);
+    list.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    list.extend_from_slice(value);
+    list
+}
+
+// ECHConfigList 中各个可识别版本配置的 public_name，即 ECH 被拒绝时外层 ClientHello 的 SNI
+pub fn public_names(config_list: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let Some(mut configs) = read_u16_prefixed(&mut &config_list[..]) else { return names };
+    while !configs.is_empty() {
+        let (Some(version), Some(mut contents)) = (read_u16(&mut configs), read_u16_prefixed(&mut configs)) else {
+            break;
+        };
+        if version == ECH_VERSION {
+            if let Some(name) = read_public_name(&mut contents) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+// draft-ietf-tls-esni-18 的 ECHConfig 版本
+const ECH_VERSION: u16 = 0xfe0d;
+
+// ECHConfigContents: HpkeKeyConfig (config_id、kem_id、public_key、cipher_suites)、maximum_name_length、public_name
+fn read_public_name(contents: &mut &[u8]) -> Option<String> {
+    take(contents, 3)?;
+    read_u16_prefixed(contents)?;
+    read_u16_prefixed(contents)?;
+    take(contents, 1)?;
+    let len = *take(contents, 1)?.first()? as usize;
+    String::from_utf8(take(contents, len)?.to_vec()).ok()
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Some(head)
+}
+
+fn read_u16(input: &mut &[u8]) -> Option<u16> {
+    take(input, 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u16_prefixed<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_u16(input)? as usize;
+    take(input, len)
+}
+
+// 一次 ECH 尝试使用的配置，public_names 用于在 ECH 被拒绝时验证外层握手的证书
+#[derive(Debug, Clone)]
+pub struct EchOffer {
+    pub config: EchConfig,
+    pub public_names: Vec<String>,
+}
+
+impl EchOffer {
+    // rustls 选择第一个使用本地支持的 HPKE 套件的配置；没有兼容配置时返回错误，此时不发送 ECH
+    pub fn new(config_list: &[u8]) -> Result<Self> {
+        let config = EchConfig::new(EchConfigListBytes::from(config_list), hpke_suites()?)
+            .context("ECHConfigList 中没有可用的配置")?;
+        Ok(Self { config, public_names: public_names(config_list) })
+    }
+}
+
+// --- 3. ECH 结果判断 ---
+// quinn / reqwest 不暴露 rustls 的 ech_status，根据握手结果推断：rustls 在 ECH 被拒绝时总会在握手末尾
+// 以 ServerRejectedEncryptedClientHello 中止，因此握手完成即表示被接受。
+// 外层证书只对 public_name 有效时由 RecordingVerifier 按 public_name 验证，使握手能走到这一步
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EchOutcome {
+    NotOffered, // 没有可用的 ECH 配置，按普通握手连接
+    Accepted,   // 真实 SNI 已加密
+    Rejected,   // 服务端使用外层 ClientHello，真实 SNI 未发送
+    Unknown,    // 握手在判断 ECH 结果前失败 (超时、网络错误、证书无效等)
+}
+
+impl std::fmt::Display for EchOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EchOutcome::NotOffered => "not_offered",
+            EchOutcome::Accepted => "accepted",
+            EchOutcome::Rejected => "rejected",
+            EchOutcome::Unknown => "unknown",
+        })
+    }
+}
+
+// 握手错误中的 ECH 拒绝：Some(retry_configs)，retry_configs 为 None 表示无法得知服务端是否给出了新配置。
+// TCP 上可从错误链取出 rustls::Error；quinn 只保留本端发出的 TLS 告警 (ech_required)
+pub fn rejection(error: &anyhow::Error) -> Option<Option<bool>> {
+    let ech_required = quinn::TransportErrorCode::crypto(u8::from(AlertDescription::EncryptedClientHelloRequired));
+    error.chain().find_map(|cause| {
+        if let Some(quinn::ConnectionError::TransportError(e)) = cause.downcast_ref() {
+            return (e.code == ech_required).then_some(None);
+        }
+        match tls_error(cause)? {
+            rustls::Error::PeerIncompatible(PeerIncompatible::ServerRejectedEncryptedClientHello(retry_configs)) => {
+                Some(Some(retry_configs.is_some()))
+            }
+            _ => None,
+        }
+    })
+}
+
+// hyper / tokio-rustls 把 rustls::Error 包在 (可能多层的) io::Error 中，source() 不会展开这一层
+fn tls_error<'a>(cause: &'a (dyn std::error::Error + 'static)) -> Option<&'a rustls::Error> {
+    if let Some(e) = cause.downcast_ref::<rustls::Error>() {
+        return Some(e);
+    }
+    tls_error(cause.downcast_ref::<std::io::Error>()?.get_ref()?)
+}
+
+// 返回 (结果, 是否收到 retry_configs)；verified_name 为证书验证通过时使用的名称，rejection 来自 rejection()
+pub fn classify(
+    handshake_completed: bool,
+    verified_name: Option<&str>,
+    inner_sni: &str,
+    rejection: Option<Option<bool>>,
+) -> (EchOutcome, Option<bool>) {
+    if let Some(retry_configs) = rejection {
+        return (EchOutcome::Rejected, retry_configs);
+    }
+    if handshake_completed {
+        return (EchOutcome::Accepted, None);
+    }
+    match verified_name {
+        // 对端出示了 public_name 的证书，即按外层 ClientHello 完成了握手
+        Some(name) if !name.trim_end_matches('.').eq_ignore_ascii_case(inner_sni.trim_end_matches('.')) => {
+            (EchOutcome::Rejected, None)
+        }
+        _ => (EchOutcome::Unknown, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_list_from_svc_param() {
+        let list = test_support::config_list("public.example", 0x0001);
+        assert_eq!(config_list_from_svc_param(&list[2..]), list);
+        assert_eq!(public_names(&list), vec!["public.example"]);
+        assert!(public_names(b"\x00\x01").is_empty());
+
+        // 需要 aws-lc-rs 提供 HPKE；只有 EXPORT_ONLY 套件的配置无法使用
+        assert_eq!(EchOffer::new(&list).is_ok(), crate::tls_trust::PQ_AVAILABLE);
+        assert!(EchOffer::new(&test_support::config_list("public.example", 0xffff)).is_err());
+    }
+
+    #[test]
+    fn test_classify_ech_outcome() {
+        assert_eq!(classify(true, Some("inner.example"), "inner.example", None), (EchOutcome::Accepted, None));
+        assert_eq!(classify(false, Some("public.example"), "inner.example", Some(Some(true))),
+                   (EchOutcome::Rejected, Some(true)));
+        assert_eq!(classify(false, None, "inner.example", Some(None)), (EchOutcome::Rejected, None));
+        assert_eq!(classify(false, Some("public.example"), "inner.example", None), (EchOutcome::Rejected, None));
+        assert_eq!(classify(false, Some("Inner.Example."), "inner.example", None), (EchOutcome::Unknown, None));
+        assert_eq!(classify(false, None, "inner.example", None), (EchOutcome::Unknown, None));
+    }
+
+    #[test]
+    fn test_rejection_from_typed_errors() {
+        let rejected = rustls::Error::PeerIncompatible(PeerIncompatible::ServerRejectedEncryptedClientHello(None));
+        let io = std::io::Error::new(std::io::ErrorKind::InvalidData, rejected);
+        let io = std::io::Error::other(io);
+        assert_eq!(rejection(&anyhow::Error::new(io).context("TCP 请求失败")), Some(Some(false)));
+
+        let alert = quinn::ConnectionError::TransportError(quinn_proto::TransportError {
+            code: quinn::TransportErrorCode::crypto(u8::from(AlertDescription::EncryptedClientHelloRequired)),
+            frame: None,
+            reason: String::new(),
+        });
+        assert_eq!(rejection(&anyhow::Error::new(alert)), Some(None));
+
+        let other = rustls::Error::PeerIncompatible(PeerIncompatible::Tls13RequiredForQuic);
+        assert_eq!(rejection(&anyhow::Error::new(other)), None);
+    }
+}
+
+// 测试用 ECHConfigList：固定的 X25519 公钥，服务端无对应私钥，只能用于观察拒绝路径
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::ECH_VERSION;
+
+    fn u16_prefixed(body: &[u8]) -> Vec<u8> {
+        [&(body.len() as u16).to_be_bytes()[..], body].concat()
+    }
+
+    pub(crate) fn config_list(public_name: &str, aead_id: u16) -> Vec<u8> {
+        let mut contents = vec![1]; // config_id
+        contents.extend_from_slice(&0x0020u16.to_be_bytes()); // DHKEM(X25519, HKDF-SHA256)
+        contents.extend(u16_prefixed(&[0x42; 32]));
+        contents.extend(u16_prefixed(&[&0x0001u16.to_be_bytes()[..], &aead_id.to_be_bytes()].concat())); // HKDF-SHA256
+        contents.push(0); // maximum_name_length
+        contents.push(public_name.len() as u8);
+        contents.extend_from_slice(public_name.as_bytes());
+        contents.extend(u16_prefixed(&[])); // extensions
+
+        let config = [&ECH_VERSION.to_be_bytes()[..], &u16_prefixed(&contents)].concat();
+        u16_prefixed(&config)
+    }
+}
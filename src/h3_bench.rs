@@ -0,0 +1,455 @@
+// HTTP/3 压测模块 - 类似 h2load，对单个 IP/SNI/Host 组合施加负载
+use anyhow::{Context, Result};
+use bytes::Buf;
+use http::{Method, Request};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+use crate::h3_direct_test::{H3SendRequest, H3TestConfig, H3Tester};
+use crate::quic_transport::QuicTransportOptions;
+
+// 直方图桶上界（毫秒），最后一个桶收集其余样本
+const HISTOGRAM_BOUNDS_MS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+// --- 1. 压测配置 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BenchConfig {
+    pub target_ip: IpAddr,
+    pub port: u16,
+    pub sni: String,  // TLS SNI
+    pub host: String, // :authority / Host
+    pub path: String,
+    pub connections: usize,            // -c: 连接数
+    pub streams: usize,                // -m: 每个连接上的并发流数
+    pub total_requests: usize,         // -n: 总请求数（未指定 duration 时生效）
+    pub duration_seconds: Option<u64>, // -D: 按时长压测
+    pub timeout_seconds: u64,
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub transport: QuicTransportOptions, // h3 连接的 QUIC 传输参数
+}
+
+// --- 2. 压测结果 ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HistogramBucket {
+    pub le_ms: Option<u64>, // None 表示超过最后一个上界
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LatencyHistogram {
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+impl LatencyHistogram {
+    pub fn from_latencies(latencies: &[Duration]) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted: Vec<f64> = latencies.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+
+        let mut buckets: Vec<HistogramBucket> = HISTOGRAM_BOUNDS_MS
+            .iter()
+            .map(|&le| HistogramBucket { le_ms: Some(le), count: 0 })
+            .chain(std::iter::once(HistogramBucket { le_ms: None, count: 0 }))
+            .collect();
+        for &latency in &sorted {
+            let index = HISTOGRAM_BOUNDS_MS
+                .iter()
+                .position(|&le| latency <= le as f64)
+                .unwrap_or(HISTOGRAM_BOUNDS_MS.len());
+            buckets[index].count += 1;
+        }
+
+        Self {
+            min_ms: sorted[0],
+            mean_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50_ms: percentile(0.50),
+            p90_ms: percentile(0.90),
+            p99_ms: percentile(0.99),
+            max_ms: sorted[sorted.len() - 1],
+            buckets,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BenchReport {
+    pub protocol: String, // "h3" 或 "h2"
+    pub target: String,
+    pub connections: usize,
+    pub streams: usize,
+    pub requests_started: usize,
+    pub requests_succeeded: usize,
+    pub requests_failed: usize,
+    pub elapsed_ms: u64,
+    pub requests_per_sec: f64,
+    pub bytes_received: u64,
+    pub status_codes: BTreeMap<u16, usize>,
+    pub errors: BTreeMap<String, usize>, // 按类别统计: connect, timeout, request, http_4xx, http_5xx
+    pub latency: LatencyHistogram,
+}
+
+// 单个请求的结果
+struct Sample {
+    latency: Duration,
+    bytes: u64,
+    status: Option<u16>,
+    error: Option<&'static str>,
+}
+
+impl Sample {
+    fn failed(latency: Duration, class: &'static str) -> Self {
+        Self { latency, bytes: 0, status: None, error: Some(class) }
+    }
+
+    fn response(latency: Duration, status: u16, bytes: u64) -> Self {
+        let error = match status {
+            400..=499 => Some("http_4xx"),
+            500..=599 => Some("http_5xx"),
+            _ => None,
+        };
+        Self { latency, bytes, status: Some(status), error }
+    }
+}
+
+// 请求配额：按总请求数或截止时间发放
+struct Budget {
+    remaining: AtomicUsize,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    fn new(config: &BenchConfig) -> Self {
+        Self {
+            remaining: AtomicUsize::new(config.total_requests),
+            deadline: config.duration_seconds.map(|s| Instant::now() + Duration::from_secs(s)),
+        }
+    }
+
+    fn take(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => Instant::now() < deadline,
+            None => self
+                .remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok(),
+        }
+    }
+}
+
+impl BenchConfig {
+    fn target_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.target_ip, self.port)
+    }
+
+    fn url(&self) -> String {
+        format!("https://{}{}", self.host, self.path)
+    }
+
+    fn h3_config(&self) -> H3TestConfig {
+        H3TestConfig {
+            host_header: Some(self.host.clone()),
+            test_path: self.path.clone(),
+            timeout_seconds: self.timeout_seconds,
+            user_agent: self.user_agent.clone(),
+            max_concurrent_requests: self.streams,
+            transport: self.transport.clone(),
+            ..H3TestConfig::new(&self.sni, self.target_ip, self.port)
+        }
+    }
+
+    fn report(&self, protocol: &str, samples: Vec<Sample>, connect_errors: usize, elapsed: Duration) -> BenchReport {
+        let mut report = BenchReport {
+            protocol: protocol.to_string(),
+            target: format!("{} (SNI {}, Host {})", self.target_addr(), self.sni, self.host),
+            connections: self.connections,
+            streams: self.streams,
+            requests_started: samples.len(),
+            elapsed_ms: elapsed.as_millis() as u64,
+            ..Default::default()
+        };
+        if connect_errors > 0 {
+            report.errors.insert("connect".to_string(), connect_errors);
+        }
+
+        let mut latencies = Vec::new();
+        for sample in &samples {
+            report.bytes_received += sample.bytes;
+            if let Some(status) = sample.status {
+                *report.status_codes.entry(status).or_default() += 1;
+            }
+            match sample.error {
+                Some(class) => {
+                    report.requests_failed += 1;
+                    *report.errors.entry(class.to_string()).or_default() += 1;
+                }
+                None => {
+                    report.requests_succeeded += 1;
+                    latencies.push(sample.latency);
+                }
+            }
+        }
+
+        report.requests_per_sec = report.requests_succeeded as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        report.latency = LatencyHistogram::from_latencies(&latencies);
+        report
+    }
+}
+
+// --- 3. 原生 h3 压测 ---
+pub async fn run_h3_bench(tester: &H3Tester, config: &BenchConfig) -> BenchReport {
+    let h3_config = config.h3_config();
+    let budget = Budget::new(config);
+    let start_time = Instant::now();
+
+    let per_connection = futures::future::join_all((0..config.connections).map(|_| async {
+        let session = match tester.connect(&h3_config).await {
+            Ok(session) => session,
+            Err(_) => return (Vec::new(), 1),
+        };
+
+        let per_stream = futures::future::join_all((0..config.streams).map(|_| {
+            let mut send_request = session.send_request.clone();
+            let budget = &budget;
+            async move {
+                let mut samples = Vec::new();
+                while budget.take() {
+                    samples.push(h3_bench_request(&mut send_request, config).await);
+                }
+                samples
+            }
+        }))
+        .await;
+
+        (per_stream.into_iter().flatten().collect::<Vec<_>>(), 0)
+    }))
+    .await;
+
+    let elapsed = start_time.elapsed();
+    let connect_errors = per_connection.iter().map(|(_, errors)| errors).sum();
+    let samples = per_connection.into_iter().flat_map(|(samples, _)| samples).collect();
+    config.report("h3", samples, connect_errors, elapsed)
+}
+
+async fn h3_bench_request(send_request: &mut H3SendRequest, config: &BenchConfig) -> Sample {
+    let start_time = Instant::now();
+
+    let result = timeout(Duration::from_secs(config.timeout_seconds), async {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(config.url())
+            .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-bench/1.0"))
+            .body(())?;
+
+        let mut stream = send_request.send_request(request).await
+            .context("Failed to send HTTP/3 request")?;
+        stream.finish().await
+            .context("Failed to finish request stream")?;
+        let response = stream.recv_response().await
+            .context("Failed to receive HTTP/3 response")?;
+
+        let mut bytes = 0u64;
+        while let Some(chunk) = stream.recv_data().await
+            .context("Failed to receive response data")? {
+            bytes += chunk.remaining() as u64;
+        }
+
+        Ok::<_, anyhow::Error>((response.status().as_u16(), bytes))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((status, bytes))) => Sample::response(start_time.elapsed(), status, bytes),
+        Ok(Err(_)) => Sample::failed(start_time.elapsed(), "request"),
+        Err(_) => Sample::failed(start_time.elapsed(), "timeout"),
+    }
+}
+
+// --- 4. reqwest h2 对照压测 ---
+pub async fn run_h2_bench(tester: &H3Tester, config: &BenchConfig) -> Result<BenchReport> {
+    let budget = Budget::new(config);
+
+    // 每个 reqwest 客户端对应一条 h2 连接，其上的并发请求多路复用
+    let clients = (0..config.connections)
+        .map(|_| {
+            reqwest::Client::builder()
+                .use_preconfigured_tls(tester.h2_tls_config()?)
+                .http2_prior_knowledge()
+                .resolve(&config.host, config.target_addr())
+                .timeout(Duration::from_secs(config.timeout_seconds))
+                .user_agent(config.user_agent.as_deref().unwrap_or("rust-h3-bench/1.0"))
+                .build()
+                .context("创建 h2 客户端失败")
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let start_time = Instant::now();
+    let per_stream = futures::future::join_all(clients.iter().flat_map(|client| {
+        let budget = &budget;
+        (0..config.streams).map(move |_| async move {
+            let mut samples = Vec::new();
+            while budget.take() {
+                samples.push(h2_bench_request(client, config).await);
+            }
+            samples
+        })
+    }))
+    .await;
+
+    let elapsed = start_time.elapsed();
+    let samples = per_stream.into_iter().flatten().collect();
+    Ok(config.report("h2", samples, 0, elapsed))
+}
+
+async fn h2_bench_request(client: &reqwest::Client, config: &BenchConfig) -> Sample {
+    let start_time = Instant::now();
+
+    let response = match client.get(config.url()).send().await {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() => return Sample::failed(start_time.elapsed(), "timeout"),
+        Err(e) if e.is_connect() => return Sample::failed(start_time.elapsed(), "connect"),
+        Err(_) => return Sample::failed(start_time.elapsed(), "request"),
+    };
+
+    let status = response.status().as_u16();
+    match response.bytes().await {
+        Ok(body) => Sample::response(start_time.elapsed(), status, body.len() as u64),
+        Err(e) if e.is_timeout() => Sample::failed(start_time.elapsed(), "timeout"),
+        Err(_) => Sample::failed(start_time.elapsed(), "request"),
+    }
+}
+
+// --- 5. 压测报告 ---
+pub fn generate_bench_report(reports: &[BenchReport]) -> String {
+    let mut report = String::new();
+
+    for bench in reports {
+        report.push_str(&format!("=== {} 压测: {} ===\n", bench.protocol, bench.target));
+        report.push_str(&format!("连接数: {}, 每连接并发流: {}\n", bench.connections, bench.streams));
+        report.push_str(&format!("请求: {} 发出, {} 成功, {} 失败\n",
+                                 bench.requests_started, bench.requests_succeeded, bench.requests_failed));
+        report.push_str(&format!("耗时: {}ms, 吞吐: {:.2} req/s, 接收: {} 字节\n",
+                                 bench.elapsed_ms, bench.requests_per_sec, bench.bytes_received));
+
+        let latency = &bench.latency;
+        report.push_str(&format!("延迟 (ms): min {:.2} / mean {:.2} / p50 {:.2} / p90 {:.2} / p99 {:.2} / max {:.2}\n",
+                                 latency.min_ms, latency.mean_ms, latency.p50_ms,
+                                 latency.p90_ms, latency.p99_ms, latency.max_ms));
+
+        let total: usize = latency.buckets.iter().map(|b| b.count).sum();
+        for bucket in latency.buckets.iter().filter(|b| b.count > 0) {
+            let label = match bucket.le_ms {
+                Some(le) => format!("<= {}ms", le),
+                None => format!("> {}ms", HISTOGRAM_BOUNDS_MS[HISTOGRAM_BOUNDS_MS.len() - 1]),
+            };
+            let bar = "#".repeat((bucket.count * 40).div_ceil(total.max(1)));
+            report.push_str(&format!("  {:<10} {:>8} {}\n", label, bucket.count, bar));
+        }
+
+        if !bench.status_codes.is_empty() {
+            let codes: Vec<String> = bench.status_codes.iter().map(|(code, n)| format!("{}: {}", code, n)).collect();
+            report.push_str(&format!("状态码: {}\n", codes.join(", ")));
+        }
+        if !bench.errors.is_empty() {
+            let errors: Vec<String> = bench.errors.iter().map(|(class, n)| format!("{}: {}", class, n)).collect();
+            report.push_str(&format!("错误: {}\n", errors.join(", ")));
+        }
+        report.push('\n');
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h3_test_server::{self, TestServerOptions};
+
+    fn local_bench(addr: SocketAddr) -> BenchConfig {
+        BenchConfig {
+            target_ip: addr.ip(),
+            port: addr.port(),
+            sni: "localhost".to_string(),
+            host: "localhost".to_string(),
+            path: "/bench".to_string(),
+            connections: 2,
+            streams: 3,
+            total_requests: 20,
+            duration_seconds: None,
+            timeout_seconds: 5,
+            user_agent: None,
+            transport: QuicTransportOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_latency_histogram_percentiles() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let histogram = LatencyHistogram::from_latencies(&latencies);
+
+        assert_eq!(histogram.min_ms, 1.0);
+        assert_eq!(histogram.max_ms, 100.0);
+        assert_eq!(histogram.p50_ms, 51.0);
+        assert_eq!(histogram.p99_ms, 99.0);
+        assert_eq!(histogram.buckets.iter().map(|b| b.count).sum::<usize>(), 100);
+        assert_eq!(histogram.buckets[0].count, 1); // <= 1ms
+        assert_eq!(histogram.buckets[6].count, 50); // 50ms < x <= 100ms
+    }
+
+    #[tokio::test]
+    async fn test_h3_bench_request_count() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let report = run_h3_bench(&tester, &local_bench(server.addr)).await;
+
+        assert_eq!(report.requests_started, 20);
+        assert_eq!(report.requests_succeeded, 20);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.status_codes.get(&200), Some(&20));
+        assert_eq!(report.bytes_received, 20 * "hello from h3 test server: /bench".len() as u64);
+        assert!(report.requests_per_sec > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_h3_bench_duration() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_bench(server.addr);
+        config.duration_seconds = Some(1);
+        let report = run_h3_bench(&tester, &config).await;
+
+        assert!(report.requests_succeeded > 0);
+        assert_eq!(report.requests_failed, 0);
+        assert!(report.elapsed_ms >= 1000);
+    }
+
+    #[tokio::test]
+    async fn test_h3_bench_uses_transport_options() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        // 低于 QUIC 最小值的固定 MTU 在创建端点时即被拒绝
+        let mut config = local_bench(server.addr);
+        config.transport.fixed_mtu = Some(1000);
+        let report = run_h3_bench(&tester, &config).await;
+
+        assert_eq!(report.requests_succeeded, 0);
+        assert_eq!(report.errors.get("connect"), Some(&config.connections));
+    }
+}
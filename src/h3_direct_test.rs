@@ -1,25 +1,63 @@
 // HTTP/3 直接测试模块 - 使用 h3 库进行原生 HTTP/3 测试
 use anyhow::{Context, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use h3::{
     client::{builder, SendRequest},
-    quic,
+    ext::Protocol,
+    proto::varint::VarInt,
+    quic::StreamId,
+    ConnectionState,
 };
 use h3_quinn::quinn;
-use quinn::{ClientConfig, Endpoint, TransportConfig};
-use rustls_native_certs::CertificateResult;
+use quinn::{ClientConfig, TransportConfig};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::time::timeout;
-use http::{Method, Request, Uri};
+use http::{Method, Request};
 use rustls::{ClientConfig as RustlsClientConfig, RootCertStore};
+use trust_dns_proto::op::{Message, MessageType, Query};
+use trust_dns_proto::rr::{Name, RecordType};
+
+use crate::h3_raw_headers;
+use crate::h3_peer_params::{
+    CapturingConnection, CapturingCryptoConfig, CapturingOpenStreams, H3PeerSettings, QuicTransportParams,
+    SettingsCapture, TransportParamsCapture,
+};
+use crate::alt_svc::{parse_alt_svc, AltSvc};
+use crate::ech::{self, EchOffer, EchOutcome};
+use crate::tls_inspect::{recording_client_config, CertCapture, CertificateReport, ClientAuthResult};
+use crate::tls_trust::{KeyExchange, TlsTrustOptions, TrustPolicy, PQ_AVAILABLE};
+use crate::quic_qlog::QlogDir;
+use crate::quic_transport::{
+    CongestionController, QuicTransportOptions, DEFAULT_IDLE_TIMEOUT_MS, DEFAULT_MTU_UPPER_BOUND, QUIC_MIN_MTU,
+};
+
+// RFC 9298 默认 URI 模板
+pub const DEFAULT_MASQUE_URI_TEMPLATE: &str = "/.well-known/masque/udp/{target_host}/{target_port}/";
+
+// GREASE / 字段区扫描默认使用的 SETTINGS_MAX_FIELD_SECTION_SIZE 取值（从大到小）
+pub const DEFAULT_FIELD_SECTION_SWEEP: [u64; 6] = [16384, 8192, 4096, 1024, 256, 64];
+
+// 空闲探测使用的空闲时长 (毫秒) 与保活间隔
+pub const DEFAULT_IDLE_PROBE_INTERVALS_MS: [u64; 6] = [1_000, 5_000, 15_000, 30_000, 60_000, 120_000];
+pub const DEFAULT_KEEP_ALIVE_INTERVAL_MS: u64 = 5_000;
+
+// mtu 探测依次固定的 MTU
+pub const DEFAULT_MTU_SWEEP: [u16; 6] = [1500, 1452, 1400, 1350, 1280, 1200];
+
+// race 探测中 TCP 尝试相对 h3 的延后时间，参考 Happy Eyeballs (RFC 8305) 的 250ms 连接尝试间隔
+pub const DEFAULT_RACE_HEAD_START_MS: u64 = 250;
 
 // --- 1. HTTP/3 测试配置 ---
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct H3TestConfig {
-    pub target_domain: String,
+    pub target_domain: String, // TLS SNI
+    #[serde(default)]
+    pub host_header: Option<String>, // 请求的 :authority (TCP 上为 Host)，默认与 target_domain 相同
     pub target_ip: String,
     pub ip_version: String,
     pub port: u16,
@@ -31,6 +69,72 @@ pub struct H3TestConfig {
     pub send_grease: bool,
     pub user_agent: Option<String>,
     pub max_concurrent_requests: usize,
+    #[serde(default)]
+    pub connect_protocol: Option<String>, // 扩展 CONNECT 使用的 :protocol，默认 "webtransport"
+    #[serde(default)]
+    pub connect_path: Option<String>, // 扩展 CONNECT 的请求路径，默认与 test_path 相同
+    #[serde(default)]
+    pub masque_target: Option<String>, // CONNECT-UDP 隧道目标 host:port，默认 "1.1.1.1:53"
+    #[serde(default)]
+    pub masque_uri_template: Option<String>, // CONNECT-UDP URI 模板，默认 DEFAULT_MASQUE_URI_TEMPLATE
+    #[serde(default)]
+    pub masque_dns_name: Option<String>, // 通过隧道查询的域名，默认 target_domain
+    #[serde(default)]
+    pub transport: QuicTransportOptions, // 可选的 QUIC 传输参数 (空闲超时、窗口、MTU、拥塞控制等)
+}
+
+impl H3TestConfig {
+    // 对 ip:port 上 SNI 为 target_domain 的源站发送 GET / 的默认配置，其余字段通过结构体更新语法覆盖
+    pub fn new(target_domain: &str, ip: IpAddr, port: u16) -> Self {
+        Self {
+            target_domain: target_domain.to_string(),
+            host_header: None,
+            target_ip: ip.to_string(),
+            ip_version: if ip.is_ipv6() { "IPv6" } else { "IPv4" }.to_string(),
+            port,
+            test_path: "/".to_string(),
+            timeout_seconds: 10,
+            max_field_section_size: None,
+            enable_datagram: false,
+            enable_extended_connect: false,
+            send_grease: true,
+            user_agent: Some("rust-http3-test-tool/1.0".to_string()),
+            max_concurrent_requests: 1,
+            connect_protocol: None,
+            connect_path: None,
+            masque_target: None,
+            masque_uri_template: None,
+            masque_dns_name: None,
+            transport: QuicTransportOptions::default(),
+        }
+    }
+
+    pub fn authority(&self) -> &str {
+        self.host_header.as_deref().unwrap_or(&self.target_domain)
+    }
+
+    // 扩展 CONNECT 使用的 :protocol
+    pub fn extended_connect_protocol(&self) -> &str {
+        self.connect_protocol.as_deref().unwrap_or("webtransport")
+    }
+
+    // 请求的 authority：与 tcp_get 的 URL 一致，非 443 端口时带上端口 (host_header 已带端口时保持原样)
+    pub fn request_authority(&self) -> String {
+        let authority = self.authority();
+        let has_port = authority
+            .parse::<http::uri::Authority>()
+            .is_ok_and(|a| a.port().is_some());
+        if self.port == 443 || has_port {
+            authority.to_string()
+        } else {
+            format!("{}:{}", authority, self.port)
+        }
+    }
+
+    // h3 根据 URI 的 authority 生成 :authority 伪头，因此 Host 只能通过 URI 指定
+    pub fn request_uri(&self, path: &str) -> String {
+        format!("https://{}{}", self.request_authority(), path)
+    }
 }
 
 // --- 2. HTTP/3 测试结果 ---
@@ -45,101 +149,754 @@ pub struct H3TestResult {
     pub response_size: Option<usize>,
     pub latency_ms: u64,
     pub error_message: Option<String>,
+    pub response_header_too_big: Option<u64>, // 响应头超过本端 SETTINGS_MAX_FIELD_SECTION_SIZE 被 h3 客户端拒绝时的大小
     pub alpn_protocol: Option<String>,
     pub cipher_suite: Option<String>,
+    pub datagram: Option<H3DatagramResult>,
+    pub extended_connect: Option<H3ExtendedConnectResult>,
+    pub connect_udp: Option<H3ConnectUdpResult>,
+    pub multiplex: Option<H3MultiplexResult>,
+    pub idle: Option<H3IdleResult>,
+    pub migration: Option<H3MigrationResult>,
+    pub path_mtu: Option<H3PathMtu>,
+    pub mtu_sweep: Option<H3MtuSweep>,
+    pub congestion: Option<Vec<H3CongestionRun>>,
+    pub alt_svc: Option<H3AltSvcResult>,
+    pub race: Option<H3RaceResult>,
+    pub peer_settings: Option<H3PeerSettings>, // 对端控制流上的 SETTINGS 帧
+    pub transport_params: Option<QuicTransportParams>, // 对端 QUIC 传输参数
+    pub certificate: Option<CertificateReport>, // 对端证书链，握手因证书失败时同样记录
+    pub client_auth: Option<ClientAuthResult>,  // 对端是否请求及是否接受客户端证书
+    pub ech: Option<H3EchResult>,
+    pub key_exchange: Option<H3KeyExchangeResult>,
+    pub fallback: Option<H3FallbackResult>,
+}
+
+impl H3TestResult {
+    pub(crate) fn empty(config: &H3TestConfig, latency_ms: u64) -> Self {
+        Self {
+            config: config.clone(),
+            target_ip: config.target_ip.clone(),
+            ip_version: config.ip_version.clone(),
+            success: false,
+            protocol_version: "HTTP/3".to_string(),
+            response_status: None,
+            response_size: None,
+            latency_ms,
+            error_message: None,
+            response_header_too_big: None,
+            alpn_protocol: None,
+            cipher_suite: None,
+            datagram: None,
+            extended_connect: None,
+            connect_udp: None,
+            multiplex: None,
+            idle: None,
+            migration: None,
+            path_mtu: None,
+            mtu_sweep: None,
+            congestion: None,
+            alt_svc: None,
+            race: None,
+            peer_settings: None,
+            transport_params: None,
+            certificate: None,
+            client_auth: None,
+            ech: None,
+            key_exchange: None,
+            fallback: None,
+        }
+    }
+
+    pub fn failure(config: &H3TestConfig, latency_ms: u64, error: String) -> Self {
+        Self {
+            error_message: Some(error),
+            ..Self::empty(config, latency_ms)
+        }
+    }
+}
+
+// --- 2.1 HTTP/3 Datagram 探测结果 (RFC 9221 / RFC 9297) ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3DatagramResult {
+    pub quic_datagram_supported: bool, // 对端传输参数中是否包含 max_datagram_frame_size
+    pub max_datagram_size: Option<usize>,
+    pub h3_datagram_advertised: bool, // 对端 SETTINGS 中是否声明 SETTINGS_H3_DATAGRAM
+    pub echo_success: bool,
+    pub echo_rtt_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.2 扩展 CONNECT 探测结果 (RFC 9220) ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3ExtendedConnectResult {
+    pub settings_enable_connect_protocol: bool, // 对端 SETTINGS 中是否声明 SETTINGS_ENABLE_CONNECT_PROTOCOL
+    pub protocol: String,
+    pub path: String,
+    pub attempted: bool,
+    pub response_status: Option<u16>,
+    pub session_established: bool,
+    pub latency_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.3 MASQUE CONNECT-UDP 探测结果 (RFC 9298) ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3ConnectUdpResult {
+    pub target: String,
+    pub uri: String,
+    pub settings_enable_connect_protocol: bool,
+    pub h3_datagram_advertised: bool,
+    pub response_status: Option<u16>,
+    pub tunnel_established: bool,
+    pub connect_latency_ms: Option<u64>,
+    pub dns_response_received: bool,
+    pub dns_answer_count: Option<usize>,
+    pub dns_rtt_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.4 GREASE / 字段区大小扫描结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3SweepStep {
+    pub send_grease: bool,
+    pub max_field_section_size: Option<u64>,
+    pub success: bool,
+    pub response_status: Option<u16>,
+    pub latency_ms: u64,
+    pub rejected_by: Option<String>, // "client": 本地 h3 客户端按限制拒绝；"peer": 对端拒绝或返回错误状态
+    pub error_message: Option<String>,
+}
+
+impl H3SweepStep {
+    fn from_result(result: &H3TestResult) -> Self {
+        let rejected_by = match (result.success, result.response_status) {
+            (true, Some(status)) if status < 400 => None,
+            (true, _) => Some("peer"),
+            (false, _) if result.response_header_too_big.is_some() => Some("client"),
+            (false, _) => Some("peer"),
+        };
+
+        Self {
+            send_grease: result.config.send_grease,
+            max_field_section_size: result.config.max_field_section_size,
+            success: result.success && rejected_by.is_none(),
+            response_status: result.response_status,
+            latency_ms: result.latency_ms,
+            rejected_by: rejected_by.map(str::to_string),
+            error_message: result.error_message.clone(),
+        }
+    }
+}
+
+// --- 2.5 多路复用测试结果 ---
+// 所有时间均从本轮测试开始（建立连接之前）计起
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3StreamTiming {
+    pub index: usize,
+    pub opened_ms: Option<u64>,     // 请求流打开并发出请求头
+    pub first_byte_ms: Option<u64>, // 收到响应头
+    pub completed_ms: Option<u64>,  // 响应体读取完毕
+    pub response_status: Option<u16>,
+    pub response_size: Option<usize>,
+    pub error_message: Option<String>,
+    pub timed_out: bool, // timeout_seconds 内未完成而被放弃，区别于连接或流上的错误
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3LoadRun {
+    pub connections: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub total_ms: u64,
+    pub min_latency_ms: Option<u64>,
+    pub avg_latency_ms: Option<u64>,
+    pub max_latency_ms: Option<u64>,
+    pub latency_spread_ms: Option<u64>, // 最慢与最快请求之差，反映队头阻塞程度
+    pub streams: Vec<H3StreamTiming>,
+}
+
+impl H3LoadRun {
+    fn from_streams(connections: usize, streams: Vec<H3StreamTiming>, total_ms: u64) -> Self {
+        let latencies: Vec<u64> = streams
+            .iter()
+            .filter(|s| s.error_message.is_none())
+            .filter_map(|s| s.completed_ms)
+            .collect();
+        let min_latency_ms = latencies.iter().min().copied();
+        let max_latency_ms = latencies.iter().max().copied();
+
+        Self {
+            connections,
+            successful: latencies.len(),
+            failed: streams.len() - latencies.len(),
+            total_ms,
+            min_latency_ms,
+            avg_latency_ms: (!latencies.is_empty()).then(|| latencies.iter().sum::<u64>() / latencies.len() as u64),
+            max_latency_ms,
+            latency_spread_ms: min_latency_ms.zip(max_latency_ms).map(|(min, max)| max - min),
+            streams,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3MultiplexResult {
+    pub concurrent_requests: usize,
+    pub peer_max_streams_bidi: Option<u64>, // 对端 initial_max_streams_bidi
+    pub stream_limit_hit_at: Option<usize>, // 第一个需等待其他流结束才能打开的请求序号
+    pub multiplexed: H3LoadRun,             // 一个连接上 N 个并发双向流
+    pub separate_connections: H3LoadRun,    // N 个连接各发一个请求
+}
+
+// --- 2.6 空闲超时与保活探测结果 ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3IdleStep {
+    pub idle_ms: u64,
+    pub keep_alive_ms: Option<u64>,
+    // "ok" | "idle_timeout" (本地空闲超时) | "closed" (对端 CONNECTION_CLOSE) |
+    // "reset" (对端已丢弃连接，返回 stateless reset) | "silent_drop" (无任何响应) | "error"
+    pub outcome: String,
+    pub path_challenges: u64, // 空闲后收到的 PATH_CHALLENGE 数，非 0 说明对端看到了新的客户端地址 (NAT 重绑定)
+    pub response_status: Option<u16>,
+    pub latency_ms: Option<u64>, // 空闲后请求的耗时
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3IdleResult {
+    pub local_idle_timeout_ms: u64,
+    pub peer_idle_timeout_ms: Option<u64>, // 对端传输参数 max_idle_timeout，0 表示不限制
+    pub longest_idle_ok_ms: Option<u64>, // 不保活时空闲后仍可用的最长时长
+    pub shortest_idle_failed_ms: Option<u64>, // 不保活时空闲后不可用的最短时长
+    pub keep_alive_holds: Option<bool>, // 保活时所有空闲时长均可用
+    pub nat_rebinding_seen: bool,
+    pub silent_drops: usize,
+    pub steps: Vec<H3IdleStep>,
+}
+
+impl H3IdleStep {
+    fn classify_close(reason: &quinn::ConnectionError) -> &'static str {
+        match reason {
+            quinn::ConnectionError::TimedOut => "idle_timeout",
+            quinn::ConnectionError::Reset => "reset",
+            quinn::ConnectionError::ConnectionClosed(_) | quinn::ConnectionError::ApplicationClosed(_) => "closed",
+            _ => "error",
+        }
+    }
+}
+
+// --- 2.7 连接迁移探测结果 ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3MigrationResult {
+    pub old_local_addr: Option<String>,
+    pub new_local_addr: Option<String>,
+    pub peer_disable_active_migration: Option<bool>, // 对端传输参数 disable_active_migration
+    pub rebound: bool,                  // Endpoint::rebind 是否成功
+    pub path_challenges_received: u64,  // 换绑后收到的 PATH_CHALLENGE 数，即对端对新路径发起了验证
+    pub path_responses_sent: u64,
+    pub path_validated: bool,           // 对端验证了新路径且换绑后请求成功
+    pub request_after_ok: bool,
+    pub response_status: Option<u16>,  // 换绑后的请求
+    pub latency_ms: Option<u64>,       // 换绑后请求的耗时
+    pub rtt_before_ms: Option<u64>,
+    pub rtt_after_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.8 路径 MTU 探测结果 ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3PathMtu {
+    pub current_mtu: u16, // 请求结束时 quinn 认定的路径 MTU (UDP 负载字节数)
+    pub initial_mtu: u16,
+    pub discovery_enabled: bool,
+    pub upper_bound: Option<u16>, // MTU 探测上限，固定 MTU 时为 None
+    pub sent_probes: u64,
+    pub lost_probes: u64,
+    pub black_holes_detected: u64, // quinn 检测到的 MTU 黑洞次数
+}
+
+impl H3PathMtu {
+    fn from_connection(conn: &quinn::Connection, config: &H3TestConfig) -> Self {
+        let path = conn.stats().path;
+        Self {
+            current_mtu: path.current_mtu,
+            initial_mtu: config.transport.fixed_mtu.or(config.transport.initial_mtu).unwrap_or(QUIC_MIN_MTU),
+            discovery_enabled: config.transport.fixed_mtu.is_none(),
+            upper_bound: match config.transport.fixed_mtu {
+                Some(_) => None,
+                None => Some(config.transport.mtu_upper_bound.unwrap_or(DEFAULT_MTU_UPPER_BOUND)),
+            },
+            sent_probes: path.sent_plpmtud_probes,
+            lost_probes: path.lost_plpmtud_probes,
+            black_holes_detected: path.black_holes_detected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3MtuStep {
+    pub mtu: u16,
+    pub success: bool,
+    pub response_size: Option<usize>,
+    pub latency_ms: u64,
+    pub current_mtu: Option<u16>,
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3MtuSweep {
+    pub largest_working_mtu: Option<u16>, // 固定 MTU 下请求仍能完成的最大值
+    pub blackhole_suspected: bool, // 默认请求 (开启 MTU 探测) 失败，而某个固定 MTU 能完成请求
+    pub steps: Vec<H3MtuStep>,
+}
+
+impl H3MtuSweep {
+    fn new(default_success: bool, steps: Vec<H3MtuStep>) -> Self {
+        let largest_working_mtu = steps.iter().filter(|s| s.success).map(|s| s.mtu).max();
+        Self {
+            largest_working_mtu,
+            blackhole_suspected: !default_success && largest_working_mtu.is_some(),
+            steps,
+        }
+    }
+}
+
+// --- 2.9 拥塞控制算法对比结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3CongestionRun {
+    pub controller: CongestionController,
+    pub success: bool,
+    pub response_status: Option<u16>,
+    pub response_size: Option<usize>,
+    pub first_byte_ms: Option<u64>,
+    pub completed_ms: Option<u64>, // 自建立连接起
+    pub mbps: Option<f64>,         // 从首字节到响应结束
+    pub rtt_ms: Option<u64>,
+    pub cwnd: Option<u64>,
+    pub congestion_events: Option<u64>,
+    pub lost_packets: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.10 Alt-Svc 升级探测结果 (RFC 7838) ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3AltSvcResult {
+    pub tcp_protocol: Option<String>, // TCP 请求实际协商的 HTTP 版本
+    pub tcp_status: Option<u16>,
+    pub alt_svc_header: Option<String>, // 多个 Alt-Svc 头以逗号拼接
+    pub parsed: Option<AltSvc>,
+    pub parse_error: Option<String>,
+    pub advertises_h3: bool,
+    pub h3_protocol_id: Option<String>, // 选用的 h3 / h3-NN 条目
+    pub h3_authority: Option<String>,   // 通告的 alt-authority，如 ":443"
+    pub h3_works: Option<bool>,         // 未通告 h3 时为 None
+    pub h3_status: Option<u16>,
+    pub h3_latency_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// --- 2.11 h3 / h2 / http/1.1 竞速结果 (Happy Eyeballs) ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3RaceAttempt {
+    pub protocol: String, // "h3"、"h2" 或 "http/1.1"
+    pub started_ms: u64,  // 自竞速开始
+    pub success: bool,    // 收到完整响应；不会因为已有胜者而取消，因此也表示落败方能否成功
+    pub response_status: Option<u16>,
+    pub completed_ms: Option<u64>, // 自竞速开始
+    pub error_message: Option<String>,
+}
+
+impl H3RaceAttempt {
+    fn new(protocol: impl Into<String>, started_ms: u64) -> Self {
+        Self {
+            protocol: protocol.into(),
+            started_ms,
+            success: false,
+            response_status: None,
+            completed_ms: None,
+            error_message: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3RaceResult {
+    pub head_start_ms: u64, // h3 领先 TCP 尝试的时间
+    pub winner: Option<String>,
+    pub margin_ms: Option<u64>, // 胜者领先第二个成功者的时间，仅胜者成功时为 None
+    pub attempts: Vec<H3RaceAttempt>,
+}
+
+// --- 2.12 ECH 探测结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3EchAttempt {
+    pub protocol: String, // "h3" 或 "tcp"
+    pub outcome: EchOutcome,
+    pub retry_configs: Option<bool>,   // 拒绝时服务端是否在 retry_configs 中给出了新的 ECH 配置，仅 TCP 可判断
+    pub verified_name: Option<String>, // 证书验证通过时使用的名称：接受时为真实 SNI，拒绝时为 public_name
+    pub response_status: Option<u16>,
+    pub error_message: Option<String>,
+}
+
+impl H3EchAttempt {
+    fn not_offered(protocol: &str, reason: String) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            outcome: EchOutcome::NotOffered,
+            retry_configs: None,
+            verified_name: None,
+            response_status: None,
+            error_message: Some(reason),
+        }
+    }
+
+    fn from_capture(
+        protocol: &str,
+        config: &H3TestConfig,
+        certificate: &CertCapture,
+        handshake_completed: bool,
+        response_status: Option<u16>,
+        error: Option<&anyhow::Error>,
+        error_message: Option<String>,
+    ) -> Self {
+        let verified_name = certificate.verified_name();
+        let (outcome, retry_configs) = ech::classify(
+            handshake_completed,
+            verified_name.as_deref(),
+            &config.target_domain,
+            error.and_then(ech::rejection),
+        );
+        Self { protocol: protocol.to_string(), outcome, retry_configs, verified_name, response_status, error_message }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3EchResult {
+    pub config_published: bool,   // 有可用的 ECHConfigList (HTTPS 记录或手动指定)
+    pub public_names: Vec<String>, // ECHConfigList 中的 public_name，即外层 ClientHello 的 SNI
+    pub attempts: Vec<H3EchAttempt>,
+}
+
+// --- 2.13 后量子混合密钥交换探测结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3KeyExchangeAttempt {
+    pub key_exchange: KeyExchange,
+    pub protocol: String,              // "h3" 或 "tcp"
+    pub offered_groups: Vec<String>,   // ClientHello 中按优先顺序提供的组
+    pub negotiated_group: Option<String>, // 对端在 ServerHello 中选择的组，TLS 1.2 时为 None
+    pub client_hello_bytes: Option<usize>, // 仅 h3
+    pub initial_packets: Option<usize>, // ClientHello 占用的 Initial 包数 (按初始 MTU 估算)，仅 h3
+    pub success: bool, // 握手完成并收到响应，不论状态码
+    pub response_status: Option<u16>,
+    pub latency_ms: u64,
+    pub error_message: Option<String>,
+}
+
+impl H3KeyExchangeAttempt {
+    fn new(key_exchange: KeyExchange, protocol: &str, trust: &TrustPolicy, certificate: &CertCapture) -> Self {
+        Self {
+            key_exchange,
+            protocol: protocol.to_string(),
+            offered_groups: trust.offered_groups(),
+            negotiated_group: certificate.kx_group(),
+            client_hello_bytes: None,
+            initial_packets: None,
+            success: false,
+            response_status: None,
+            latency_ms: 0,
+            error_message: None,
+        }
+    }
+
+    pub fn is_pq(&self) -> bool {
+        self.negotiated_group.as_deref().is_some_and(|group| group.contains("MLKEM"))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3KeyExchangeResult {
+    pub pq_available: bool,       // 当前构建包含 pq 特性，能够提供 X25519MLKEM768
+    pub pq_negotiated: bool,      // 对端在至少一次 pq 尝试中选择了后量子混合组
+    pub pq_broken: Vec<String>,   // pq 尝试失败而经典尝试成功的协议，通常是路径上的设备无法处理较大的 ClientHello
+    pub attempts: Vec<H3KeyExchangeAttempt>,
+}
+
+// --- 2.14 HTTP/3 → HTTP/2 → HTTP/1.1 回退结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct H3FallbackAttempt {
+    pub protocol: String, // "h3"、"h2" 或 "http/1.1"
+    pub success: bool,    // 收到响应，不论状态码
+    pub response_status: Option<u16>,
+    pub remote_addr: Option<String>, // TCP 尝试实际连接的对端地址，与被测 IP 不一致时尝试失败
+    pub latency_ms: u64,
+    pub error_message: Option<String>,
+}
+
+impl H3FallbackAttempt {
+    fn new(protocol: &str) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            success: false,
+            response_status: None,
+            remote_addr: None,
+            latency_ms: 0,
+            error_message: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct H3FallbackResult {
+    pub protocol: Option<String>, // 第一个成功的协议，之后的协议不再尝试
+    pub attempts: Vec<H3FallbackAttempt>,
 }
 
 // --- 3. HTTP/3 测试器 ---
 pub struct H3Tester {
-    client_config: ClientConfig,
-    transport_config: Arc<TransportConfig>,
+    trust: TrustPolicy, // QUIC 与 TCP/TLS 对照请求使用同一信任配置
+    qlog: Option<QlogDir>, // 设置时为每个原生 QUIC 连接写入 qlog
+}
+
+// 粗略估算的 Initial 包开销：长包头 (8 字节连接 ID)、包号、CRYPTO 帧头与 AEAD 标签
+const INITIAL_PACKET_OVERHEAD: usize = 64;
+
+// 原生 HTTP/3 连接协商的 ALPN
+const H3_ALPN: [&[u8]; 5] = [b"h3", b"h3-29", b"h3-32", b"h3-33", b"h3-34"];
+
+// TCP/TLS 对照请求的协议：Auto 时通过 ALPN 在 h2 与 http/1.1 之间协商
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TcpProtocol {
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "h2")]
+    Http2,
+    #[serde(rename = "http/1.1")]
+    Http11,
+}
+
+impl std::fmt::Display for TcpProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TcpProtocol::Auto => "auto",
+            TcpProtocol::Http2 => "h2",
+            TcpProtocol::Http11 => "http/1.1",
+        })
+    }
+}
+
+// 原生 HTTP/3 连接上的请求发送端
+pub(crate) type H3SendRequest = SendRequest<CapturingOpenStreams<h3_quinn::OpenStreams>, Bytes>;
+
+// 已建立的原生 HTTP/3 连接
+pub(crate) struct H3Session {
+    pub(crate) endpoint: quinn::Endpoint,
+    pub(crate) quic_conn: quinn::Connection,
+    pub(crate) send_request: H3SendRequest,
+    pub(crate) peer_settings: SettingsCapture,
+    pub(crate) transport_params: TransportParamsCapture,
+    pub(crate) certificate: CertCapture,
 }
 
 impl H3Tester {
     pub fn new() -> Result<Self> {
-        // 配置 TLS
-        let mut root_store = RootCertStore::empty();
-        let (certs, errors) = rustls_native_certs::load_native_certs();
-        for cert in certs {
-            if let Err(e) = root_store.add_parsable_certificates(&cert) {
-                eprintln!("Failed to parse trust anchor: {}", e);
-            }
-        }
-        for e in errors {
-            eprintln!("Couldn't load default trust roots: {}", e);
-        }
+        Self::with_trust(&TlsTrustOptions::default())
+    }
 
-        let tls_config = RustlsClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+    pub fn with_trust(options: &TlsTrustOptions) -> Result<Self> {
+        Ok(Self { trust: options.build()?, qlog: None })
+    }
 
-        // 配置 ALPN
-        let mut tls_config = tls_config;
-        tls_config.alpn_protocols = vec![
-            "h3".into(),
-            "h3-29".into(),
-            "h3-32".into(),
-            "h3-33".into(),
-            "h3-34".into(),
-        ];
+    pub fn with_root_store(root_store: RootCertStore) -> Result<Self> {
+        Ok(Self { trust: TrustPolicy::from_root_store(root_store)?, qlog: None })
+    }
 
-        let mut transport_config = TransportConfig::default();
-        transport_config.max_idle_timeout(Some(quinn::IdleTimeout::from(Duration::from_secs(10))));
-        transport_config.max_concurrent_uni_streams(100u32.into());
-        transport_config.max_concurrent_bidi_streams(100u32.into());
-        transport_config.datagram_send_buffer_size(1024 * 1024);
-        let transport_config = Arc::new(transport_config);
+    pub fn with_qlog(self, qlog: Option<QlogDir>) -> Self {
+        Self { qlog, ..self }
+    }
 
-        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)?;
-        let client_config = ClientConfig::new(Arc::new(crypto));
+    // 并发探测各用一份独立的 qlog 句柄，分别按自身结果 finish
+    pub fn with_qlog_scope(&self) -> Self {
+        Self { trust: self.trust.clone(), qlog: self.qlog.as_ref().map(QlogDir::scope) }
+    }
 
-        Ok(Self {
-            client_config,
-            transport_config,
-        })
+    // 一次探测结束：成功时删除其间写入的 qlog (除非要求全部保留)，返回保留的文件
+    pub fn finish_qlog(&self, success: bool) -> Vec<std::path::PathBuf> {
+        self.qlog.as_ref().map(|qlog| qlog.finish(success)).unwrap_or_default()
     }
 
-    pub async fn test_http3_connection(&self, config: &H3TestConfig) -> Result<H3TestResult> {
-        let start_time = Instant::now();
+    // 每个连接单独的 TLS 配置：按信任配置验证、按需出示客户端证书，并记录到 capture
+    fn tls_config(&self, capture: &CertCapture, alpn_protocols: &[&[u8]]) -> Result<RustlsClientConfig> {
+        let mut tls_config = recording_client_config(&self.trust, capture)?;
+        tls_config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+        Ok(tls_config)
+    }
 
-        // 解析目标地址
-        let target_addr = format!("{}:{}", config.target_ip, config.port);
-        let socket_addr: SocketAddr = target_addr.parse()
-            .with_context(|| format!("Invalid target address: {}", target_addr))?;
+    // h2 对照客户端的 TLS 配置：与原生 h3 连接使用同一信任根、SPKI 固定、客户端证书与密钥日志
+    pub(crate) fn h2_tls_config(&self) -> Result<RustlsClientConfig> {
+        self.tls_config(&CertCapture::default(), &[b"h2"])
+    }
 
-        println!("    -> 开始 HTTP/3 连接测试: {} ({})",
-                 config.target_domain, config.target_ip);
+    // 固定连接到 config.target_ip 的 TCP/TLS 客户端，SNI 与 Host 均为 target_domain；
+    // reqwest 会忽略 resolve 地址中的端口，请求 URL 需显式带上 config.port
+    pub(crate) fn tcp_client(&self, config: &H3TestConfig, protocol: TcpProtocol) -> Result<reqwest::Client> {
+        self.tcp_client_with(config, protocol, &CertCapture::default())
+    }
+
+    // 同 tcp_client，由调用方提供证书记录
+    fn tcp_client_with(&self, config: &H3TestConfig, protocol: TcpProtocol, capture: &CertCapture) -> Result<reqwest::Client> {
+        let alpn_protocols: &[&[u8]] = match protocol {
+            TcpProtocol::Auto => &[b"h2", b"http/1.1"],
+            TcpProtocol::Http2 => &[b"h2"],
+            TcpProtocol::Http11 => &[b"http/1.1"],
+        };
+        let tls_config = self.tls_config(capture, alpn_protocols)?;
+
+        let ip = std::net::IpAddr::from_str(&config.target_ip)
+            .with_context(|| format!("Invalid target IP: {}", config.target_ip))?;
+        let builder = reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .resolve(&config.target_domain, SocketAddr::new(ip, config.port))
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .no_proxy();
+        let builder = match protocol {
+            TcpProtocol::Auto => builder,
+            TcpProtocol::Http2 => builder.http2_prior_knowledge(),
+            TcpProtocol::Http11 => builder.http1_only(),
+        };
+        builder.build().context("Failed to build TCP client")
+    }
+
+    fn transport_config_for(config: &H3TestConfig) -> TransportConfig {
+        let mut transport_config = config.transport.build();
+        transport_config.datagram_send_buffer_size(1024 * 1024);
+        // 仅在启用 datagram 时向对端通告 max_datagram_frame_size
+        if !config.enable_datagram {
+            transport_config.datagram_receive_buffer_size(None);
+        }
+        transport_config
+    }
+
+    // 建立 QUIC 连接并在其上创建 h3 客户端
+    pub(crate) async fn connect(&self, config: &H3TestConfig) -> Result<H3Session> {
+        self.connect_with(config, &CertCapture::default(), &TransportParamsCapture::default()).await
+    }
+
+    // 同 connect，由调用方提供证书与传输参数记录，连接失败时仍可读取对端证书链与本端 ClientHello 大小
+    pub(crate) async fn connect_with(
+        &self,
+        config: &H3TestConfig,
+        certificate: &CertCapture,
+        transport_params: &TransportParamsCapture,
+    ) -> Result<H3Session> {
+        // 解析目标地址
+        let ip = std::net::IpAddr::from_str(&config.target_ip)
+            .with_context(|| format!("Invalid target IP: {}", config.target_ip))?;
+        let socket_addr = SocketAddr::new(ip, config.port);
 
         // 创建 quinn 客户端点
-        let mut client_endpoint = h3_quinn::quinn::Endpoint::client("0.0.0.0:0")?;
-        client_endpoint.set_default_client_config(self.client_config.clone());
+        let bind_addr: SocketAddr = if socket_addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }.parse()?;
+        // 固定 MTU 时同时限制通告给对端的 max_udp_payload_size，使对端发来的包也不超过该值
+        let mut endpoint_config = quinn::EndpointConfig::default();
+        if let Some(mtu) = config.transport.fixed_mtu {
+            endpoint_config.max_udp_payload_size(mtu)?;
+        }
+        let runtime = quinn::default_runtime().context("No async runtime found")?;
+        let mut client_endpoint =
+            quinn::Endpoint::new(endpoint_config, None, std::net::UdpSocket::bind(bind_addr)?, runtime)?;
+        // 每个连接单独包装 TLS 会话，以记录对端证书链与传输参数
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(self.tls_config(certificate, &H3_ALPN)?)?;
+        let mut client_config = ClientConfig::new(Arc::new(CapturingCryptoConfig::new(
+            Arc::new(crypto),
+            transport_params.clone(),
+        )));
+        let mut transport_config = Self::transport_config_for(config);
+        let qlog = self.qlog.as_ref().map(|dir| dir.trace(&config.target_ip, &config.target_domain)).transpose()?;
+        transport_config.qlog_stream(qlog.as_ref().and_then(|trace| trace.stream()));
+        client_config.transport_config(Arc::new(transport_config));
+        client_endpoint.set_default_client_config(client_config);
 
         // 建立 QUIC 连接
         let quinn_conn = client_endpoint
-            .connect(socket_addr, &config.target_domain)
+            .connect(socket_addr, &config.target_domain)?
             .await
             .context("Failed to establish QUIC connection")?;
 
         println!("    -> QUIC 连接建立成功");
 
-        // 创建 h3 连接
-        let quinn_conn = h3_quinn::Connection::new(quinn_conn);
+        // 保留 quinn 连接句柄，用于读取传输层信息和收发 datagram
+        let quic_conn = quinn_conn.clone();
+
+        // 创建 h3 连接，并记录对端控制流上的 SETTINGS
+        let peer_settings = SettingsCapture::default();
+        // WebTransport 会话要求本端同时声明 SETTINGS_ENABLE_WEBTRANSPORT 与 SETTINGS_H3_DATAGRAM
+        let webtransport = config.enable_extended_connect && config.extended_connect_protocol() == "webtransport";
+        let quinn_conn = CapturingConnection::new(h3_quinn::Connection::new(quinn_conn), peer_settings.clone(), qlog)
+            .with_webtransport(webtransport);
 
         // 创建 HTTP/3 客户端
-        let (mut driver, mut send_request) = h3::client::new(quinn_conn).await
+        let mut h3_builder = builder();
+        h3_builder
+            .send_grease(config.send_grease)
+            .enable_datagram(config.enable_datagram || webtransport)
+            .enable_extended_connect(config.enable_extended_connect);
+        if let Some(size) = config.max_field_section_size {
+            h3_builder.max_field_section_size(size);
+        }
+        let (mut driver, send_request) = h3_builder
+            .build::<_, _, Bytes>(quinn_conn)
+            .await
             .context("Failed to build HTTP/3 connection")?;
 
+        // 驱动 h3 连接（处理控制流和对端 SETTINGS）
+        tokio::spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
+        Ok(H3Session {
+            endpoint: client_endpoint,
+            quic_conn,
+            send_request,
+            peer_settings,
+            transport_params: transport_params.clone(),
+            certificate: certificate.clone(),
+        })
+    }
+
+    pub async fn test_http3_connection(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+
+        println!("    -> 开始 HTTP/3 连接测试: {} ({})",
+                 config.target_domain, config.target_ip);
+
+        // 证书问题导致握手失败时返回带证书信息的失败结果，其它连接错误照常返回
+        let certificate = CertCapture::default();
+        let H3Session { endpoint: _endpoint, quic_conn, mut send_request, peer_settings, transport_params, .. } =
+            match self.connect_with(config, &certificate, &TransportParamsCapture::default()).await {
+                Ok(session) => session,
+                Err(e) => match certificate.report(&config.target_domain) {
+                    Some(report) => {
+                        let client_auth = certificate.client_auth(rejected_by_tls_alert(e.downcast_ref()));
+                        return Ok(H3TestResult {
+                            certificate: Some(report),
+                            client_auth,
+                            ..H3TestResult::failure(config, start_time.elapsed().as_millis() as u64, format!("{:#}", e))
+                        });
+                    }
+                    None => return Err(e),
+                },
+            };
+
         println!("    -> HTTP/3 连接建立成功");
 
         // 创建请求
-        let request_url = format!("https://{}{}", config.target_domain, config.test_path);
+        let request_url = config.request_uri(&config.test_path);
         let user_agent = config.user_agent.as_deref().unwrap_or("rust-h3-test-tool/1.0");
 
         let http_request = Request::builder()
             .method(Method::GET)
             .uri(&request_url)
             .header("User-Agent", user_agent)
-            .header("Host", &config.target_domain)
             .body(())
             .context("Failed to build HTTP request")?;
 
@@ -155,136 +912,1926 @@ impl H3Tester {
             stream.finish().await
                 .context("Failed to finish request stream")?;
 
+            // 在接收响应前进行 datagram 回显测试，此时请求流仍处于打开状态
+            let datagram = if config.enable_datagram {
+                Some(probe_datagram(&quic_conn, stream.id(), timeout_duration / 4).await)
+            } else {
+                None
+            };
+
             println!("    -> 等待 HTTP/3 响应...");
 
             // 接收响应头
             let response = stream.recv_response().await
                 .context("Failed to receive HTTP/3 response")?;
 
-            println!("    -> HTTP/3 响应接收成功: {} {}",
+            println!("    -> HTTP/3 响应接收成功: {:?} {}",
                      response.version(), response.status());
 
             // 读取响应体
             let mut response_body = Vec::new();
             let mut response_size = 0usize;
 
-            while let Some(chunk) = stream.recv_data().await.transpose() {
-                let chunk = chunk.context("Failed to receive response data")?;
-                response_size += chunk.len();
-                response_body.extend_from_slice(&chunk);
+            while let Some(mut chunk) = stream.recv_data().await
+                .context("Failed to receive response data")? {
+                response_size += chunk.remaining();
+                response_body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
             }
 
             println!("    -> HTTP/3 响应体读取完成: {} bytes", response_size);
 
-            Ok::<_, anyhow::Error>((response, response_size))
+            // 普通请求完成后对端 SETTINGS 已经到达，再尝试扩展 CONNECT
+            let extended_connect = if config.enable_extended_connect {
+                Some(probe_extended_connect(&send_request, &quic_conn, config).await)
+            } else {
+                None
+            };
+
+            Ok::<_, anyhow::Error>((response, response_size, datagram, extended_connect))
         }).await;
 
-        let (response, response_size) = match response_result {
-            Ok(Ok((resp, size))) => (Some(resp), Some(size)),
+        // 握手成功但响应失败时同样记录路径 MTU，便于识别 MTU 黑洞
+        let path_mtu = Some(H3PathMtu::from_connection(&quic_conn, config));
+        let (response, response_size, mut datagram, extended_connect) = match response_result {
+            Ok(Ok((resp, size, datagram, extended_connect))) => (Some(resp), Some(size), datagram, extended_connect),
             Ok(Err(e)) => {
+                let response_header_too_big = match e.downcast_ref::<h3::error::StreamError>() {
+                    Some(h3::error::StreamError::HeaderTooBig { actual_size, .. }) => Some(*actual_size),
+                    _ => None,
+                };
                 return Ok(H3TestResult {
-                    config: config.clone(),
-                    target_ip: config.target_ip.clone(),
-                    ip_version: config.ip_version.clone(),
-                    success: false,
-                    protocol_version: "HTTP/3".to_string(),
-                    response_status: None,
-                    response_size: None,
-                    latency_ms: start_time.elapsed().as_millis(),
-                    error_message: Some(format!("HTTP/3 request failed: {}", e)),
-                    alpn_protocol: None,
-                    cipher_suite: None,
+                    path_mtu,
+                    response_header_too_big,
+                    certificate: certificate.report(&config.target_domain),
+                    client_auth: certificate.client_auth(rejected_by_tls_alert(quic_conn.close_reason().as_ref())),
+                    ..H3TestResult::failure(
+                        config,
+                        start_time.elapsed().as_millis() as u64,
+                        format!("HTTP/3 request failed: {:#}", e),
+                    )
                 });
             }
             Err(_) => {
                 return Ok(H3TestResult {
-                    config: config.clone(),
-                    target_ip: config.target_ip.clone(),
-                    ip_version: config.ip_version.clone(),
-                    success: false,
-                    protocol_version: "HTTP/3".to_string(),
-                    response_status: None,
-                    response_size: None,
-                    latency_ms: start_time.elapsed().as_millis(),
-                    error_message: Some("HTTP/3 request timeout".to_string()),
-                    alpn_protocol: None,
-                    cipher_suite: None,
+                    path_mtu,
+                    certificate: certificate.report(&config.target_domain),
+                    client_auth: certificate.client_auth(rejected_by_tls_alert(quic_conn.close_reason().as_ref())),
+                    ..H3TestResult::failure(
+                        config,
+                        start_time.elapsed().as_millis() as u64,
+                        "HTTP/3 request timeout".to_string(),
+                    )
                 });
             }
         };
 
-        let latency = start_time.elapsed().as_millis();
+        let latency = start_time.elapsed().as_millis() as u64;
+
+        // 收到响应后对端 SETTINGS 已经到达
+        if let Some(datagram) = datagram.as_mut() {
+            datagram.h3_datagram_advertised = send_request.settings().enable_datagram();
+        }
 
         Ok(H3TestResult {
-            config: config.clone(),
-            target_ip: config.target_ip.clone(),
-            ip_version: config.ip_version.clone(),
             success: true,
-            protocol_version: "HTTP/3".to_string(),
             response_status: response.as_ref().map(|r| r.status().as_u16()),
             response_size,
-            latency_ms: latency,
-            error_message: None,
             alpn_protocol: Some("h3".to_string()),
-            cipher_suite: Some("TLS_AES_256_GCM_SHA384".to_string()),
+            cipher_suite: certificate.cipher_suite(),
+            datagram,
+            extended_connect,
+            path_mtu,
+            peer_settings: peer_settings.get(),
+            transport_params: transport_params.get(),
+            certificate: certificate.report(&config.target_domain),
+            client_auth: certificate.client_auth(Some(true)),
+            ..H3TestResult::empty(config, latency)
         })
     }
 
-    pub async fn run_multiple_tests(&self, configs: &[H3TestConfig]) -> Result<Vec<H3TestResult>> {
-        let mut results = Vec::new();
+    // MASQUE CONNECT-UDP 探测：建立 UDP 隧道并通过 HTTP datagram 发送 DNS 查询
+    pub async fn test_connect_udp(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
 
-        for config in configs {
-            println!("\n🚀 开始 HTTP/3 测试: {}", config.target_domain);
-            let result = self.test_http3_connection(config).await?;
-            results.push(result);
-        }
+        println!("    -> 开始 CONNECT-UDP 测试: {} ({})",
+                 config.target_domain, config.target_ip);
 
-        Ok(results)
-    }
-}
+        // CONNECT-UDP 依赖扩展 CONNECT 和 HTTP datagram；:protocol 不是 webtransport，本端不声明 WebTransport
+        let mut config = config.clone();
+        config.enable_datagram = true;
+        config.enable_extended_connect = true;
+        config.connect_protocol = Some("connect-udp".to_string());
 
-// --- 4. 协议信息提取 ---
-pub fn extract_protocol_info(_connection: &quinn::Connection) -> (String, Option<String>) {
-    // 注意：这些方法可能需要根据quinn库的版本调整
-    let alpn = "h3".to_string(); // 暂时使用默认值
+        let H3Session { endpoint: _endpoint, quic_conn, mut send_request, peer_settings, transport_params, certificate } =
+            self.connect(&config).await?;
+
+        let wait = Duration::from_secs(config.timeout_seconds);
+        let connect_udp = probe_connect_udp(&quic_conn, &mut send_request, &peer_settings, &config, wait).await;
+        let client_auth = certificate.client_auth(Some(connect_udp.response_status.is_some()));
+        let latency = start_time.elapsed().as_millis() as u64;
+
+        Ok(H3TestResult {
+            success: connect_udp.dns_response_received,
+            response_status: connect_udp.response_status,
+            error_message: connect_udp.error_message.clone(),
+            alpn_protocol: Some("h3".to_string()),
+            connect_udp: Some(connect_udp),
+            peer_settings: peer_settings.get(),
+            transport_params: transport_params.get(),
+            certificate: certificate.report(&config.target_domain),
+            client_auth,
+            ..H3TestResult::empty(&config, latency)
+        })
+    }
+
+    // GREASE 开/关以及逐步减小 max_field_section_size 的扫描，记录每一步由谁拒绝
+    pub async fn sweep_grease_and_field_section(&self, config: &H3TestConfig, limits: &[u64]) -> Vec<H3SweepStep> {
+        let mut limits = limits.to_vec();
+        limits.sort_unstable_by(|a, b| b.cmp(a));
+        limits.dedup();
+
+        let mut variants = vec![
+            (true, config.max_field_section_size),
+            (false, config.max_field_section_size),
+        ];
+        variants.extend(limits.into_iter().map(|limit| (config.send_grease, Some(limit))));
+
+        let mut steps = Vec::new();
+        for (send_grease, max_field_section_size) in variants {
+            let mut step_config = config.clone();
+            step_config.send_grease = send_grease;
+            step_config.max_field_section_size = max_field_section_size;
+
+            println!("    -> 扫描: GREASE={} max_field_section_size={:?}", send_grease, max_field_section_size);
+
+            let start_time = Instant::now();
+            let result = match self.test_http3_connection(&step_config).await {
+                Ok(result) => result,
+                Err(e) => H3TestResult::failure(
+                    &step_config,
+                    start_time.elapsed().as_millis() as u64,
+                    format!("{:#}", e),
+                ),
+            };
+            steps.push(H3SweepStep::from_result(&result));
+        }
+
+        steps
+    }
+
+    // 路径 MTU 测试：先按配置 (默认开启 MTU 探测) 请求一次，再依次固定各个 MTU 请求，找出仍能完成请求的最大值
+    pub async fn test_mtu(&self, config: &H3TestConfig, sizes: &[u16]) -> Result<H3TestResult> {
+        let mut sizes = sizes.to_vec();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        sizes.dedup();
+
+        println!("    -> 开始路径 MTU 测试: {} ({})，固定 MTU {:?}", config.target_domain, config.target_ip, sizes);
+
+        let start_time = Instant::now();
+        let mut result = self.test_http3_connection(config).await?;
+
+        let mut steps = Vec::new();
+        for mtu in sizes {
+            let mut step = H3MtuStep { mtu, ..Default::default() };
+            if mtu < QUIC_MIN_MTU {
+                step.error_message = Some(format!("QUIC 要求 MTU 至少为 {}", QUIC_MIN_MTU));
+                steps.push(step);
+                continue;
+            }
+
+            println!("    -> 固定 MTU: {}", mtu);
+
+            let mut step_config = config.clone();
+            step_config.transport.fixed_mtu = Some(mtu);
+            let step_start = Instant::now();
+            match self.test_http3_connection(&step_config).await {
+                Ok(step_result) => {
+                    step.success = step_result.success;
+                    step.response_size = step_result.response_size;
+                    step.current_mtu = step_result.path_mtu.map(|m| m.current_mtu);
+                    step.error_message = step_result.error_message;
+                }
+                Err(e) => step.error_message = Some(format!("{:#}", e)),
+            }
+            step.latency_ms = step_start.elapsed().as_millis() as u64;
+            steps.push(step);
+        }
+
+        // success 保持默认请求的结果，固定 MTU 的扫描结果单独记录
+        let sweep = H3MtuSweep::new(result.success, steps);
+
+        println!("    -> 路径 MTU 测试完成: 当前 MTU {:?}，固定 MTU 下最大可用 {:?}{}",
+                 result.path_mtu.as_ref().map(|m| m.current_mtu), sweep.largest_working_mtu,
+                 if sweep.blackhole_suspected { "，疑似 MTU 黑洞" } else { "" });
+
+        result.latency_ms = start_time.elapsed().as_millis() as u64;
+        result.mtu_sweep = Some(sweep);
+        Ok(result)
+    }
+
+    // 拥塞控制对比：依次在每种拥塞控制算法下新建连接请求同一目标，比较耗时、吞吐与丢包
+    pub async fn test_congestion_controllers(
+        &self,
+        config: &H3TestConfig,
+        controllers: &[CongestionController],
+    ) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+        let mut runs = Vec::new();
+        let mut peer_settings = None;
+        let mut transport_params = None;
+
+        for &controller in controllers {
+            println!("    -> 拥塞控制 {}: {} ({})", controller, config.target_domain, config.target_ip);
+
+            let mut run_config = config.clone();
+            run_config.transport.congestion_controller = Some(controller);
+            let mut run = H3CongestionRun {
+                controller,
+                success: false,
+                response_status: None,
+                response_size: None,
+                first_byte_ms: None,
+                completed_ms: None,
+                mbps: None,
+                rtt_ms: None,
+                cwnd: None,
+                congestion_events: None,
+                lost_packets: None,
+                error_message: None,
+            };
+
+            let run_start = Instant::now();
+            match self.connect(&run_config).await {
+                Ok(mut session) => {
+                    let timing = timed_request(&mut session.send_request, &run_config, 0, run_start).await;
+                    let stats = session.quic_conn.stats();
+                    run.success = timing.error_message.is_none();
+                    run.response_status = timing.response_status;
+                    run.response_size = timing.response_size;
+                    run.first_byte_ms = timing.first_byte_ms;
+                    run.completed_ms = timing.completed_ms;
+                    run.mbps = match (timing.response_size, timing.first_byte_ms, timing.completed_ms) {
+                        (Some(size), Some(first), Some(done)) if done > first => {
+                            Some(size as f64 * 8.0 / ((done - first) as f64 / 1000.0) / 1_000_000.0)
+                        }
+                        _ => None,
+                    };
+                    run.rtt_ms = Some(stats.path.rtt.as_millis() as u64);
+                    run.cwnd = Some(stats.path.cwnd);
+                    run.congestion_events = Some(stats.path.congestion_events);
+                    run.lost_packets = Some(stats.path.lost_packets);
+                    run.error_message = timing.error_message;
+                    peer_settings = peer_settings.or(session.peer_settings.get());
+                    transport_params = transport_params.or(session.transport_params.get());
+                }
+                Err(e) => run.error_message = Some(format!("{:#}", e)),
+            }
+            runs.push(run);
+        }
+
+        let error_message = runs.iter().find_map(|r| r.error_message.clone());
+        Ok(H3TestResult {
+            success: !runs.is_empty() && runs.iter().all(|r| r.success),
+            response_status: runs.iter().find_map(|r| r.response_status),
+            response_size: runs.iter().find_map(|r| r.response_size),
+            error_message,
+            alpn_protocol: Some("h3".to_string()),
+            congestion: Some(runs),
+            peer_settings,
+            transport_params,
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+
+    // Alt-Svc 升级测试：先通过 TCP 请求读取 Alt-Svc，再在同一 IP 上连接通告的 h3 端口
+    pub async fn test_alt_svc(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+        let mut alt_svc = H3AltSvcResult::default();
+
+        println!("    -> 开始 Alt-Svc 测试: {} ({})", config.target_domain, config.target_ip);
+
+        let client = self.tcp_client(config, TcpProtocol::Auto)?;
+        let response = tcp_send(&client, config).await;
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                alt_svc.error_message = Some(format!("TCP 请求失败: {:#}", e));
+                return Ok(H3TestResult {
+                    error_message: alt_svc.error_message.clone(),
+                    alt_svc: Some(alt_svc),
+                    ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+                });
+            }
+        };
+
+        alt_svc.tcp_protocol = Some(format!("{:?}", response.version()));
+        alt_svc.tcp_status = Some(response.status().as_u16());
+        let values: Vec<&str> = response
+            .headers()
+            .get_all(http::header::ALT_SVC)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        if !values.is_empty() {
+            let header = values.join(", ");
+            match parse_alt_svc(&header) {
+                Ok(parsed) => alt_svc.parsed = Some(parsed),
+                Err(e) => alt_svc.parse_error = Some(e),
+            }
+            alt_svc.alt_svc_header = Some(header);
+        }
+
+        println!("    -> TCP 响应: {} {}，Alt-Svc: {}",
+                 alt_svc.tcp_protocol.as_deref().unwrap_or("-"),
+                 alt_svc.tcp_status.unwrap_or(0),
+                 alt_svc.alt_svc_header.as_deref().unwrap_or("无"));
+
+        let mut h3_result = None;
+        if let Some(entry) = alt_svc.parsed.as_ref().and_then(|p| p.preferred_h3()).cloned() {
+            alt_svc.advertises_h3 = true;
+            alt_svc.h3_protocol_id = Some(entry.protocol_id.clone());
+            alt_svc.h3_authority = Some(format!("{}:{}", entry.host.as_deref().unwrap_or(""), entry.port));
+
+            // 替代服务须对源站有效，SNI 仍使用源站域名；按要求只连接同一 IP 上通告的端口
+            let h3_config = H3TestConfig { port: entry.port, ..config.clone() };
+            let h3_start = Instant::now();
+            let result = match self.test_http3_connection(&h3_config).await {
+                Ok(result) => result,
+                Err(e) => H3TestResult::failure(&h3_config, h3_start.elapsed().as_millis() as u64, format!("{:#}", e)),
+            };
+            alt_svc.h3_works = Some(result.success);
+            alt_svc.h3_status = result.response_status;
+            alt_svc.h3_latency_ms = Some(h3_start.elapsed().as_millis() as u64);
+            alt_svc.error_message = result.error_message.clone();
+            h3_result = Some(result);
+        } else if alt_svc.parse_error.is_none() {
+            alt_svc.error_message = Some("源站未通告 h3".to_string());
+        }
+
+        println!("    -> Alt-Svc 测试完成: 通告 h3 {}，h3 可用 {:?}",
+                 if alt_svc.advertises_h3 { "是" } else { "否" }, alt_svc.h3_works);
+
+        let base = h3_result.unwrap_or_else(|| H3TestResult::empty(config, 0));
+        Ok(H3TestResult {
+            success: alt_svc.h3_works == Some(true),
+            error_message: alt_svc.parse_error.clone().or_else(|| alt_svc.error_message.clone()),
+            alt_svc: Some(alt_svc),
+            config: config.clone(),
+            latency_ms: start_time.elapsed().as_millis() as u64,
+            ..base
+        })
+    }
+
+    // 竞速测试：h3 立即开始，h2 与 http/1.1 在 head_start_ms 后对同一 IP 发起，先拿到完整响应者胜出。
+    // 与浏览器不同，胜者产生后不取消其余尝试，以便记录落败方是否也能成功
+    pub async fn test_race(&self, config: &H3TestConfig, head_start_ms: u64) -> Result<H3TestResult> {
+        let race_start = Instant::now();
+
+        println!("    -> 开始竞速测试: {} ({})，h3 领先 {}ms", config.target_domain, config.target_ip, head_start_ms);
+
+        let h3 = async {
+            let mut attempt = H3RaceAttempt::new("h3", 0);
+            let outcome = timeout(Duration::from_secs(config.timeout_seconds), async {
+                let mut session = self.connect(config).await?;
+                Ok::<_, anyhow::Error>(timed_request(&mut session.send_request, config, 0, race_start).await)
+            })
+            .await;
+            match outcome {
+                Ok(Ok(timing)) => {
+                    attempt.success = timing.error_message.is_none();
+                    attempt.response_status = timing.response_status;
+                    attempt.completed_ms = timing.completed_ms;
+                    attempt.error_message = timing.error_message;
+                }
+                Ok(Err(e)) => attempt.error_message = Some(format!("{:#}", e)),
+                Err(_) => attempt.error_message = Some("HTTP/3 connection timeout".to_string()),
+            }
+            attempt
+        };
+        let (h3, h2, h1) = tokio::join!(
+            h3,
+            self.race_tcp(config, TcpProtocol::Http2, head_start_ms, race_start),
+            self.race_tcp(config, TcpProtocol::Http11, head_start_ms, race_start),
+        );
+        let attempts = vec![h3, h2, h1];
+
+        let mut finishers: Vec<&H3RaceAttempt> = attempts.iter().filter(|a| a.success).collect();
+        finishers.sort_by_key(|a| a.completed_ms);
+        let winner = finishers.first().map(|a| a.protocol.clone());
+        let margin_ms = match (finishers.first(), finishers.get(1)) {
+            (Some(first), Some(second)) => Some(second.completed_ms.unwrap_or(0) - first.completed_ms.unwrap_or(0)),
+            _ => None,
+        };
+
+        println!("    -> 竞速完成: 胜者 {}，领先 {}",
+                 winner.as_deref().unwrap_or("无"),
+                 margin_ms.map(|m| format!("{}ms", m)).unwrap_or_else(|| "-".to_string()));
+
+        let winning = finishers.first().copied();
+        Ok(H3TestResult {
+            success: winner.is_some(),
+            response_status: winning.and_then(|a| a.response_status),
+            alpn_protocol: winner.clone(),
+            error_message: match winning {
+                Some(_) => None,
+                None => Some(attempts
+                    .iter()
+                    .map(|a| format!("{}: {}", a.protocol, a.error_message.as_deref().unwrap_or("失败")))
+                    .collect::<Vec<_>>()
+                    .join("; ")),
+            },
+            race: Some(H3RaceResult { head_start_ms, winner, margin_ms, attempts }),
+            ..H3TestResult::empty(config, race_start.elapsed().as_millis() as u64)
+        })
+    }
+
+    async fn race_tcp(
+        &self,
+        config: &H3TestConfig,
+        protocol: TcpProtocol,
+        head_start_ms: u64,
+        race_start: Instant,
+    ) -> H3RaceAttempt {
+        tokio::time::sleep(Duration::from_millis(head_start_ms)).await;
+        let mut attempt = H3RaceAttempt::new(protocol.to_string(), race_start.elapsed().as_millis() as u64);
+
+        let outcome = async {
+            let client = self.tcp_client(config, protocol)?;
+            let response = tcp_send(&client, config).await?;
+            let status = response.status().as_u16();
+            response.bytes().await?;
+            Ok::<_, anyhow::Error>(status)
+        }
+        .await;
+
+        match outcome {
+            Ok(status) => {
+                attempt.success = true;
+                attempt.response_status = Some(status);
+                attempt.completed_ms = Some(race_start.elapsed().as_millis() as u64);
+            }
+            Err(e) => attempt.error_message = Some(format!("{:#}", e)),
+        }
+        attempt
+    }
+
+    // 回退测试：仿照只支持顺序回退的客户端，先用原生 h3 请求，失败后依次通过 h2、http/1.1 请求同一 IP，
+    // 第一个收到响应的协议即为该 IP 实际可用的协议；TCP 尝试核对对端地址，确保结果来自被测 IP
+    pub async fn test_fallback(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+
+        println!("    -> 开始回退测试: {} ({})", config.target_domain, config.target_ip);
+
+        let mut h3 = H3FallbackAttempt::new("h3");
+        let outcome = timeout(Duration::from_secs(config.timeout_seconds), async {
+            let mut session = self.connect(config).await?;
+            Ok::<_, anyhow::Error>(timed_request(&mut session.send_request, config, 0, start_time).await)
+        })
+        .await;
+        match outcome {
+            Ok(Ok(timing)) => {
+                h3.success = timing.error_message.is_none();
+                h3.response_status = timing.response_status;
+                h3.error_message = timing.error_message;
+            }
+            Ok(Err(e)) => h3.error_message = Some(format!("{:#}", e)),
+            Err(_) => h3.error_message = Some("HTTP/3 connection timeout".to_string()),
+        }
+        h3.latency_ms = start_time.elapsed().as_millis() as u64;
+
+        let mut attempts = vec![h3];
+        for protocol in [TcpProtocol::Http2, TcpProtocol::Http11] {
+            if attempts.iter().any(|a| a.success) {
+                break;
+            }
+            println!("    -> {} 失败: {}，回退到 {}",
+                     attempts.last().map(|a| a.protocol.as_str()).unwrap_or("-"),
+                     attempts.last().and_then(|a| a.error_message.as_deref()).unwrap_or("-"),
+                     protocol);
+            attempts.push(self.fallback_tcp(config, protocol).await);
+        }
+
+        let winning = attempts.iter().find(|a| a.success);
+        let protocol = winning.map(|a| a.protocol.clone());
+        println!("    -> 回退完成: 可用协议 {}", protocol.as_deref().unwrap_or("无"));
+
+        Ok(H3TestResult {
+            success: winning.is_some(),
+            response_status: winning.and_then(|a| a.response_status),
+            alpn_protocol: protocol.clone(),
+            error_message: match winning {
+                Some(_) => None,
+                None => Some(attempts
+                    .iter()
+                    .map(|a| format!("{}: {}", a.protocol, a.error_message.as_deref().unwrap_or("失败")))
+                    .collect::<Vec<_>>()
+                    .join("; ")),
+            },
+            fallback: Some(H3FallbackResult { protocol, attempts }),
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+
+    async fn fallback_tcp(&self, config: &H3TestConfig, protocol: TcpProtocol) -> H3FallbackAttempt {
+        let started = Instant::now();
+        let mut attempt = H3FallbackAttempt::new(&protocol.to_string());
+
+        let outcome = async {
+            let client = self.tcp_client(config, protocol)?;
+            let response = tcp_send(&client, config).await?;
+            let remote_addr = response.remote_addr();
+            let status = response.status().as_u16();
+            response.bytes().await?;
+            Ok::<_, anyhow::Error>((status, remote_addr))
+        }
+        .await;
+
+        attempt.latency_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok((status, remote_addr)) => {
+                attempt.success = true;
+                attempt.response_status = Some(status);
+                attempt.remote_addr = remote_addr.map(|addr| addr.to_string());
+            }
+            Err(e) => attempt.error_message = Some(format!("{:#}", e)),
+        }
+        attempt
+    }
+
+    // ECH 探测：用 HTTPS 记录中的 ECHConfigList 分别以原生 h3 与 TCP/TLS 请求同一 IP，
+    // 根据证书验证名称与握手错误判断 ECH 是否被接受；没有可用配置时不发送 ECH，也不发起连接
+    pub async fn test_ech(&self, config: &H3TestConfig, ech_config_list: Option<&[u8]>) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+
+        println!("    -> 开始 ECH 测试: {} ({})", config.target_domain, config.target_ip);
+
+        let mut ech = H3EchResult {
+            config_published: ech_config_list.is_some(),
+            public_names: ech_config_list.map(ech::public_names).unwrap_or_default(),
+            attempts: Vec::new(),
+        };
+        let offer = match ech_config_list.map(EchOffer::new) {
+            Some(Ok(offer)) => Some(offer),
+            Some(Err(e)) => {
+                let reason = format!("{:#}", e);
+                ech.attempts = vec![H3EchAttempt::not_offered("h3", reason.clone()), H3EchAttempt::not_offered("tcp", reason)];
+                None
+            }
+            None => {
+                let reason = "未发布 ECH 配置".to_string();
+                ech.attempts = vec![H3EchAttempt::not_offered("h3", reason.clone()), H3EchAttempt::not_offered("tcp", reason)];
+                None
+            }
+        };
+
+        let mut certificate = None;
+        if let Some(offer) = offer {
+            let tester = H3Tester { trust: TrustPolicy { ech: Some(offer), ..self.trust.clone() }, qlog: self.qlog.clone() };
+            let h3_certificate = CertCapture::default();
+            let (h3, tcp) = tokio::join!(tester.ech_h3(config, &h3_certificate), tester.ech_tcp(config));
+            // 被拒绝时对端出示的是 public_name 的证书
+            let name = h3.verified_name.as_deref().unwrap_or(&config.target_domain);
+            certificate = h3_certificate.report(name);
+            ech.attempts = vec![h3, tcp];
+        }
+
+        for attempt in &ech.attempts {
+            println!("    -> ECH {}: {}{}", attempt.protocol, attempt.outcome,
+                     if attempt.retry_configs == Some(true) { " (带 retry_configs)" } else { "" });
+        }
+
+        let accepted = ech.attempts.iter().all(|a| a.outcome == EchOutcome::Accepted);
+        Ok(H3TestResult {
+            success: accepted,
+            response_status: ech.attempts.first().and_then(|a| a.response_status),
+            error_message: (!accepted).then(|| {
+                ech.attempts
+                    .iter()
+                    .filter(|a| a.outcome != EchOutcome::Accepted)
+                    .map(|a| format!("{}: ECH {} ({})", a.protocol, a.outcome, a.error_message.as_deref().unwrap_or("-")))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            }),
+            certificate,
+            ech: Some(ech),
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+
+    async fn ech_h3(&self, config: &H3TestConfig, certificate: &CertCapture) -> H3EchAttempt {
+        let outcome = timeout(Duration::from_secs(config.timeout_seconds), async {
+            let mut session = self.connect_with(config, certificate, &TransportParamsCapture::default()).await?;
+            Ok::<_, anyhow::Error>(timed_request(&mut session.send_request, config, 0, Instant::now()).await)
+        })
+        .await;
+        let handshake_completed = matches!(outcome, Ok(Ok(_)));
+        let error = outcome.as_ref().ok().and_then(|result| result.as_ref().err());
+        let (response_status, error_message) = match &outcome {
+            Ok(Ok(timing)) => (timing.response_status, timing.error_message.clone()),
+            Ok(Err(e)) => (None, Some(format!("{:#}", e))),
+            Err(_) => (None, Some("HTTP/3 connection timeout".to_string())),
+        };
+        H3EchAttempt::from_capture("h3", config, certificate, handshake_completed, response_status, error, error_message)
+    }
+
+    async fn ech_tcp(&self, config: &H3TestConfig) -> H3EchAttempt {
+        let certificate = CertCapture::default();
+        let outcome = async {
+            let client = self.tcp_client_with(config, TcpProtocol::Auto, &certificate)?;
+            let response = tcp_send(&client, config).await?;
+            Ok::<_, anyhow::Error>(response.status().as_u16())
+        }
+        .await;
+        let (response_status, error_message) = match &outcome {
+            Ok(status) => (Some(*status), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        H3EchAttempt::from_capture("tcp", config, &certificate, response_status.is_some(), response_status, outcome.as_ref().err(), error_message)
+    }
+
+    // 密钥交换探测：以后量子混合组 (需要 pq 特性) 与经典组分别通过原生 h3 与 TCP/TLS 请求同一 IP，
+    // 记录对端选择的组与 ClientHello 占用的 Initial 包数；pq 失败而经典成功说明路径上有设备无法处理较大的 ClientHello
+    pub async fn test_key_exchange(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+
+        println!("    -> 开始密钥交换测试: {} ({})", config.target_domain, config.target_ip);
+
+        let modes: &[KeyExchange] = if PQ_AVAILABLE { &[KeyExchange::Pq, KeyExchange::Classic] } else { &[KeyExchange::Classic] };
+        let mut attempts = Vec::new();
+        for &key_exchange in modes {
+            let tester = H3Tester {
+                trust: TrustPolicy { provider: key_exchange.provider()?, ..self.trust.clone() },
+                qlog: self.qlog.clone(),
+            };
+            let (h3, tcp) = tokio::join!(tester.key_exchange_h3(config, key_exchange), tester.key_exchange_tcp(config, key_exchange));
+            attempts.extend([h3, tcp]);
+        }
+
+        for attempt in &attempts {
+            println!("    -> {} {}: {}{}", attempt.key_exchange, attempt.protocol,
+                     attempt.negotiated_group.as_deref().unwrap_or("-"),
+                     attempt.initial_packets.map(|n| format!(" (ClientHello 占用 {} 个 Initial 包)", n)).unwrap_or_default());
+        }
+
+        let succeeded = |key_exchange: KeyExchange, protocol: &str| {
+            attempts.iter().any(|a| a.key_exchange == key_exchange && a.protocol == protocol && a.success)
+        };
+        let key_exchange = H3KeyExchangeResult {
+            pq_available: PQ_AVAILABLE,
+            pq_negotiated: attempts.iter().any(|a| a.key_exchange == KeyExchange::Pq && a.is_pq()),
+            pq_broken: ["h3", "tcp"]
+                .into_iter()
+                .filter(|&protocol| PQ_AVAILABLE && !succeeded(KeyExchange::Pq, protocol) && succeeded(KeyExchange::Classic, protocol))
+                .map(String::from)
+                .collect(),
+            attempts,
+        };
+
+        let success = key_exchange.attempts.iter().all(|a| a.success);
+        Ok(H3TestResult {
+            success,
+            response_status: key_exchange.attempts.first().and_then(|a| a.response_status),
+            error_message: (!success).then(|| {
+                key_exchange
+                    .attempts
+                    .iter()
+                    .filter(|a| !a.success)
+                    .map(|a| format!("{} {}: {}", a.key_exchange, a.protocol, a.error_message.as_deref().unwrap_or("-")))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            }),
+            key_exchange: Some(key_exchange),
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+
+    async fn key_exchange_h3(&self, config: &H3TestConfig, key_exchange: KeyExchange) -> H3KeyExchangeAttempt {
+        let started = Instant::now();
+        let certificate = CertCapture::default();
+        let transport_params = TransportParamsCapture::default();
+        let outcome = timeout(Duration::from_secs(config.timeout_seconds), async {
+            let mut session = self.connect_with(config, &certificate, &transport_params).await?;
+            Ok::<_, anyhow::Error>(timed_request(&mut session.send_request, config, 0, Instant::now()).await)
+        })
+        .await;
+
+        let mut attempt = H3KeyExchangeAttempt::new(key_exchange, "h3", &self.trust, &certificate);
+        let initial_mtu = config.transport.fixed_mtu.or(config.transport.initial_mtu).unwrap_or(QUIC_MIN_MTU);
+        attempt.client_hello_bytes = transport_params.client_hello_len();
+        attempt.initial_packets = attempt
+            .client_hello_bytes
+            .map(|len| len.div_ceil(usize::from(initial_mtu).saturating_sub(INITIAL_PACKET_OVERHEAD).max(1)));
+        attempt.latency_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok(Ok(timing)) => {
+                attempt.success = timing.response_status.is_some();
+                attempt.response_status = timing.response_status;
+                attempt.error_message = timing.error_message;
+            }
+            Ok(Err(e)) => attempt.error_message = Some(format!("{:#}", e)),
+            Err(_) => attempt.error_message = Some("HTTP/3 connection timeout".to_string()),
+        }
+        attempt
+    }
+
+    async fn key_exchange_tcp(&self, config: &H3TestConfig, key_exchange: KeyExchange) -> H3KeyExchangeAttempt {
+        let started = Instant::now();
+        let certificate = CertCapture::default();
+        let outcome = async {
+            let client = self.tcp_client_with(config, TcpProtocol::Auto, &certificate)?;
+            let response = tcp_send(&client, config).await?;
+            Ok::<_, anyhow::Error>(response.status())
+        }
+        .await;
+
+        let mut attempt = H3KeyExchangeAttempt::new(key_exchange, "tcp", &self.trust, &certificate);
+        attempt.latency_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok(status) => {
+                attempt.success = true;
+                attempt.response_status = Some(status.as_u16());
+            }
+            Err(e) => attempt.error_message = Some(format!("{:#}", e)),
+        }
+        attempt
+    }
+
+    // 多路复用测试：同一连接上并发 max_concurrent_requests 个请求，再与分散到独立连接的同等负载对比
+    pub async fn test_multiplexed(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let concurrency = config.max_concurrent_requests.max(1);
+
+        println!("    -> 开始多路复用测试: {} ({})，并发 {} 个请求",
+                 config.target_domain, config.target_ip, concurrency);
+
+        // 单连接多流
+        let start_time = Instant::now();
+        let H3Session { endpoint: _endpoint, quic_conn: _, send_request, peer_settings, transport_params, certificate } =
+            self.connect(config).await?;
+        let streams = futures::future::join_all((0..concurrency).map(|index| {
+            let mut send_request = send_request.clone();
+            async move { timed_request(&mut send_request, config, index, start_time).await }
+        }))
+        .await;
+        let multiplexed = H3LoadRun::from_streams(1, streams, start_time.elapsed().as_millis() as u64);
+
+        println!("    -> 单连接多路复用完成: {}/{} 成功", multiplexed.successful, concurrency);
+
+        // 多连接各一个请求
+        let start_time = Instant::now();
+        let streams = futures::future::join_all((0..concurrency).map(|index| async move {
+            match self.connect(config).await {
+                Ok(mut session) => timed_request(&mut session.send_request, config, index, start_time).await,
+                Err(e) => H3StreamTiming {
+                    index,
+                    error_message: Some(format!("{:#}", e)),
+                    ..Default::default()
+                },
+            }
+        }))
+        .await;
+        let separate_connections = H3LoadRun::from_streams(concurrency, streams, start_time.elapsed().as_millis() as u64);
+
+        println!("    -> 独立连接对照完成: {}/{} 成功", separate_connections.successful, concurrency);
+
+        // 被对端流数量限制阻塞的请求只能在其他流结束后打开
+        let earliest_completion = multiplexed.streams.iter().filter_map(|s| s.completed_ms).min();
+        let stream_limit_hit_at = multiplexed
+            .streams
+            .iter()
+            .find(|s| matches!((s.opened_ms, earliest_completion), (Some(opened), Some(done)) if opened >= done))
+            .map(|s| s.index);
+
+        let transport_params = transport_params.get();
+        let multiplex = H3MultiplexResult {
+            concurrent_requests: concurrency,
+            peer_max_streams_bidi: transport_params.as_ref().map(|p| p.initial_max_streams_bidi),
+            stream_limit_hit_at,
+            multiplexed,
+            separate_connections,
+        };
+
+        let first = multiplex.multiplexed.streams.first();
+        let response_status = first.and_then(|s| s.response_status);
+        let response_size = first.and_then(|s| s.response_size);
+        let error_message = multiplex.multiplexed.streams.iter().find_map(|s| s.error_message.clone());
+        let latency = multiplex.multiplexed.total_ms;
+
+        Ok(H3TestResult {
+            success: multiplex.multiplexed.failed == 0,
+            response_status,
+            response_size,
+            error_message,
+            alpn_protocol: Some("h3".to_string()),
+            multiplex: Some(multiplex),
+            peer_settings: peer_settings.get(),
+            transport_params,
+            certificate: certificate.report(&config.target_domain),
+            client_auth: certificate.client_auth(Some(response_status.is_some())),
+            ..H3TestResult::empty(config, latency)
+        })
+    }
+
+    // 空闲超时探测：每个空闲时长 (分别在不保活与保活下) 使用独立连接，先请求一次，空闲后再请求
+    pub async fn test_idle_timeout(&self, config: &H3TestConfig, intervals_ms: &[u64]) -> Result<H3TestResult> {
+        let mut intervals = intervals_ms.to_vec();
+        intervals.sort_unstable();
+        intervals.dedup();
+        let keep_alive = config.transport.keep_alive_interval_ms.unwrap_or(DEFAULT_KEEP_ALIVE_INTERVAL_MS);
+
+        // 本地空闲超时需大于最长空闲时长，才能观察到对端实际执行的超时
+        let longest = intervals.last().copied().unwrap_or(0);
+        let local_idle_timeout = config
+            .transport
+            .idle_timeout_ms
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_MS)
+            .max(longest + config.timeout_seconds * 1000);
+
+        println!("    -> 开始空闲超时测试: {} ({})，空闲时长 {:?}ms，保活间隔 {}ms",
+                 config.target_domain, config.target_ip, intervals, keep_alive);
+
+        let start_time = Instant::now();
+        let plans: Vec<(u64, Option<u64>)> = [None, Some(keep_alive)]
+            .into_iter()
+            .flat_map(|keep_alive| intervals.iter().map(move |&idle| (idle, keep_alive)))
+            .collect();
+        let runs = futures::future::join_all(plans.into_iter().map(|(idle_ms, keep_alive_ms)| {
+            let mut config = config.clone();
+            config.transport.idle_timeout_ms = Some(local_idle_timeout);
+            config.transport.keep_alive_interval_ms = keep_alive_ms;
+            async move { self.idle_step(&config, idle_ms).await }
+        }))
+        .await;
+
+        let mut steps = Vec::new();
+        let mut peer_settings = None;
+        let mut transport_params = None;
+        for (step, settings, params) in runs {
+            steps.push(step);
+            peer_settings = peer_settings.or(settings);
+            transport_params = transport_params.or(params);
+        }
+
+        let without_keep_alive = || steps.iter().filter(|s| s.keep_alive_ms.is_none());
+        let with_keep_alive: Vec<_> = steps.iter().filter(|s| s.keep_alive_ms.is_some()).collect();
+        let idle = H3IdleResult {
+            local_idle_timeout_ms: local_idle_timeout,
+            peer_idle_timeout_ms: transport_params.as_ref().map(|p: &QuicTransportParams| p.max_idle_timeout_ms),
+            longest_idle_ok_ms: without_keep_alive().filter(|s| s.outcome == "ok").map(|s| s.idle_ms).max(),
+            shortest_idle_failed_ms: without_keep_alive().filter(|s| s.outcome != "ok").map(|s| s.idle_ms).min(),
+            keep_alive_holds: (!with_keep_alive.is_empty()).then(|| with_keep_alive.iter().all(|s| s.outcome == "ok")),
+            nat_rebinding_seen: steps.iter().any(|s| s.path_challenges > 0),
+            silent_drops: steps.iter().filter(|s| s.outcome == "silent_drop").count(),
+            steps,
+        };
+
+        println!("    -> 空闲超时测试完成: 不保活最长可用 {:?}ms，最短失效 {:?}ms",
+                 idle.longest_idle_ok_ms, idle.shortest_idle_failed_ms);
+
+        let error_message = idle.steps.iter().find_map(|s| s.error_message.clone());
+        Ok(H3TestResult {
+            success: idle.steps.iter().any(|s| s.outcome == "ok"),
+            error_message,
+            alpn_protocol: Some("h3".to_string()),
+            idle: Some(idle),
+            peer_settings,
+            transport_params,
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+
+    async fn idle_step(
+        &self,
+        config: &H3TestConfig,
+        idle_ms: u64,
+    ) -> (H3IdleStep, Option<H3PeerSettings>, Option<QuicTransportParams>) {
+        let mut step = H3IdleStep {
+            idle_ms,
+            keep_alive_ms: config.transport.keep_alive_interval_ms,
+            outcome: "error".to_string(),
+            ..Default::default()
+        };
+
+        let mut session = match self.connect(config).await {
+            Ok(session) => session,
+            Err(e) => {
+                step.error_message = Some(format!("{:#}", e));
+                return (step, None, None);
+            }
+        };
+        let settings_and_params = |session: &H3Session| (session.peer_settings.get(), session.transport_params.get());
+
+        let first = timed_request(&mut session.send_request, config, 0, Instant::now()).await;
+        if let Some(error) = first.error_message {
+            step.error_message = Some(format!("空闲前的请求失败: {}", error));
+            let (settings, params) = settings_and_params(&session);
+            return (step, settings, params);
+        }
+
+        let challenges_before = session.quic_conn.stats().frame_rx.path_challenge;
+        tokio::time::sleep(Duration::from_millis(idle_ms)).await;
+
+        if let Some(reason) = session.quic_conn.close_reason() {
+            step.outcome = H3IdleStep::classify_close(&reason).to_string();
+            step.error_message = Some(reason.to_string());
+        } else {
+            let start_time = Instant::now();
+            let second = timed_request(&mut session.send_request, config, 1, start_time).await;
+            step.response_status = second.response_status;
+            step.latency_ms = Some(start_time.elapsed().as_millis() as u64);
+            match (second.error_message, session.quic_conn.close_reason()) {
+                (None, _) => step.outcome = "ok".to_string(),
+                (Some(_), Some(reason)) => {
+                    step.outcome = H3IdleStep::classify_close(&reason).to_string();
+                    step.error_message = Some(reason.to_string());
+                }
+                // 连接仍存活但请求没有任何回应：路径上的 NAT 映射或对端状态被静默丢弃
+                (Some(error), None) => {
+                    if second.timed_out && second.first_byte_ms.is_none() {
+                        step.outcome = "silent_drop".to_string();
+                    }
+                    step.error_message = Some(error);
+                }
+            }
+        }
+        step.path_challenges = session.quic_conn.stats().frame_rx.path_challenge - challenges_before;
+
+        let (settings, params) = settings_and_params(&session);
+        (step, settings, params)
+    }
+
+    // 连接迁移测试：请求一次后把 Endpoint 换绑到新的本地 UDP socket，在同一连接上再请求一次
+    pub async fn test_migration(&self, config: &H3TestConfig) -> Result<H3TestResult> {
+        let start_time = Instant::now();
+
+        println!("    -> 开始连接迁移测试: {} ({})", config.target_domain, config.target_ip);
+
+        let mut session = self.connect(config).await?;
+        let mut migration = H3MigrationResult {
+            old_local_addr: session.endpoint.local_addr().ok().map(|a| a.to_string()),
+            ..Default::default()
+        };
+
+        let before = timed_request(&mut session.send_request, config, 0, start_time).await;
+        migration.rtt_before_ms = Some(session.quic_conn.rtt().as_millis() as u64);
+        if let Some(error) = before.error_message {
+            migration.error_message = Some(format!("迁移前的请求失败: {}", error));
+        } else {
+            let stats_before = session.quic_conn.stats();
+            // 绑定与原 socket 相同地址族的新端口，模拟 NAT 重绑定 / 网络切换
+            let rebind = session
+                .endpoint
+                .local_addr()
+                .and_then(|old| std::net::UdpSocket::bind(SocketAddr::new(old.ip(), 0)))
+                .and_then(|socket| session.endpoint.rebind(socket));
+            match rebind {
+                Ok(()) => {
+                    migration.rebound = true;
+                    migration.new_local_addr = session.endpoint.local_addr().ok().map(|a| a.to_string());
+                    println!("    -> 已换绑本地地址: {} -> {}",
+                             migration.old_local_addr.as_deref().unwrap_or("-"),
+                             migration.new_local_addr.as_deref().unwrap_or("-"));
+
+                    let request_start = Instant::now();
+                    let after = timed_request(&mut session.send_request, config, 1, request_start).await;
+                    migration.latency_ms = Some(request_start.elapsed().as_millis() as u64);
+                    migration.response_status = after.response_status;
+                    migration.request_after_ok = after.error_message.is_none();
+                    migration.error_message = after.error_message;
+
+                    // PATH_RESPONSE 可能在响应之后才发出，等待一个 RTT 再读取统计
+                    tokio::time::sleep(session.quic_conn.rtt().max(Duration::from_millis(50))).await;
+                    let stats_after = session.quic_conn.stats();
+                    migration.path_challenges_received =
+                        stats_after.frame_rx.path_challenge - stats_before.frame_rx.path_challenge;
+                    migration.path_responses_sent =
+                        stats_after.frame_tx.path_response - stats_before.frame_tx.path_response;
+                    migration.path_validated = migration.request_after_ok
+                        && migration.path_challenges_received > 0
+                        && migration.path_responses_sent > 0;
+                    migration.rtt_after_ms = Some(session.quic_conn.rtt().as_millis() as u64);
+                }
+                Err(e) => migration.error_message = Some(format!("换绑本地 UDP socket 失败: {}", e)),
+            }
+        }
+
+        let transport_params = session.transport_params.get();
+        let client_auth = session.certificate.client_auth(Some(migration.response_status.is_some()));
+        migration.peer_disable_active_migration = transport_params.as_ref().map(|p| p.disable_active_migration);
+
+        println!("    -> 连接迁移测试完成: 换绑后请求{}，路径验证{}",
+                 if migration.request_after_ok { "成功" } else { "失败" },
+                 if migration.path_validated { "通过" } else { "未观察到" });
+
+        Ok(H3TestResult {
+            success: migration.request_after_ok,
+            response_status: migration.response_status,
+            error_message: migration.error_message.clone(),
+            alpn_protocol: Some("h3".to_string()),
+            migration: Some(migration),
+            peer_settings: session.peer_settings.get(),
+            transport_params,
+            certificate: session.certificate.report(&config.target_domain),
+            client_auth,
+            ..H3TestResult::empty(config, start_time.elapsed().as_millis() as u64)
+        })
+    }
+    pub async fn run_multiple_tests(&self, configs: &[H3TestConfig]) -> Result<Vec<H3TestResult>> {
+        let mut results = Vec::new();
+
+        for config in configs {
+            println!("\n🚀 开始 HTTP/3 测试: {}", config.target_domain);
+            let result = self.test_http3_connection(config).await?;
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+}
+
+// 对端以 TLS 告警关闭连接 (QUIC CRYPTO_ERROR 0x100-0x1ff) 时视为拒绝了客户端证书；其它情况无法判断。
+// TransportError 是本端检测到的错误 (如对端证书验证失败)，与客户端证书无关
+fn rejected_by_tls_alert(reason: Option<&quinn::ConnectionError>) -> Option<bool> {
+    let quinn::ConnectionError::ConnectionClosed(close) = reason? else {
+        return None;
+    };
+    (0x100..0x200).contains(&u64::from(close.error_code)).then_some(false)
+}
+
+// TCP 对照请求：URL 中的域名决定 SNI，设置 host_header 时覆盖 Host
+fn tcp_get(client: &reqwest::Client, config: &H3TestConfig) -> reqwest::RequestBuilder {
+    let url = format!("https://{}:{}{}", config.target_domain, config.port, config.test_path);
+    let mut request = client
+        .get(&url)
+        .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-test-tool/1.0"));
+    if let Some(host) = &config.host_header {
+        request = request.header(http::header::HOST, host);
+    }
+    request
+}
+
+// 发送 TCP 对照请求，并确认实际连接的对端就是 config.target_ip:port，否则该 IP 的对照结果没有意义
+async fn tcp_send(client: &reqwest::Client, config: &H3TestConfig) -> Result<reqwest::Response> {
+    let response = tcp_get(client, config).send().await?;
+    check_remote_addr(config, response.remote_addr())?;
+    Ok(response)
+}
+
+// reqwest 未报告对端地址时不做判断
+fn check_remote_addr(config: &H3TestConfig, remote_addr: Option<SocketAddr>) -> Result<()> {
+    let Some(addr) = remote_addr else {
+        return Ok(());
+    };
+    let pinned = IpAddr::from_str(&config.target_ip)
+        .with_context(|| format!("Invalid target IP: {}", config.target_ip))?;
+    let expected = SocketAddr::new(pinned, config.port);
+    if addr != expected {
+        anyhow::bail!("Request went to {} instead of pinned address {}", addr, expected);
+    }
+    Ok(())
+}
+
+// 在已有连接上发送一个 GET 请求并记录各阶段时间
+async fn timed_request(
+    send_request: &mut H3SendRequest,
+    config: &H3TestConfig,
+    index: usize,
+    start_time: Instant,
+) -> H3StreamTiming {
+    let mut timing = H3StreamTiming { index, ..Default::default() };
+    let elapsed_ms = || start_time.elapsed().as_millis() as u64;
+
+    let result = timeout(Duration::from_secs(config.timeout_seconds), async {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(config.request_uri(&config.test_path))
+            .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-test-tool/1.0"))
+            .body(())?;
+
+        let mut stream = send_request.send_request(request).await
+            .context("Failed to send HTTP/3 request")?;
+        timing.opened_ms = Some(elapsed_ms());
+        stream.finish().await
+            .context("Failed to finish request stream")?;
+
+        let response = stream.recv_response().await
+            .context("Failed to receive HTTP/3 response")?;
+        timing.first_byte_ms = Some(elapsed_ms());
+        timing.response_status = Some(response.status().as_u16());
+
+        let mut size = 0usize;
+        while let Some(chunk) = stream.recv_data().await
+            .context("Failed to receive response data")? {
+            size += chunk.remaining();
+        }
+        timing.completed_ms = Some(elapsed_ms());
+        timing.response_size = Some(size);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => timing.error_message = Some(format!("{:#}", e)),
+        Err(_) => {
+            timing.timed_out = true;
+            timing.error_message = Some("HTTP/3 request timeout".to_string());
+        }
+    }
+
+    timing
+}
+
+// --- 3.1 HTTP/3 Datagram 探测 ---
+// RFC 9297: datagram 负载 = Quarter Stream ID (varint) + HTTP datagram 内容
+pub fn encode_h3_datagram(stream_id: StreamId, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(VarInt::MAX_SIZE + payload.len());
+    (VarInt::from(stream_id) / 4).encode(&mut buf);
+    buf.put_slice(payload);
+    buf.freeze()
+}
+
+pub fn decode_h3_datagram(mut datagram: Bytes) -> Option<(StreamId, Bytes)> {
+    let quarter = VarInt::decode(&mut datagram).ok()?;
+    let stream_id = StreamId::try_from(quarter.into_inner().checked_mul(4)?).ok()?;
+    Some((stream_id, datagram))
+}
+
+async fn probe_datagram(conn: &quinn::Connection, stream_id: StreamId, wait: Duration) -> H3DatagramResult {
+    let mut result = H3DatagramResult {
+        max_datagram_size: conn.max_datagram_size(),
+        ..Default::default()
+    };
+    result.quic_datagram_supported = result.max_datagram_size.is_some();
+
+    if !result.quic_datagram_supported {
+        result.error_message = Some("Peer does not support QUIC DATAGRAM".to_string());
+        return result;
+    }
+
+    let payload = format!("h3-datagram-echo-{}", rand::random::<u64>()).into_bytes();
+    let start = Instant::now();
+    if let Err(e) = conn.send_datagram(encode_h3_datagram(stream_id, &payload)) {
+        result.error_message = Some(format!("Failed to send datagram: {}", e));
+        return result;
+    }
+
+    let echo = timeout(wait, async {
+        loop {
+            let datagram = conn.read_datagram().await?;
+            if let Some((id, body)) = decode_h3_datagram(datagram) {
+                if id == stream_id && body.as_ref() == payload.as_slice() {
+                    return Ok::<_, quinn::ConnectionError>(());
+                }
+            }
+        }
+    })
+    .await;
+
+    match echo {
+        Ok(Ok(())) => {
+            result.echo_success = true;
+            result.echo_rtt_ms = Some(start.elapsed().as_millis() as u64);
+            println!("    -> HTTP/3 datagram 回显成功: {:?}", start.elapsed());
+        }
+        Ok(Err(e)) => result.error_message = Some(format!("Failed to read datagram: {}", e)),
+        Err(_) => result.error_message = Some("Datagram echo timeout".to_string()),
+    }
+
+    result
+}
+
+// --- 3.2 扩展 CONNECT 探测 ---
+// h3 只能发送 webtransport / connect-udp 的 :protocol，请求因此直接在 QUIC 双向流上发送，
+// websocket (RFC 9220) 等取值原样写入 :protocol
+async fn probe_extended_connect(
+    send_request: &H3SendRequest,
+    quic_conn: &quinn::Connection,
+    config: &H3TestConfig,
+) -> H3ExtendedConnectResult {
+    let protocol = config.extended_connect_protocol();
+    let path = config.connect_path.clone().unwrap_or_else(|| config.test_path.clone());
+    let mut result = H3ExtendedConnectResult {
+        settings_enable_connect_protocol: send_request.settings().enable_extended_connect(),
+        protocol: protocol.to_string(),
+        path: path.clone(),
+        ..Default::default()
+    };
+
+    // RFC 9220: 对端未声明 SETTINGS_ENABLE_CONNECT_PROTOCOL 时不得发送扩展 CONNECT
+    if !result.settings_enable_connect_protocol {
+        result.error_message = Some("Peer did not send SETTINGS_ENABLE_CONNECT_PROTOCOL".to_string());
+        return result;
+    }
+
+    let uri = match config.request_uri(&path).parse::<http::Uri>() {
+        Ok(uri) => uri,
+        Err(e) => {
+            result.error_message = Some(format!("Failed to build CONNECT request: {}", e));
+            return result;
+        }
+    };
+    let authority = uri.authority().map(|a| a.as_str()).unwrap_or(config.authority());
+    let mut fields = vec![
+        (":method", "CONNECT"),
+        (":protocol", protocol),
+        (":scheme", "https"),
+        (":authority", authority),
+        (":path", path.as_str()),
+        ("user-agent", config.user_agent.as_deref().unwrap_or("rust-h3-test-tool/1.0")),
+    ];
+    match protocol {
+        "webtransport" => fields.push(("sec-webtransport-http3-draft02", "1")),
+        "websocket" => fields.push(("sec-websocket-version", "13")),
+        _ => {}
+    }
+
+    println!("    -> 发送扩展 CONNECT 请求: :protocol={} {}", protocol, path);
+
+    let start = Instant::now();
+    result.attempted = true;
+    let (mut send, mut recv) = match quic_conn.open_bi().await {
+        Ok(stream) => stream,
+        Err(e) => {
+            result.error_message = Some(format!("Failed to open CONNECT stream: {}", e));
+            return result;
+        }
+    };
+    if let Err(e) = send.write_all(&h3_raw_headers::encode_headers_frame(&fields)).await {
+        result.error_message = Some(format!("Failed to send CONNECT request: {}", e));
+        return result;
+    }
+
+    match h3_raw_headers::read_response_status(&mut recv).await {
+        Ok(status) => {
+            result.response_status = Some(status);
+            result.session_established = (200..300).contains(&status);
+            result.latency_ms = Some(start.elapsed().as_millis() as u64);
+            println!("    -> 扩展 CONNECT 响应: {}", status);
+        }
+        Err(e) => {
+            result.error_message = Some(format!("Failed to receive CONNECT response: {:#}", e));
+        }
+    }
+
+    // 关闭会话
+    let _ = send.finish();
+
+    result
+}
+
+// --- 3.3 MASQUE CONNECT-UDP 探测 ---
+fn split_host_port(target: &str) -> Option<(String, u16)> {
+    if let Ok(addr) = target.parse::<SocketAddr>() {
+        return Some((addr.ip().to_string(), addr.port()));
+    }
+    let (host, port) = target.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+// 展开 RFC 9298 URI 模板；相对模板使用目标域名作为代理 authority
+pub fn expand_masque_uri_template(template: &str, authority: &str, host: &str, port: u16) -> String {
+    let expanded = template
+        .replace("{target_host}", &urlencoding::encode(host))
+        .replace("{target_port}", &port.to_string());
+    if expanded.starts_with("https://") {
+        expanded
+    } else {
+        format!("https://{}{}", authority, expanded)
+    }
+}
+
+async fn probe_connect_udp(
+    conn: &quinn::Connection,
+    send_request: &mut H3SendRequest,
+    peer_settings: &SettingsCapture,
+    config: &H3TestConfig,
+    wait: Duration,
+) -> H3ConnectUdpResult {
+    let target = config.masque_target.clone().unwrap_or_else(|| "1.1.1.1:53".to_string());
+    let template = config.masque_uri_template.as_deref().unwrap_or(DEFAULT_MASQUE_URI_TEMPLATE);
+    let mut result = H3ConnectUdpResult {
+        target: target.clone(),
+        ..Default::default()
+    };
+
+    let Some((host, port)) = split_host_port(&target) else {
+        result.error_message = Some(format!("Invalid CONNECT-UDP target: {}", target));
+        return result;
+    };
+    result.uri = expand_masque_uri_template(template, &config.request_authority(), &host, port);
+
+    // 扩展 CONNECT 需要先确认对端 SETTINGS；超时视为未声明
+    let settings = peer_settings.wait(wait / 4).await.unwrap_or_default();
+    result.settings_enable_connect_protocol = settings.enable_connect_protocol.is_some_and(|v| v != 0);
+    result.h3_datagram_advertised = settings.h3_datagram.is_some_and(|v| v != 0);
+
+    if !result.settings_enable_connect_protocol {
+        result.error_message = Some("Peer did not send SETTINGS_ENABLE_CONNECT_PROTOCOL".to_string());
+        return result;
+    }
+
+    let mut request = match Request::builder()
+        .method(Method::CONNECT)
+        .uri(&result.uri)
+        .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-test-tool/1.0"))
+        .header("capsule-protocol", "?1")
+        .body(())
+    {
+        Ok(request) => request,
+        Err(e) => {
+            result.error_message = Some(format!("Failed to build CONNECT-UDP request: {}", e));
+            return result;
+        }
+    };
+    request.extensions_mut().insert(Protocol::CONNECT_UDP);
+
+    println!("    -> 发送 CONNECT-UDP 请求: {}", result.uri);
+
+    let start = Instant::now();
+    let mut stream = match send_request.send_request(request).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            result.error_message = Some(format!("Failed to send CONNECT-UDP request: {}", e));
+            return result;
+        }
+    };
+
+    match timeout(wait / 2, stream.recv_response()).await {
+        Ok(Ok(response)) => {
+            result.response_status = Some(response.status().as_u16());
+            result.connect_latency_ms = Some(start.elapsed().as_millis() as u64);
+            result.tunnel_established = response.status().is_success();
+            println!("    -> CONNECT-UDP 响应: {}", response.status());
+        }
+        Ok(Err(e)) => {
+            result.error_message = Some(format!("Failed to receive CONNECT-UDP response: {}", e));
+            return result;
+        }
+        Err(_) => {
+            result.error_message = Some("CONNECT-UDP response timeout".to_string());
+            return result;
+        }
+    }
+
+    if !result.tunnel_established {
+        result.error_message = Some(format!("Proxy rejected CONNECT-UDP: {:?}", result.response_status));
+        return result;
+    }
+
+    if conn.max_datagram_size().is_none() {
+        result.error_message = Some("Peer does not support QUIC DATAGRAM".to_string());
+        return result;
+    }
+
+    // 构造 DNS 查询，作为 Context ID 0 的 UDP 负载发送
+    let dns_name = config.masque_dns_name.as_deref().unwrap_or(&config.target_domain);
+    let query_id = rand::random::<u16>();
+    let query = match build_dns_query(dns_name, query_id) {
+        Ok(query) => query,
+        Err(e) => {
+            result.error_message = Some(format!("Failed to build DNS query: {}", e));
+            return result;
+        }
+    };
+    let mut payload = vec![0u8];
+    payload.extend_from_slice(&query);
+
+    let stream_id = stream.id();
+    let dns_start = Instant::now();
+    if let Err(e) = conn.send_datagram(encode_h3_datagram(stream_id, &payload)) {
+        result.error_message = Some(format!("Failed to send datagram: {}", e));
+        return result;
+    }
+
+    let answer = timeout(wait / 2, async {
+        loop {
+            let datagram = conn.read_datagram().await?;
+            let Some((id, mut body)) = decode_h3_datagram(datagram) else {
+                continue;
+            };
+            // 只处理本隧道上 Context ID 为 0 的 UDP 负载
+            if id != stream_id || VarInt::decode(&mut body).map(|c| c.into_inner()) != Ok(0) {
+                continue;
+            }
+            if let Ok(message) = Message::from_vec(&body) {
+                if message.id() == query_id && message.message_type() == MessageType::Response {
+                    return Ok::<_, quinn::ConnectionError>(message);
+                }
+            }
+        }
+    })
+    .await;
+
+    match answer {
+        Ok(Ok(message)) => {
+            result.dns_response_received = true;
+            result.dns_answer_count = Some(message.answers().len());
+            result.dns_rtt_ms = Some(dns_start.elapsed().as_millis() as u64);
+            println!("    -> 隧道内 DNS 响应: {} 条记录, 耗时 {:?}",
+                     message.answers().len(), dns_start.elapsed());
+        }
+        Ok(Err(e)) => result.error_message = Some(format!("Failed to read datagram: {}", e)),
+        Err(_) => result.error_message = Some("DNS response through tunnel timeout".to_string()),
+    }
+
+    // 关闭隧道
+    let _ = stream.finish().await;
+
+    result
+}
+
+fn build_dns_query(domain: &str, id: u16) -> Result<Vec<u8>> {
+    let name = Name::from_ascii(domain).context(format!("无效的域名: {}", domain))?;
+    let mut message = Message::new();
+    message.set_id(id);
+    message.set_recursion_desired(true);
+    message.add_query(Query::query(name, RecordType::A));
+    message.to_vec().context("序列化 DNS 查询失败")
+}
+
+// --- 4. 协议信息提取 ---
+pub fn extract_protocol_info(_connection: &quinn::Connection) -> (String, Option<String>) {
+    // 注意：这些方法可能需要根据quinn库的版本调整
+    let alpn = "h3".to_string(); // 暂时使用默认值
     let cipher_suite = Some("TLS_AES_256_GCM_SHA384".to_string()); // 暂时使用默认值
 
-    (alpn, cipher_suite)
+    (alpn, cipher_suite)
+}
+
+// --- 5. 测试报告生成 ---
+pub fn generate_test_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== HTTP/3 直接测试报告 ===\n\n");
+
+    // 基本统计
+    let total = results.len();
+    let successful = results.iter().filter(|r| r.success).count();
+    let failed = total - successful;
+
+    report.push_str(&format!("总测试数: {}\n", total));
+    report.push_str(&format!("成功: {} ({:.1}%)\n", successful, successful as f64 / total as f64 * 100.0));
+    report.push_str(&format!("失败: {} ({:.1}%)\n\n", failed, failed as f64 / total as f64 * 100.0));
+
+    // 详细结果
+    report.push_str("详细结果:\n");
+    report.push_str(&format!("{:<20} {:<15} {:<8} {:<10} {:<8} {:<10} {:<6} {:<15}\n",
+                         "域名", "IP地址", "版本", "状态", "延迟", "大小", "MTU", "错误"));
+    report.push_str(&"-".repeat(90));
+
+    for result in results {
+        let status = if result.success { "成功" } else { "失败" };
+        let size = result.response_size.unwrap_or(0).to_string();
+        let error = result.error_message.as_deref().unwrap_or("");
+        let mtu = result.path_mtu.as_ref().map(|m| m.current_mtu.to_string()).unwrap_or_else(|| "-".to_string());
+
+        report.push_str(&format!("{:<20} {:<15} {:<8} {:<10} {:<8}ms {:<10} {:<6} {:<15}\n",
+                             result.config.target_domain,
+                             result.target_ip,
+                             result.ip_version,
+                             status,
+                             result.latency_ms,
+                             size,
+                             mtu,
+                             error));
+    }
+
+    report
+}
+
+pub fn generate_sweep_report(config: &H3TestConfig, steps: &[H3SweepStep]) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("=== GREASE / 字段区大小扫描: {} ({}) ===\n\n",
+                             config.target_domain, config.target_ip));
+    report.push_str(&format!("{:<8} {:<12} {:<8} {:<8} {:<10} {:<8} {:<15}\n",
+                             "GREASE", "字段区上限", "结果", "状态码", "延迟", "拒绝方", "错误"));
+    report.push_str(&"-".repeat(90));
+    report.push('\n');
+
+    for step in steps {
+        let limit = step.max_field_section_size.map(|l| l.to_string()).unwrap_or_else(|| "默认".to_string());
+        let status = step.response_status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string());
+
+        report.push_str(&format!("{:<8} {:<12} {:<8} {:<8} {:<8}ms {:<8} {:<15}\n",
+                                 if step.send_grease { "开" } else { "关" },
+                                 limit,
+                                 if step.success { "成功" } else { "失败" },
+                                 status,
+                                 step.latency_ms,
+                                 step.rejected_by.as_deref().unwrap_or("-"),
+                                 step.error_message.as_deref().unwrap_or("")));
+    }
+
+    report
+}
+
+pub fn generate_multiplex_report(config: &H3TestConfig, multiplex: &H3MultiplexResult) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("=== 多路复用测试: {} ({})，并发 {} ===\n\n",
+                             config.target_domain, config.target_ip, multiplex.concurrent_requests));
+
+    let limit = multiplex.peer_max_streams_bidi.map(|l| l.to_string()).unwrap_or_else(|| "未知".to_string());
+    report.push_str(&format!("对端双向流上限: {}\n", limit));
+    match multiplex.stream_limit_hit_at {
+        Some(index) => report.push_str(&format!("流数量限制生效: 第 {} 个请求起需等待其他流结束\n\n", index + 1)),
+        None => report.push_str("流数量限制生效: 否\n\n"),
+    }
+
+    let ms = |v: Option<u64>| v.map(|v| format!("{}ms", v)).unwrap_or_else(|| "-".to_string());
+    report.push_str(&format!("{:<12} {:<8} {:<8} {:<10} {:<10} {:<10} {:<10} {:<10}\n",
+                             "模式", "连接数", "成功", "总耗时", "最小", "平均", "最大", "离散"));
+    report.push_str(&"-".repeat(90));
+    report.push('\n');
+    for (name, run) in [("单连接多流", &multiplex.multiplexed), ("独立连接", &multiplex.separate_connections)] {
+        report.push_str(&format!("{:<12} {:<8} {:<8} {:<10} {:<10} {:<10} {:<10} {:<10}\n",
+                                 name,
+                                 run.connections,
+                                 format!("{}/{}", run.successful, run.streams.len()),
+                                 format!("{}ms", run.total_ms),
+                                 ms(run.min_latency_ms),
+                                 ms(run.avg_latency_ms),
+                                 ms(run.max_latency_ms),
+                                 ms(run.latency_spread_ms)));
+    }
+
+    report.push_str("\n单连接各流时间线 (打开 / 首字节 / 完成):\n");
+    for stream in &multiplex.multiplexed.streams {
+        report.push_str(&format!("  #{:<4} {:<8} {:<8} {:<8} {}\n",
+                                 stream.index + 1,
+                                 ms(stream.opened_ms),
+                                 ms(stream.first_byte_ms),
+                                 ms(stream.completed_ms),
+                                 stream.error_message.as_deref().unwrap_or("")));
+    }
+
+    report
+}
+
+pub fn generate_idle_report(config: &H3TestConfig, idle: &H3IdleResult) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("=== 空闲超时测试: {} ({}) ===\n\n", config.target_domain, config.target_ip));
+
+    let ms = |v: Option<u64>| v.map(|v| format!("{}ms", v)).unwrap_or_else(|| "-".to_string());
+    report.push_str(&format!("本地 max_idle_timeout: {}ms\n", idle.local_idle_timeout_ms));
+    report.push_str(&format!("对端 max_idle_timeout: {}\n", match idle.peer_idle_timeout_ms {
+        Some(0) => "不限制".to_string(),
+        other => ms(other),
+    }));
+    report.push_str(&format!("不保活时实际空闲超时: {} ~ {}\n",
+                             ms(idle.longest_idle_ok_ms), ms(idle.shortest_idle_failed_ms)));
+    report.push_str(&format!("保活有效: {}\n", match idle.keep_alive_holds {
+        Some(true) => "是",
+        Some(false) => "否",
+        None => "-",
+    }));
+    report.push_str(&format!("NAT 重绑定: {}\n", if idle.nat_rebinding_seen { "是" } else { "否" }));
+    report.push_str(&format!("静默丢弃: {}\n\n", idle.silent_drops));
+
+    report.push_str(&format!("{:<10} {:<10} {:<14} {:<8} {:<10} {:<10} {:<15}\n",
+                             "空闲", "保活", "结果", "状态码", "耗时", "路径验证", "错误"));
+    report.push_str(&"-".repeat(90));
+    report.push('\n');
+    for step in &idle.steps {
+        report.push_str(&format!("{:<10} {:<10} {:<14} {:<8} {:<10} {:<10} {:<15}\n",
+                                 format!("{}ms", step.idle_ms),
+                                 ms(step.keep_alive_ms),
+                                 step.outcome,
+                                 step.response_status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                                 ms(step.latency_ms),
+                                 step.path_challenges,
+                                 step.error_message.as_deref().unwrap_or("")));
+    }
+
+    report
+}
+
+pub fn generate_congestion_report(config: &H3TestConfig, runs: &[H3CongestionRun]) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("=== 拥塞控制对比: {} ({}) {} ===\n\n",
+                             config.target_domain, config.target_ip, config.test_path));
+    report.push_str(&format!("{:<10} {:<8} {:<8} {:<12} {:<10} {:<10} {:<12} {:<8} {:<12} {:<8} {:<15}\n",
+                             "算法", "结果", "状态码", "大小", "首字节", "完成", "吞吐", "RTT", "cwnd", "丢包", "错误"));
+    report.push_str(&"-".repeat(120));
+    report.push('\n');
+
+    let ms = |v: Option<u64>| v.map(|v| format!("{}ms", v)).unwrap_or_else(|| "-".to_string());
+    let num = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+    for run in runs {
+        report.push_str(&format!("{:<10} {:<8} {:<8} {:<12} {:<10} {:<10} {:<12} {:<8} {:<12} {:<8} {:<15}\n",
+                                 run.controller.to_string(),
+                                 if run.success { "成功" } else { "失败" },
+                                 num(run.response_status.map(u64::from)),
+                                 num(run.response_size.map(|s| s as u64)),
+                                 ms(run.first_byte_ms),
+                                 ms(run.completed_ms),
+                                 run.mbps.map(|m| format!("{:.2}Mbps", m)).unwrap_or_else(|| "-".to_string()),
+                                 ms(run.rtt_ms),
+                                 num(run.cwnd),
+                                 num(run.lost_packets),
+                                 run.error_message.as_deref().unwrap_or("")));
+    }
+
+    report
+}
+
+// 各 IP 的竞速结果: 胜者、领先时间以及每个协议的完成时间 (失败记为 ✗)
+pub fn generate_race_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== h3 / h2 / http/1.1 竞速 ===\n\n");
+    report.push_str(&format!("{:<40} {:<8} {:<10} {:<10} {:<10} {:<10} {:<10}\n",
+                             "IP", "领先", "胜者", "差距", "h3", "h2", "http/1.1"));
+    report.push_str(&"-".repeat(100));
+    report.push('\n');
+
+    for result in results {
+        let Some(race) = &result.race else { continue };
+        let outcome = |protocol: &str| {
+            race.attempts
+                .iter()
+                .find(|a| a.protocol == protocol)
+                .map(|a| match (a.success, a.completed_ms) {
+                    (true, Some(ms)) => format!("{}ms", ms),
+                    _ => "✗".to_string(),
+                })
+                .unwrap_or_else(|| "-".to_string())
+        };
+        report.push_str(&format!("{:<40} {:<8} {:<10} {:<10} {:<10} {:<10} {:<10}\n",
+                                 result.config.target_ip,
+                                 format!("{}ms", race.head_start_ms),
+                                 race.winner.as_deref().unwrap_or("无"),
+                                 race.margin_ms.map(|m| format!("{}ms", m)).unwrap_or_else(|| "-".to_string()),
+                                 outcome("h3"),
+                                 outcome("h2"),
+                                 outcome("http/1.1")));
+    }
+
+    report
+}
+
+// 各 IP 上 h3 → h2 → http/1.1 回退的结果，未尝试的协议显示为 -
+pub fn generate_fallback_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== h3 / h2 / http/1.1 回退 ===\n\n");
+    report.push_str(&format!("{:<40} {:<10} {:<10} {:<10} {:<10}\n",
+                             "IP", "可用协议", "h3", "h2", "http/1.1"));
+    report.push_str(&"-".repeat(84));
+    report.push('\n');
+
+    for result in results {
+        let Some(fallback) = &result.fallback else { continue };
+        let outcome = |protocol: &str| {
+            fallback.attempts
+                .iter()
+                .find(|a| a.protocol == protocol)
+                .map(|a| match (a.success, a.response_status) {
+                    (true, Some(status)) => status.to_string(),
+                    _ => "✗".to_string(),
+                })
+                .unwrap_or_else(|| "-".to_string())
+        };
+        report.push_str(&format!("{:<40} {:<10} {:<10} {:<10} {:<10}\n",
+                                 result.config.target_ip,
+                                 fallback.protocol.as_deref().unwrap_or("无"),
+                                 outcome("h3"),
+                                 outcome("h2"),
+                                 outcome("http/1.1")));
+    }
+
+    report
+}
+
+// 各 IP 上 h3 与 TCP 的 ECH 结果；accepted 表示真实 SNI 未以明文出现在 ClientHello 中
+pub fn generate_ech_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== ECH 探测 ===\n\n");
+
+    for result in results {
+        let Some(ech) = &result.ech else { continue };
+        let names = if ech.public_names.is_empty() { "-".to_string() } else { ech.public_names.join(", ") };
+        report.push_str(&format!("{} ({}) public_name: {}\n", result.target_ip, result.config.target_domain, names));
+        for attempt in &ech.attempts {
+            report.push_str(&format!(
+                "  {:<4} {:<12} retry_configs: {:<3} 证书名称: {}\n",
+                attempt.protocol,
+                attempt.outcome.to_string(),
+                match attempt.retry_configs {
+                    Some(true) => "是",
+                    Some(false) => "否",
+                    None => "-",
+                },
+                attempt.verified_name.as_deref().unwrap_or("-"),
+            ));
+        }
+    }
+
+    let hidden = results
+        .iter()
+        .filter_map(|r| r.ech.as_ref())
+        .filter(|ech| ech.attempts.iter().all(|a| a.outcome == EchOutcome::Accepted))
+        .count();
+    report.push_str(&format!("真实 SNI 已隐藏: {}/{} 个 IP\n", hidden, results.iter().filter(|r| r.ech.is_some()).count()));
+    report
+}
+
+// 各 IP 上每种密钥交换配置协商到的组，以及较大的 ClientHello 是否被路径上的设备丢弃
+pub fn generate_key_exchange_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== 密钥交换 ===\n\n");
+    if !PQ_AVAILABLE {
+        report.push_str("当前构建未启用 pq 特性，只测试经典密钥交换\n\n");
+    }
+
+    for result in results {
+        let Some(key_exchange) = &result.key_exchange else { continue };
+        report.push_str(&format!("{} ({})\n", result.target_ip, result.config.target_domain));
+        for attempt in &key_exchange.attempts {
+            report.push_str(&format!(
+                "  {:<7} {:<4} {:<4} 协商: {:<16} ClientHello: {}\n",
+                attempt.key_exchange.to_string(),
+                attempt.protocol,
+                if attempt.success { "成功" } else { "失败" },
+                attempt.negotiated_group.as_deref().unwrap_or("-"),
+                match (attempt.client_hello_bytes, attempt.initial_packets) {
+                    (Some(bytes), Some(packets)) => format!("{} 字节 / {} 个 Initial 包", bytes, packets),
+                    _ => "-".to_string(),
+                },
+            ));
+        }
+        for protocol in &key_exchange.pq_broken {
+            report.push_str(&format!("  ⚠️ {} 上后量子握手失败而经典握手成功，路径上可能有设备无法处理较大的 ClientHello\n", protocol));
+        }
+    }
+
+    let tested: Vec<&H3KeyExchangeResult> = results.iter().filter_map(|r| r.key_exchange.as_ref()).collect();
+    if PQ_AVAILABLE {
+        report.push_str(&format!(
+            "协商到后量子混合组: {}/{} 个 IP，后量子握手受阻: {} 个 IP\n",
+            tested.iter().filter(|k| k.pq_negotiated).count(),
+            tested.len(),
+            tested.iter().filter(|k| !k.pq_broken.is_empty()).count(),
+        ));
+    }
+    report
+}
+
+// 按叶子证书指纹对各 IP 分组：多数 IP 使用同一证书时，少数组往往是中间人或未更新的旧证书
+pub fn generate_certificate_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== 对端证书 ===\n\n");
+
+    let mut groups: BTreeMap<&str, Vec<&H3TestResult>> = BTreeMap::new();
+    let mut missing = Vec::new();
+    for result in results {
+        match result.certificate.as_ref().and_then(|c| c.leaf_fingerprint.as_deref()) {
+            Some(fingerprint) => groups.entry(fingerprint).or_default().push(result),
+            None => missing.push(result.config.target_ip.as_str()),
+        }
+    }
+
+    let majority = groups.values().map(Vec::len).max().unwrap_or(0);
+    report.push_str(&format!("不同的叶子证书: {} 个\n", groups.len()));
+    for (fingerprint, members) in &groups {
+        let Some(certificate) = members[0].certificate.as_ref() else { continue };
+        let Some(leaf) = certificate.chain.first() else { continue };
+        let minority = groups.len() > 1 && members.len() < majority;
+        report.push_str(&format!("\n{} SHA-256 {}\n", if minority { "⚠️" } else { "🔒" }, fingerprint));
+        report.push_str(&format!("    主题: {}\n", leaf.subject));
+        report.push_str(&format!("    签发者: {}\n", leaf.issuer));
+        report.push_str(&format!("    有效期: {} ~ {} (剩余 {} 天)\n", leaf.not_before, leaf.not_after, leaf.days_remaining));
+        report.push_str(&format!("    密钥: {}  证书链: {} 张  SCT: {}  OCSP 装订: {}\n",
+                                 leaf.key_type,
+                                 certificate.chain.len(),
+                                 if certificate.embedded_sct { "有" } else { "无" },
+                                 if certificate.ocsp_stapled { "有" } else { "无" }));
+        report.push_str(&format!("    SAN: {}\n", leaf.sans.join(", ")));
+        let ips: Vec<&str> = members.iter().map(|r| r.config.target_ip.as_str()).collect();
+        report.push_str(&format!("    IP ({}): {}\n", ips.len(), ips.join(", ")));
+        if minority {
+            report.push_str(&format!("    ⚠️ 与多数 IP ({} 个) 的证书不同\n", majority));
+        }
+        // 同一证书在不同 IP 上的警告相同，只取第一条记录
+        for warning in &certificate.warnings {
+            report.push_str(&format!("    ⚠️ {}\n", warning));
+        }
+    }
+
+    if !missing.is_empty() {
+        report.push_str(&format!("\n未获取到证书: {}\n", missing.join(", ")));
+    }
+
+    report
 }
 
-// --- 5. 测试报告生成 ---
-pub fn generate_test_report(results: &[H3TestResult]) -> String {
+// 各 IP 的路径 MTU: 当前 MTU、探测统计与固定 MTU 扫描结果
+pub fn generate_mtu_report(results: &[H3TestResult]) -> String {
     let mut report = String::new();
-    report.push_str("=== HTTP/3 直接测试报告 ===\n\n");
+    report.push_str("=== 路径 MTU ===\n\n");
+    report.push_str(&format!("{:<40} {:<8} {:<8} {:<12} {:<6} {:<12} {:<10} {:<15}\n",
+                             "IP", "结果", "当前", "探测/丢失", "黑洞", "最大可用", "疑似黑洞", "固定 MTU 失败"));
+    report.push_str(&"-".repeat(110));
+    report.push('\n');
 
-    // 基本统计
-    let total = results.len();
-    let successful = results.iter().filter(|r| r.success).count();
-    let failed = total - successful;
+    for result in results.iter().filter(|r| r.path_mtu.is_some() || r.mtu_sweep.is_some()) {
+        let path_mtu = result.path_mtu.as_ref();
+        let failed: Vec<String> = result
+            .mtu_sweep
+            .iter()
+            .flat_map(|sweep| sweep.steps.iter().filter(|s| !s.success).map(|s| s.mtu.to_string()))
+            .collect();
+        report.push_str(&format!("{:<40} {:<8} {:<8} {:<12} {:<6} {:<12} {:<10} {:<15}\n",
+                                 result.target_ip,
+                                 if result.success { "成功" } else { "失败" },
+                                 path_mtu.map(|m| m.current_mtu.to_string()).unwrap_or_else(|| "-".to_string()),
+                                 path_mtu.map(|m| format!("{}/{}", m.sent_probes, m.lost_probes)).unwrap_or_else(|| "-".to_string()),
+                                 path_mtu.map(|m| m.black_holes_detected.to_string()).unwrap_or_else(|| "-".to_string()),
+                                 result
+                                     .mtu_sweep
+                                     .as_ref()
+                                     .and_then(|s| s.largest_working_mtu)
+                                     .map(|m| m.to_string())
+                                     .unwrap_or_else(|| "-".to_string()),
+                                 match &result.mtu_sweep {
+                                     Some(sweep) if sweep.blackhole_suspected => "是",
+                                     Some(_) => "否",
+                                     None => "-",
+                                 },
+                                 failed.join(",")));
+    }
 
-    report.push_str(&format!("总测试数: {}\n", total));
-    report.push_str(&format!("成功: {} ({:.1}%)\n", successful, successful as f64 / total as f64 * 100.0));
-    report.push_str(&format!("失败: {} ({:.1}%)\n\n", failed, failed as f64 / total as f64 * 100.0));
+    report
+}
 
-    // 详细结果
-    report.push_str("详细结果:\n");
-    report.push_str(&format!("{:<20} {:<15} {:<8} {:<10} {:<8} {:<10} {:<15}\n",
-                         "域名", "IP地址", "版本", "状态", "延迟", "大小", "错误"));
-    report.push_str(&format!("{}", "-".repeat(90)));
+// 同一参数在不同 IP 上的取值差异
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerParamDifference {
+    pub field: String,                        // 如 "settings.max_field_section_size"
+    pub values: BTreeMap<String, Vec<String>>, // 取值 -> 返回该取值的 IP 列表
+}
 
-    for result in results {
-        let status = if result.success { "成功" } else { "失败" };
-        let size = result.response_size.unwrap_or(0).to_string();
-        let error = result.error_message.as_deref().unwrap_or("");
+fn flatten_json(prefix: &str, value: &serde_json::Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                flatten_json(&format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        value => {
+            out.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
 
-        report.push_str(&format!("{:<20} {:<15} {:<8} {:<10} {:<8}ms {:<10} {:<15}\n",
-                             result.config.target_domain,
-                             result.target_ip,
-                             result.ip_version,
-                             status,
-                             result.latency_ms,
-                             size,
-                             error));
+// 对比各 IP 返回的 SETTINGS 与传输参数，只保留存在差异的字段
+pub fn diff_peer_params(results: &[H3TestResult]) -> Vec<PeerParamDifference> {
+    let observed: Vec<(&str, BTreeMap<String, String>)> = results
+        .iter()
+        .filter(|r| r.peer_settings.is_some() || r.transport_params.is_some())
+        .map(|r| {
+            let mut flat = BTreeMap::new();
+            if let Ok(value) = serde_json::to_value(&r.peer_settings) {
+                flatten_json("settings", &value, &mut flat);
+            }
+            if let Ok(value) = serde_json::to_value(&r.transport_params) {
+                flatten_json("transport", &value, &mut flat);
+            }
+            (r.target_ip.as_str(), flat)
+        })
+        .collect();
+
+    let fields: std::collections::BTreeSet<&String> = observed.iter().flat_map(|(_, flat)| flat.keys()).collect();
+
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for (ip, flat) in &observed {
+                let value = flat.get(field).cloned().unwrap_or_else(|| "-".to_string());
+                values.entry(value).or_default().push(ip.to_string());
+            }
+            (values.len() > 1).then(|| PeerParamDifference { field: field.clone(), values })
+        })
+        .collect()
+}
+
+pub fn generate_peer_params_report(results: &[H3TestResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== 对端 SETTINGS / 传输参数对比 ===\n\n");
+
+    let observed = results.iter().filter(|r| r.peer_settings.is_some() || r.transport_params.is_some()).count();
+    report.push_str(&format!("已采集: {} 个 IP\n", observed));
+
+    let differences = diff_peer_params(results);
+    if differences.is_empty() {
+        report.push_str("所有 IP 的 SETTINGS 与传输参数一致\n");
+        return report;
+    }
+
+    report.push_str(&format!("存在差异的参数: {}\n\n", differences.len()));
+    for difference in &differences {
+        report.push_str(&format!("{}\n", difference.field));
+        for (value, ips) in &difference.values {
+            report.push_str(&format!("    {:<12} {}\n", value, ips.join(", ")));
+        }
     }
 
     report
@@ -295,6 +2842,7 @@ pub fn get_default_h3_test_configs() -> Vec<H3TestConfig> {
     vec![
         H3TestConfig {
             target_domain: "local-aria2-webui.masx200.ddns-ip.net".to_string(),
+            host_header: None,
             target_ip: "104.16.123.64".to_string(),
             ip_version: "IPv4".to_string(),
             port: 443,
@@ -306,9 +2854,16 @@ pub fn get_default_h3_test_configs() -> Vec<H3TestConfig> {
             send_grease: false,
             user_agent: Some("rust-h3-test-tool/1.0".to_string()),
             max_concurrent_requests: 1,
+            connect_protocol: None,
+            connect_path: None,
+            masque_target: None,
+            masque_uri_template: None,
+            masque_dns_name: None,
+            transport: QuicTransportOptions::default(),
         },
         H3TestConfig {
             target_domain: "google.com".to_string(),
+            host_header: None,
             target_ip: "142.250.185.80".to_string(),
             ip_version: "IPv4".to_string(),
             port: 443,
@@ -320,9 +2875,16 @@ pub fn get_default_h3_test_configs() -> Vec<H3TestConfig> {
             send_grease: false,
             user_agent: Some("rust-h3-test-tool/1.0".to_string()),
             max_concurrent_requests: 1,
+            connect_protocol: None,
+            connect_path: None,
+            masque_target: None,
+            masque_uri_template: None,
+            masque_dns_name: None,
+            transport: QuicTransportOptions::default(),
         },
         H3TestConfig {
             target_domain: "facebook.com".to_string(),
+            host_header: None,
             target_ip: "31.13.66.35".to_string(),
             ip_version: "IPv4".to_string(),
             port: 443,
@@ -334,6 +2896,922 @@ pub fn get_default_h3_test_configs() -> Vec<H3TestConfig> {
             send_grease: false,
             user_agent: Some("rust-h3-test-tool/1.0".to_string()),
             max_concurrent_requests: 1,
+            connect_protocol: None,
+            connect_path: None,
+            masque_target: None,
+            masque_uri_template: None,
+            masque_dns_name: None,
+            transport: QuicTransportOptions::default(),
         },
     ]
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h3_test_server::{self, TcpServerOptions, TestServerOptions};
+    use crate::tls_trust::TrustRoots;
+    use base64::{engine::general_purpose, Engine as _};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn local_config(addr: SocketAddr) -> H3TestConfig {
+        H3TestConfig {
+            timeout_seconds: 5,
+            send_grease: false,
+            user_agent: None,
+            ..H3TestConfig::new("localhost", addr.ip(), addr.port())
+        }
+    }
+
+    #[test]
+    fn test_h3_datagram_codec_roundtrip() {
+        let stream_id = StreamId::try_from(8u64).unwrap();
+        let encoded = encode_h3_datagram(stream_id, b"ping");
+        assert_eq!(encoded.as_ref(), &[0x02, b'p', b'i', b'n', b'g']);
+
+        let (id, payload) = decode_h3_datagram(encoded).unwrap();
+        assert_eq!(id, stream_id);
+        assert_eq!(payload.as_ref(), b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_datagram_echo_against_local_server() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            enable_datagram: true,
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.enable_datagram = true;
+        let result = tester.test_http3_connection(&config).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let datagram = result.datagram.expect("缺少 datagram 探测结果");
+        assert!(datagram.quic_datagram_supported);
+        assert!(datagram.max_datagram_size.unwrap() > 0);
+        assert!(datagram.h3_datagram_advertised);
+        assert!(datagram.echo_success, "{:?}", datagram.error_message);
+    }
+
+    #[tokio::test]
+    async fn test_datagram_disabled_skips_probe() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_http3_connection(&local_config(server.addr)).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        assert!(result.datagram.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extended_connect_webtransport_session() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            enable_extended_connect: true,
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.enable_extended_connect = true;
+        config.connect_path = Some("/wt".to_string());
+        let result = tester.test_http3_connection(&config).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let connect = result.extended_connect.expect("缺少扩展 CONNECT 探测结果");
+        assert!(connect.settings_enable_connect_protocol);
+        assert_eq!(connect.protocol, "webtransport");
+        assert_eq!(connect.path, "/wt");
+        assert!(connect.attempted);
+        assert_eq!(connect.response_status, Some(200));
+        assert!(connect.session_established, "{:?}", connect.error_message);
+    }
+
+    #[tokio::test]
+    async fn test_extended_connect_not_advertised() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.enable_extended_connect = true;
+        let result = tester.test_http3_connection(&config).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let connect = result.extended_connect.expect("缺少扩展 CONNECT 探测结果");
+        assert!(!connect.settings_enable_connect_protocol);
+        assert!(!connect.attempted);
+    }
+
+    #[tokio::test]
+    async fn test_extended_connect_sends_websocket_protocol() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            enable_extended_connect: true,
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.enable_extended_connect = true;
+        config.connect_protocol = Some("websocket".to_string());
+        let result = tester.test_http3_connection(&config).await.unwrap();
+
+        // h3 服务端解码字段区后拒绝未知的 :protocol，以 H3_MESSAGE_ERROR (0x10e) 重置请求流而不关闭连接
+        assert!(result.success, "{:?}", result.error_message);
+        let connect = result.extended_connect.expect("缺少扩展 CONNECT 探测结果");
+        assert_eq!(connect.protocol, "websocket");
+        assert!(connect.attempted);
+        assert_eq!(connect.response_status, None);
+        assert!(connect.error_message.unwrap().contains("270"));
+    }
+
+    #[test]
+    fn test_expand_masque_uri_template() {
+        assert_eq!(
+            expand_masque_uri_template(DEFAULT_MASQUE_URI_TEMPLATE, "proxy.example", "1.1.1.1", 53),
+            "https://proxy.example/.well-known/masque/udp/1.1.1.1/53/"
+        );
+        assert_eq!(
+            expand_masque_uri_template(
+                "https://masque.example/udp?h={target_host}&p={target_port}",
+                "proxy.example",
+                "2606:4700:4700::1111",
+                53
+            ),
+            "https://masque.example/udp?h=2606%3A4700%3A4700%3A%3A1111&p=53"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_udp_dns_through_local_masque_server() {
+        let dns_addr = h3_test_server::spawn_dns_stub(Ipv4Addr::LOCALHOST.into()).await;
+        let server = h3_test_server::spawn(TestServerOptions {
+            enable_connect_udp: true,
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.masque_target = Some(dns_addr.to_string());
+        config.masque_dns_name = Some("example.com".to_string());
+        let result = tester.test_connect_udp(&config).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let connect_udp = result.connect_udp.expect("缺少 CONNECT-UDP 探测结果");
+        assert!(connect_udp.settings_enable_connect_protocol);
+        assert!(connect_udp.h3_datagram_advertised);
+        assert!(connect_udp.tunnel_established);
+        assert_eq!(connect_udp.response_status, Some(200));
+        assert_eq!(connect_udp.dns_answer_count, Some(1));
+        assert!(connect_udp.dns_rtt_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_target() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            enable_connect_udp: true,
+            bind_ip: Some(Ipv6Addr::LOCALHOST.into()),
+            ..Default::default()
+        })
+        .await;
+        server.spawn_tcp(TcpServerOptions { port: Some(server.addr.port()), ..Default::default() }).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        let mut config = local_config(server.addr);
+        assert_eq!(config.target_ip, "::1");
+
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        let client = tester.tcp_client(&config, TcpProtocol::Http11).unwrap();
+        assert!(tcp_get(&client, &config).send().await.unwrap().status().is_success());
+
+        // IPv6 目标经 CONNECT-UDP 隧道转发
+        let dns_addr = h3_test_server::spawn_dns_stub(Ipv6Addr::LOCALHOST.into()).await;
+        config.masque_target = Some(dns_addr.to_string());
+        config.masque_dns_name = Some("example.com".to_string());
+        let result = tester.test_connect_udp(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert_eq!(result.connect_udp.unwrap().dns_answer_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_connect_udp_without_proxy_support() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_connect_udp(&local_config(server.addr)).await.unwrap();
+
+        assert!(!result.success);
+        let connect_udp = result.connect_udp.expect("缺少 CONNECT-UDP 探测结果");
+        assert!(!connect_udp.settings_enable_connect_protocol);
+        assert!(!connect_udp.tunnel_established);
+    }
+
+    #[tokio::test]
+    async fn test_grease_and_field_section_sweep() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            response_header_padding: 512,
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let steps = tester
+            .sweep_grease_and_field_section(&local_config(server.addr), &[64, 16384])
+            .await;
+
+        let variants: Vec<_> = steps.iter().map(|s| (s.send_grease, s.max_field_section_size)).collect();
+        assert_eq!(variants, vec![(true, None), (false, None), (false, Some(16384)), (false, Some(64))]);
+        assert!(steps[..3].iter().all(|s| s.success && s.rejected_by.is_none()), "{:?}", steps);
+        assert!(!steps[3].success);
+        // h3 服务端按本端声明的限制拒绝发送过大的响应头，因此由对端拒绝
+        assert_eq!(steps[3].rejected_by.as_deref(), Some("peer"), "{:?}", steps[3]);
+    }
+
+    #[test]
+    fn test_sweep_step_rejected_by_client_limit() {
+        let config = H3TestConfig { max_field_section_size: Some(64), ..local_config("127.0.0.1:443".parse().unwrap()) };
+        let failure = H3TestResult::failure(&config, 1, "HTTP/3 request failed".to_string());
+
+        let rejected = H3SweepStep::from_result(&H3TestResult { response_header_too_big: Some(600), ..failure.clone() });
+        assert_eq!(rejected.rejected_by.as_deref(), Some("client"));
+        assert!(!rejected.success);
+        assert_eq!(H3SweepStep::from_result(&failure).rejected_by.as_deref(), Some("peer"));
+    }
+
+    #[tokio::test]
+    async fn test_peer_settings_and_transport_params_recorded() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_http3_connection(&local_config(server.addr)).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        // 测试服务端发送会话票据，票据中带有协商的 TLS 1.3 密码套件
+        assert!(result.cipher_suite.as_deref().is_some_and(|suite| suite.starts_with("TLS13_")), "{:?}", result.cipher_suite);
+        let settings = result.peer_settings.expect("缺少对端 SETTINGS");
+        assert!(settings.grease_settings > 0);
+        assert_eq!(settings.enable_connect_protocol.unwrap_or(0), 0);
+        let params = result.transport_params.expect("缺少对端传输参数");
+        assert_eq!(params.initial_max_streams_bidi, 100);
+        assert!(params.initial_max_data > 0);
+        assert!(params.active_connection_id_limit >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_peer_params_diff_across_edges() {
+        let plain = h3_test_server::spawn(TestServerOptions::default()).await;
+        let datagram = h3_test_server::spawn(TestServerOptions {
+            enable_datagram: true,
+            ..Default::default()
+        })
+        .await;
+
+        let mut results = Vec::new();
+        for server in [&plain, &plain, &datagram] {
+            let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+            let mut result = tester.test_http3_connection(&local_config(server.addr)).await.unwrap();
+            result.target_ip = server.addr.to_string();
+            results.push(result);
+        }
+
+        let differences = diff_peer_params(&results);
+        let fields: Vec<&str> = differences.iter().map(|d| d.field.as_str()).collect();
+        assert!(fields.contains(&"settings.h3_datagram"), "{:?}", fields);
+        // 两个服务器使用相同的 quinn 传输配置
+        assert!(fields.iter().all(|f| !f.starts_with("transport.")), "{:?}", fields);
+
+        let h3_datagram = differences.iter().find(|d| d.field == "settings.h3_datagram").unwrap();
+        assert_eq!(h3_datagram.values.get("1"), Some(&vec![datagram.addr.to_string()]));
+        assert_eq!(h3_datagram.values.len(), 2);
+        assert!(h3_datagram.values.values().any(|ips| ips.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn test_multiplexed_streams_hit_server_stream_limit() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            max_concurrent_bidi_streams: Some(2),
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.max_concurrent_requests = 5;
+        let result = tester.test_multiplexed(&config).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let multiplex = result.multiplex.expect("缺少多路复用结果");
+        assert_eq!(multiplex.concurrent_requests, 5);
+        assert_eq!(multiplex.peer_max_streams_bidi, Some(2));
+        assert_eq!(multiplex.stream_limit_hit_at, Some(2));
+        assert_eq!(multiplex.multiplexed.connections, 1);
+        assert_eq!(multiplex.multiplexed.successful, 5);
+        assert_eq!(multiplex.separate_connections.connections, 5);
+        assert_eq!(multiplex.separate_connections.successful, 5);
+        assert!(multiplex.multiplexed.latency_spread_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_multiplexed_streams_within_limit() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.max_concurrent_requests = 8;
+        let multiplex = tester.test_multiplexed(&config).await.unwrap().multiplex.unwrap();
+
+        assert_eq!(multiplex.multiplexed.successful, 8);
+        assert_eq!(multiplex.stream_limit_hit_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_timed_request_reports_timeout() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        let mut config = local_config(server.addr);
+        let mut session = tester.connect(&config).await.unwrap();
+
+        let completed = timed_request(&mut session.send_request, &config, 0, Instant::now()).await;
+        assert!(!completed.timed_out);
+        assert_eq!(completed.response_status, Some(200));
+
+        // 超时为 0 时请求在收到响应前被放弃
+        config.timeout_seconds = 0;
+        let abandoned = timed_request(&mut session.send_request, &config, 1, Instant::now()).await;
+        assert!(abandoned.timed_out);
+        assert_eq!(abandoned.first_byte_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_with_and_without_keep_alive() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            max_idle_timeout_ms: Some(600),
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.transport.keep_alive_interval_ms = Some(200);
+        let result = tester.test_idle_timeout(&config, &[1500, 100]).await.unwrap();
+        let idle = result.idle.unwrap();
+
+        assert!(result.success);
+        assert_eq!(idle.peer_idle_timeout_ms, Some(600));
+        assert_eq!(idle.steps.len(), 4);
+        assert_eq!(idle.longest_idle_ok_ms, Some(100));
+        assert_eq!(idle.shortest_idle_failed_ms, Some(1500));
+        assert_eq!(idle.keep_alive_holds, Some(true));
+        assert!(!idle.nat_rebinding_seen);
+        assert_eq!(idle.silent_drops, 0);
+
+        // 对端超时后 quinn 按协商后的空闲超时 (取双方较小值) 在本地关闭连接
+        let expired = idle.steps.iter().find(|s| s.keep_alive_ms.is_none() && s.idle_ms == 1500).unwrap();
+        assert_eq!(expired.outcome, "idle_timeout");
+    }
+
+    #[tokio::test]
+    async fn test_migration_after_rebind() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_migration(&local_config(server.addr)).await.unwrap();
+        let migration = result.migration.unwrap();
+
+        assert!(result.success, "{:?}", migration.error_message);
+        assert!(migration.rebound);
+        assert_ne!(migration.old_local_addr, migration.new_local_addr);
+        assert!(migration.request_after_ok);
+        assert_eq!(migration.response_status, Some(200));
+        assert!(migration.path_challenges_received > 0);
+        assert!(migration.path_validated);
+        assert_eq!(migration.peer_disable_active_migration, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_mtu_sweep_with_fixed_sizes() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.test_path = "/__down?bytes=200000".to_string();
+        let result = tester.test_mtu(&config, &[1200, 1400, 1100]).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let path_mtu = result.path_mtu.unwrap();
+        assert!(path_mtu.discovery_enabled);
+        assert!(path_mtu.current_mtu >= QUIC_MIN_MTU);
+        assert_eq!(path_mtu.black_holes_detected, 0);
+
+        let sweep = result.mtu_sweep.unwrap();
+        assert_eq!(sweep.largest_working_mtu, Some(1400));
+        assert_eq!(sweep.steps.iter().map(|s| s.mtu).collect::<Vec<_>>(), vec![1400, 1200, 1100]);
+        assert_eq!(sweep.steps[0].current_mtu, Some(1400));
+        assert_eq!(sweep.steps[0].response_size, Some(200000));
+        assert!(sweep.steps[1].success);
+        assert!(!sweep.steps[2].success);
+        assert!(!sweep.blackhole_suspected);
+    }
+
+    #[test]
+    fn test_mtu_sweep_flags_blackhole() {
+        let step = |mtu, success| H3MtuStep { mtu, success, ..Default::default() };
+        let steps = vec![step(1400, false), step(1280, true), step(1200, true)];
+
+        // 默认请求失败而较小的固定 MTU 成功：不计为成功，单独标记疑似黑洞
+        let sweep = H3MtuSweep::new(false, steps.clone());
+        assert_eq!(sweep.largest_working_mtu, Some(1280));
+        assert!(sweep.blackhole_suspected);
+
+        assert!(!H3MtuSweep::new(true, steps).blackhole_suspected);
+        assert!(!H3MtuSweep::new(false, vec![step(1200, false)]).blackhole_suspected);
+
+        let config = local_config("127.0.0.1:443".parse().unwrap());
+        let result = H3TestResult {
+            mtu_sweep: Some(H3MtuSweep::new(false, vec![step(1280, true)])),
+            ..H3TestResult::failure(&config, 0, "HTTP/3 request timeout".to_string())
+        };
+        let report = generate_mtu_report(&[result]);
+        assert!(report.contains("失败"));
+        assert!(report.contains("是"));
+    }
+
+    #[tokio::test]
+    async fn test_alt_svc_upgrade_to_advertised_port() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tcp_addr = server
+            .spawn_tcp(TcpServerOptions {
+                alt_svc: Some(format!(r#"h3-29=":1"; ma=60, h3=":{}"; ma=3600"#, server.addr.port())),
+                ..Default::default()
+            })
+            .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_alt_svc(&local_config(tcp_addr)).await.unwrap();
+        let alt_svc = result.alt_svc.unwrap();
+
+        assert!(result.success, "{:?}", alt_svc.error_message);
+        assert_eq!(alt_svc.tcp_protocol.as_deref(), Some("HTTP/1.1"));
+        assert_eq!(alt_svc.tcp_status, Some(200));
+        assert!(alt_svc.advertises_h3);
+        assert_eq!(alt_svc.h3_protocol_id.as_deref(), Some("h3"));
+        assert_eq!(alt_svc.h3_authority, Some(format!(":{}", server.addr.port())));
+        assert_eq!(alt_svc.h3_works, Some(true));
+        assert_eq!(alt_svc.h3_status, Some(200));
+        assert_eq!(result.config.port, tcp_addr.port());
+    }
+
+    #[tokio::test]
+    async fn test_alt_svc_not_advertised() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tcp_addr = server.spawn_tcp(TcpServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_alt_svc(&local_config(tcp_addr)).await.unwrap();
+        let alt_svc = result.alt_svc.unwrap();
+
+        assert!(!result.success);
+        assert_eq!(alt_svc.tcp_status, Some(200));
+        assert_eq!(alt_svc.alt_svc_header, None);
+        assert!(!alt_svc.advertises_h3);
+        assert_eq!(alt_svc.h3_works, None);
+    }
+
+    #[tokio::test]
+    async fn test_authority_separate_from_sni() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            served_authority: Some("front.example".to_string()),
+            ..Default::default()
+        })
+        .await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        let mut config = local_config(server.addr);
+
+        // 未指定 host_header 时 :authority 与 SNI 相同
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert_eq!(result.response_status, Some(421));
+
+        config.host_header = Some("front.example".to_string());
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert_eq!(result.response_status, Some(200));
+    }
+
+    #[tokio::test]
+    async fn test_certificate_report_groups_by_fingerprint() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        let mut config = local_config(server.addr);
+
+        let covered = tester.test_http3_connection(&config).await.unwrap();
+        let certificate = covered.certificate.clone().unwrap();
+        assert!(covered.success, "{:?}", covered.error_message);
+        assert!(certificate.san_covers_sni);
+        assert_eq!(certificate.verification_error, None);
+        assert_eq!(certificate.chain[0].sans, vec!["localhost"]);
+
+        // SNI 不在 SAN 中：握手失败，但仍带回对端证书
+        config.target_domain = "wrong-sni.example".to_string();
+        let mismatch = tester.test_http3_connection(&config).await.unwrap();
+        let mismatch_certificate = mismatch.certificate.clone().unwrap();
+        assert!(!mismatch.success);
+        assert!(!mismatch_certificate.san_covers_sni);
+        assert!(mismatch_certificate.verification_error.is_some());
+        assert_eq!(mismatch_certificate.leaf_fingerprint, certificate.leaf_fingerprint);
+
+        // 另一台服务器使用不同的证书，在报告中属于少数组
+        let other = h3_test_server::spawn(TestServerOptions::default()).await;
+        let other_tester = H3Tester::with_root_store(other.root_store()).unwrap();
+        let mut other_result = other_tester.test_http3_connection(&local_config(other.addr)).await.unwrap();
+        other_result.config.target_ip = "192.0.2.1".to_string();
+
+        let report = generate_certificate_report(&[covered, mismatch, other_result]);
+        assert!(report.contains("不同的叶子证书: 2 个"));
+        assert!(report.contains("与多数 IP (2 个) 的证书不同"));
+        assert!(report.contains("192.0.2.1"));
+    }
+
+    #[tokio::test]
+    async fn test_trust_options_pem_insecure_and_spki_pin() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let config = local_config(server.addr);
+        let webpki = |spki_pins: Vec<String>, insecure: bool| {
+            H3Tester::with_trust(&TlsTrustOptions { roots: TrustRoots::Webpki, spki_pins, insecure, ..Default::default() })
+                .unwrap()
+        };
+
+        // webpki 根不包含自签名的测试证书
+        let strict = webpki(Vec::new(), false).test_http3_connection(&config).await.unwrap();
+        let certificate = strict.certificate.unwrap();
+        assert!(!strict.success);
+        assert!(certificate.verification_error.is_some());
+        let pin = certificate.chain[0].spki_pin.clone();
+
+        // PEM 证书包
+        let ca_file = std::env::temp_dir().join(format!("h3-test-ca-{}.pem", server.addr.port()));
+        let pem = general_purpose::STANDARD.encode(&server.cert);
+        std::fs::write(&ca_file, format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", pem)).unwrap();
+        let pem_tester = H3Tester::with_trust(&TlsTrustOptions {
+            roots: TrustRoots::Pem,
+            ca_file: Some(ca_file.to_string_lossy().into_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+        let result = pem_tester.test_http3_connection(&config).await.unwrap();
+        std::fs::remove_file(&ca_file).unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+
+        // insecure 模式接受验证失败的证书，但仍记录失败原因；SPKI 固定照常生效
+        let result = webpki(vec![pin], true).test_http3_connection(&config).await.unwrap();
+        let certificate = result.certificate.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert!(certificate.insecure);
+        assert!(certificate.verification_error.is_some());
+        assert_eq!(certificate.spki_pin_matched, Some(true));
+
+        let wrong_pin = format!("sha256/{}", general_purpose::STANDARD.encode([0u8; 32]));
+        let result = webpki(vec![wrong_pin], true).test_http3_connection(&config).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.certificate.unwrap().spki_pin_matched, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_keylog_file_records_h3_and_tcp_secrets() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        server.spawn_tcp(TcpServerOptions { port: Some(server.addr.port()), ..Default::default() }).await;
+        let config = local_config(server.addr);
+        let keylog = std::env::temp_dir().join(format!("h3-test-keylog-{}.txt", server.addr.port()));
+        let tester = H3Tester::with_trust(&TlsTrustOptions {
+            roots: TrustRoots::Webpki,
+            insecure: true,
+            keylog: Some(keylog.to_string_lossy().into_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        let client = tester.tcp_client(&config, TcpProtocol::Http11).unwrap();
+        tcp_get(&client, &config).send().await.unwrap();
+
+        let lines = std::fs::read_to_string(&keylog).unwrap();
+        std::fs::remove_file(&keylog).unwrap();
+        // NSS Key Log: <label> <client_random 十六进制> <secret 十六进制>，两个连接各有一组
+        let mut client_randoms = std::collections::BTreeSet::new();
+        for line in lines.lines() {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(), 3, "{}", line);
+            assert_eq!(fields[1].len(), 64);
+            client_randoms.insert(fields[1]);
+        }
+        assert_eq!(client_randoms.len(), 2);
+        assert!(lines.contains("CLIENT_HANDSHAKE_TRAFFIC_SECRET "));
+        assert!(lines.contains("CLIENT_TRAFFIC_SECRET_0 "));
+    }
+
+    #[tokio::test]
+    async fn test_qlog_trace_kept_only_when_requested() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let config = local_config(server.addr);
+        let dir = std::env::temp_dir().join(format!("h3-test-qlog-{}", server.addr.port()));
+
+        // 默认成功探测的 trace 在结束时删除
+        let tester = H3Tester::with_root_store(server.root_store())
+            .unwrap()
+            .with_qlog(Some(QlogDir::new(&dir, false).unwrap()));
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert!(tester.finish_qlog(result.success).is_empty());
+
+        let tester = H3Tester::with_root_store(server.root_store())
+            .unwrap()
+            .with_qlog(Some(QlogDir::new(&dir, true).unwrap()));
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        let paths = tester.finish_qlog(result.success);
+        assert_eq!(paths.len(), 1);
+
+        let content = std::fs::read_to_string(&paths[0]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let records: Vec<serde_json::Value> = content
+            .split('\x1e')
+            .filter(|record| !record.is_empty())
+            .map(|record| serde_json::from_str(record).unwrap())
+            .collect();
+        assert_eq!(records[0]["qlog_format"], "JSON-SEQ");
+        let names: Vec<&str> = records.iter().filter_map(|record| record["name"].as_str()).collect();
+        assert!(names.contains(&"transport:packet_sent"));
+        assert!(names.contains(&"transport:packet_received"));
+        assert!(names.contains(&"transport:stream_state_updated"));
+    }
+
+    #[tokio::test]
+    async fn test_local_verification_failure_is_not_client_certificate_rejection() {
+        let client = rcgen::generate_simple_self_signed(vec!["client.example".to_string()]).unwrap();
+        let mut client_roots = RootCertStore::empty();
+        client_roots.add(client.serialize_der().unwrap().into()).unwrap();
+        let server = h3_test_server::spawn(TestServerOptions {
+            client_auth_roots: Some(client_roots),
+            ..Default::default()
+        })
+        .await;
+
+        // 对端请求了客户端证书，但握手因本端 SPKI 固定不匹配而失败
+        let mut tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        tester.trust.spki_pins = vec![[0u8; 32]];
+        let result = tester.test_http3_connection(&local_config(server.addr)).await.unwrap();
+        let client_auth = result.client_auth.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.certificate.unwrap().spki_pin_matched, Some(false));
+        assert!(client_auth.requested);
+        assert_eq!(client_auth.accepted, None);
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate_requested_and_accepted() {
+        let client = rcgen::generate_simple_self_signed(vec!["client.example".to_string()]).unwrap();
+        let mut client_roots = RootCertStore::empty();
+        client_roots.add(client.serialize_der().unwrap().into()).unwrap();
+        let server = h3_test_server::spawn(TestServerOptions {
+            client_auth_roots: Some(client_roots),
+            ..Default::default()
+        })
+        .await;
+        let config = local_config(server.addr);
+
+        // 未配置客户端证书：对端请求后拒绝连接
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        let client_auth = result.client_auth.unwrap();
+        assert!(!result.success);
+        assert!(client_auth.requested);
+        assert!(!client_auth.presented);
+        assert_eq!(client_auth.accepted, Some(false));
+        assert_eq!(client_auth.acceptable_issuers, vec!["CN=rcgen self signed cert"]);
+
+        let dir = std::env::temp_dir();
+        let cert_file = dir.join(format!("h3-test-client-{}.pem", server.addr.port()));
+        let key_file = dir.join(format!("h3-test-client-{}.key", server.addr.port()));
+        std::fs::write(&cert_file, client.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_file, client.serialize_private_key_pem()).unwrap();
+        let mut tester = H3Tester::with_root_store(server.root_store()).unwrap();
+        tester.trust.client_cert = TlsTrustOptions {
+            roots: TrustRoots::Webpki,
+            client_cert: Some(cert_file.to_string_lossy().into_owned()),
+            client_key: Some(key_file.to_string_lossy().into_owned()),
+            ..Default::default()
+        }
+        .build()
+        .unwrap()
+        .client_cert;
+        std::fs::remove_file(&cert_file).unwrap();
+        std::fs::remove_file(&key_file).unwrap();
+
+        let result = tester.test_http3_connection(&config).await.unwrap();
+        let client_auth = result.client_auth.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert!(client_auth.configured && client_auth.requested && client_auth.presented);
+        assert_eq!(client_auth.accepted, Some(true));
+
+        // 对端未请求客户端证书
+        let plain = h3_test_server::spawn(TestServerOptions::default()).await;
+        tester.trust.verifier = TrustPolicy::from_root_store(plain.root_store()).unwrap().verifier;
+        let result = tester.test_http3_connection(&local_config(plain.addr)).await.unwrap();
+        let client_auth = result.client_auth.unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert!(client_auth.configured && !client_auth.requested);
+        assert_eq!(client_auth.accepted, None);
+    }
+
+    #[tokio::test]
+    async fn test_ech_rejected_by_server_without_ech() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        server.spawn_tcp(TcpServerOptions { port: Some(server.addr.port()), ..Default::default() }).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        // 测试服务器不支持 ECH，只能看到 SNI 为 public_name (localhost) 的外层 ClientHello
+        let config = H3TestConfig { target_domain: "inner.example".to_string(), ..local_config(server.addr) };
+        let config_list = crate::ech::test_support::config_list("localhost", 0x0001);
+        let result = tester.test_ech(&config, Some(&config_list)).await.unwrap();
+        let ech = result.ech.unwrap();
+
+        assert!(!result.success);
+        assert!(ech.config_published);
+        assert_eq!(ech.public_names, vec!["localhost"]);
+        assert_eq!(ech.attempts.len(), 2);
+        if PQ_AVAILABLE {
+            for attempt in &ech.attempts {
+                assert_eq!(attempt.outcome, EchOutcome::Rejected, "{:?}", attempt);
+                assert_eq!(attempt.verified_name.as_deref(), Some("localhost"));
+            }
+            // 只有 TCP 上能拿到 rustls 的拒绝错误
+            assert_eq!(ech.attempts[0].retry_configs, None);
+            assert_eq!(ech.attempts[1].retry_configs, Some(false));
+            assert!(result.certificate.unwrap().san_covers_sni);
+        } else {
+            // 没有 HPKE 时不发送 ECH，也不发起连接
+            assert!(ech.attempts.iter().all(|a| a.outcome == EchOutcome::NotOffered));
+            assert!(ech.attempts[0].error_message.as_deref().unwrap().contains("--features pq"));
+        }
+
+        let result = tester.test_ech(&config, None).await.unwrap();
+        let ech = result.ech.unwrap();
+        assert!(!ech.config_published);
+        assert!(ech.attempts.iter().all(|a| a.outcome == EchOutcome::NotOffered));
+    }
+
+    #[tokio::test]
+    async fn test_key_exchange_negotiated_group() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        server.spawn_tcp(TcpServerOptions { port: Some(server.addr.port()), ..Default::default() }).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_key_exchange(&local_config(server.addr)).await.unwrap();
+        let key_exchange = result.key_exchange.clone().unwrap();
+        assert!(result.success, "{:?}", result.error_message);
+        assert_eq!(key_exchange.pq_available, PQ_AVAILABLE);
+        assert_eq!(key_exchange.attempts.len(), if PQ_AVAILABLE { 4 } else { 2 });
+        assert!(key_exchange.pq_broken.is_empty());
+
+        for attempt in &key_exchange.attempts {
+            let expected = match attempt.key_exchange {
+                KeyExchange::Pq => "X25519MLKEM768",
+                KeyExchange::Classic => "X25519",
+            };
+            assert_eq!(attempt.negotiated_group.as_deref(), Some(expected), "{:?}", attempt);
+            assert_eq!(attempt.client_hello_bytes.is_some(), attempt.protocol == "h3");
+        }
+        // X25519MLKEM768 的密钥份额有 1216 字节，ClientHello 超过单个 Initial 包
+        let initial_packets = |mode| {
+            key_exchange.attempts.iter().find(|a| a.key_exchange == mode && a.protocol == "h3").and_then(|a| a.initial_packets)
+        };
+        assert_eq!(initial_packets(KeyExchange::Classic), Some(1));
+        if PQ_AVAILABLE {
+            assert!(key_exchange.pq_negotiated);
+            assert_eq!(initial_packets(KeyExchange::Pq), Some(2));
+        }
+
+        let report = generate_key_exchange_report(&[result]);
+        assert!(report.contains("协商: X25519"));
+    }
+
+    #[tokio::test]
+    async fn test_race_h3_wins_with_head_start() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        server.spawn_tcp(TcpServerOptions { port: Some(server.addr.port()), ..Default::default() }).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_race(&local_config(server.addr), 500).await.unwrap();
+        let race = result.race.unwrap();
+
+        assert!(result.success);
+        assert_eq!(race.winner.as_deref(), Some("h3"));
+        // 测试服务器只支持 http/1.1，h2 尝试失败；落败的 http/1.1 仍然成功
+        let outcome = |protocol: &str| race.attempts.iter().find(|a| a.protocol == protocol).unwrap().success;
+        assert!(!outcome("h2"));
+        assert!(outcome("http/1.1"));
+        assert!(race.margin_ms.unwrap() > 0);
+        assert!(race.attempts.iter().filter(|a| a.protocol != "h3").all(|a| a.started_ms >= 500));
+    }
+
+    #[tokio::test]
+    async fn test_race_tcp_wins_when_udp_is_blocked() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        // 同端口 UDP 上没有 h3 服务
+        let tcp_addr = server.spawn_tcp(TcpServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(tcp_addr);
+        config.timeout_seconds = 1;
+        let result = tester.test_race(&config, 0).await.unwrap();
+        let race = result.race.clone().unwrap();
+
+        assert!(result.success);
+        assert_eq!(race.winner.as_deref(), Some("http/1.1"));
+        assert_eq!(race.margin_ms, None);
+        assert!(!race.attempts[0].success);
+        assert_eq!(generate_race_report(&[result]).lines().count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_pins_tcp_attempts_to_target() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        // 同端口 UDP 上没有 h3 服务，测试服务器的 TCP 端只支持 http/1.1
+        let tcp_addr = server.spawn_tcp(TcpServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(tcp_addr);
+        config.timeout_seconds = 1;
+        let result = tester.test_fallback(&config).await.unwrap();
+        let fallback = result.fallback.clone().unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        assert_eq!(fallback.protocol.as_deref(), Some("http/1.1"));
+        let protocols: Vec<&str> = fallback.attempts.iter().map(|a| a.protocol.as_str()).collect();
+        assert_eq!(protocols, ["h3", "h2", "http/1.1"]);
+        assert!(!fallback.attempts[0].success && !fallback.attempts[1].success);
+        assert_eq!(fallback.attempts[2].response_status, Some(200));
+        assert_eq!(fallback.attempts[2].remote_addr, Some(tcp_addr.to_string()));
+        assert!(generate_fallback_report(&[result]).contains("http/1.1   ✗"));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_stops_at_h3() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = tester.test_fallback(&local_config(server.addr)).await.unwrap();
+        let fallback = result.fallback.unwrap();
+
+        assert!(result.success);
+        assert_eq!(fallback.protocol.as_deref(), Some("h3"));
+        assert_eq!(fallback.attempts.len(), 1);
+    }
+
+    #[test]
+    fn test_request_uri_includes_non_default_port() {
+        let mut config = H3TestConfig::new("example.com", IpAddr::V4(Ipv4Addr::LOCALHOST), 443);
+        assert_eq!(config.request_uri("/a"), "https://example.com/a");
+
+        config.port = 8443;
+        assert_eq!(config.request_uri("/a"), "https://example.com:8443/a");
+        config.host_header = Some("front.example".to_string());
+        assert_eq!(config.request_uri("/"), "https://front.example:8443/");
+        // host_header 已带端口时不再追加
+        config.host_header = Some("front.example:9443".to_string());
+        assert_eq!(config.request_uri("/"), "https://front.example:9443/");
+    }
+
+    #[test]
+    fn test_check_remote_addr() {
+        let config = H3TestConfig::new("localhost", IpAddr::V4(Ipv4Addr::LOCALHOST), 8443);
+
+        assert!(check_remote_addr(&config, None).is_ok());
+        assert!(check_remote_addr(&config, Some("127.0.0.1:8443".parse().unwrap())).is_ok());
+        let error = check_remote_addr(&config, Some("127.0.0.2:8443".parse().unwrap())).unwrap_err();
+        assert!(error.to_string().contains("127.0.0.2:8443"));
+        assert!(check_remote_addr(&config, Some("127.0.0.1:443".parse().unwrap())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_congestion_controller_comparison() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_config(server.addr);
+        config.test_path = "/__down?bytes=1000000".to_string();
+        config.transport.stream_receive_window = Some(256 * 1024);
+        let result = tester.test_congestion_controllers(&config, &CongestionController::ALL).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error_message);
+        let runs = result.congestion.unwrap();
+        assert_eq!(runs.iter().map(|r| r.controller).collect::<Vec<_>>(), CongestionController::ALL.to_vec());
+        for run in &runs {
+            assert_eq!(run.response_size, Some(1_000_000));
+            assert!(run.cwnd.unwrap() > 0);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// SNI × Host × IP 矩阵测试模块 - 将解析域名、SNI、Host、路径与端口列表展开为笛卡尔积，共享 DNS 结果
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use crate::h3_direct_test::{H3TestConfig, H3Tester};
+use crate::quic_transport::QuicTransportOptions;
+use crate::tls_trust::TlsTrustOptions;
+
+pub const DEFAULT_MATRIX_CONCURRENCY: usize = 8;
+
+// --- 1. 矩阵任务 ---
+// 每个列表都会参与笛卡尔积；paths / ports 省略时分别为 ["/"] 与 [443]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatrixTask {
+    pub resolve_domains: Vec<String>, // 用于 DNS 解析的优选域名
+    pub sni_hosts: Vec<String>,
+    pub host_headers: Vec<String>, // 请求的 :authority
+    #[serde(default = "default_paths")]
+    pub paths: Vec<String>,
+    #[serde(default = "default_ports")]
+    pub ports: Vec<u16>,
+    #[serde(default)]
+    pub doh_url: Option<String>, // 默认使用命令行的 --doh-server
+    #[serde(default)]
+    pub prefer_ipv6: Option<bool>, // Some(true) 只测 IPv6，Some(false) 只测 IPv4，None 全部测试
+    #[serde(default)]
+    pub direct_ips: Option<Vec<IpAddr>>, // 设置后跳过 DNS，所有解析域名共用这些 IP
+    #[serde(default)]
+    pub max_ips_per_domain: Option<usize>,
+    #[serde(default)]
+    pub trust: Option<TlsTrustOptions>, // 默认使用命令行的 --trust / --ca-file / --pin-spki / --insecure
+}
+
+fn default_paths() -> Vec<String> {
+    vec!["/".to_string()]
+}
+
+fn default_ports() -> Vec<u16> {
+    vec![443]
+}
+
+// 展开后的单个组合
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MatrixCase {
+    pub resolve_domain: String,
+    pub ip: IpAddr,
+    pub sni: String,
+    pub host: String,
+    pub path: String,
+    pub port: u16,
+}
+
+impl MatrixTask {
+    // 按 解析域名 → IP → SNI → Host → 路径 → 端口 的顺序展开；ips 为各解析域名共享的 DNS 结果
+    pub fn expand(&self, ips: &BTreeMap<String, Vec<IpAddr>>) -> Vec<MatrixCase> {
+        let mut cases = Vec::new();
+        for resolve_domain in &self.resolve_domains {
+            let Some(domain_ips) = ips.get(resolve_domain) else { continue };
+            let domain_ips = domain_ips
+                .iter()
+                .filter(|ip| self.prefer_ipv6.is_none_or(|v6| ip.is_ipv6() == v6))
+                .take(self.max_ips_per_domain.unwrap_or(usize::MAX));
+            for &ip in domain_ips {
+                for sni in &self.sni_hosts {
+                    for host in &self.host_headers {
+                        for path in &self.paths {
+                            for &port in &self.ports {
+                                cases.push(MatrixCase {
+                                    resolve_domain: resolve_domain.clone(),
+                                    ip,
+                                    sni: sni.clone(),
+                                    host: host.clone(),
+                                    path: path.clone(),
+                                    port,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        cases
+    }
+}
+
+// --- 2. 矩阵结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatrixCell {
+    #[serde(flatten)]
+    pub case: MatrixCase,
+    pub works: bool, // 收到 2xx / 3xx 响应
+    pub response_status: Option<u16>,
+    pub latency_ms: u64,
+    pub error_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub qlog_files: Vec<PathBuf>, // 该组合保留下来的 qlog 文件
+}
+
+// --- 3. 执行 ---
+pub async fn run_matrix(
+    tester: &H3Tester,
+    cases: Vec<MatrixCase>,
+    timeout_seconds: u64,
+    transport: &QuicTransportOptions,
+    concurrency: usize,
+) -> Vec<MatrixCell> {
+    let mut cells: Vec<(usize, MatrixCell)> = futures::stream::iter(cases.into_iter().enumerate())
+        .map(|(index, case)| async move {
+            let config = H3TestConfig {
+                host_header: Some(case.host.clone()),
+                test_path: case.path.clone(),
+                timeout_seconds,
+                transport: transport.clone(),
+                ..H3TestConfig::new(&case.sni, case.ip, case.port)
+            };
+            // 每个组合单独结束 qlog，只保留自身失败连接的 trace
+            let tester = tester.with_qlog_scope();
+            let mut cell = match tester.test_http3_connection(&config).await {
+                Ok(result) => MatrixCell {
+                    works: result.success && result.response_status.is_some_and(|s| s < 400),
+                    response_status: result.response_status,
+                    latency_ms: result.latency_ms,
+                    error_message: result.error_message,
+                    qlog_files: Vec::new(),
+                    case,
+                },
+                Err(e) => MatrixCell {
+                    works: false,
+                    response_status: None,
+                    latency_ms: 0,
+                    error_message: Some(format!("{:#}", e)),
+                    qlog_files: Vec::new(),
+                    case,
+                },
+            };
+            cell.qlog_files = tester.finish_qlog(cell.works);
+            (index, cell)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    cells.sort_by_key(|(index, _)| *index);
+    cells.into_iter().map(|(_, cell)| cell).collect()
+}
+
+// --- 4. 透视表 ---
+// 透视表的一行: 解析域名 → 该组合在各 IP 上的结果
+type PivotRow<'a> = BTreeMap<&'a str, Vec<&'a MatrixCell>>;
+
+// 行为 SNI / Host / 路径 / 端口组合，列为解析域名，单元格为 可用 IP 数/测试 IP 数 以及出现的状态码
+pub fn generate_matrix_report(cells: &[MatrixCell]) -> String {
+    let mut report = String::new();
+    report.push_str("=== SNI × Host × IP 矩阵 ===\n\n");
+
+    let mut columns: Vec<&str> = Vec::new();
+    let mut rows: BTreeMap<(&str, &str, &str, u16), PivotRow> = BTreeMap::new();
+    for cell in cells {
+        let case = &cell.case;
+        if !columns.contains(&case.resolve_domain.as_str()) {
+            columns.push(&case.resolve_domain);
+        }
+        rows.entry((&case.sni, &case.host, &case.path, case.port))
+            .or_default()
+            .entry(&case.resolve_domain)
+            .or_default()
+            .push(cell);
+    }
+
+    report.push_str(&format!("{:<32} {:<32} {:<16} {:<6}", "SNI", "Host", "路径", "端口"));
+    for column in &columns {
+        report.push_str(&format!(" {:<24}", column));
+    }
+    report.push('\n');
+    report.push_str(&"-".repeat(88 + 25 * columns.len()));
+    report.push('\n');
+
+    for ((sni, host, path, port), by_domain) in &rows {
+        report.push_str(&format!("{:<32} {:<32} {:<16} {:<6}", sni, host, path, port));
+        for column in &columns {
+            let summary = match by_domain.get(column) {
+                Some(cells) => {
+                    let works = cells.iter().filter(|c| c.works).count();
+                    let mut statuses: Vec<String> = cells
+                        .iter()
+                        .map(|c| c.response_status.map(|s| s.to_string()).unwrap_or_else(|| "err".to_string()))
+                        .collect();
+                    statuses.sort();
+                    statuses.dedup();
+                    format!("{} {}/{} ({})", if works > 0 { "✅" } else { "❌" }, works, cells.len(), statuses.join(","))
+                }
+                None => "-".to_string(),
+            };
+            report.push_str(&format!(" {:<24}", summary));
+        }
+        report.push('\n');
+    }
+
+    let working = cells.iter().filter(|c| c.works).count();
+    report.push_str(&format!("\n可用组合: {}/{}\n", working, cells.len()));
+
+    // 失败详情按 IP 列出，便于区分个别 IP 的问题
+    let failures: Vec<&MatrixCell> = cells.iter().filter(|c| !c.works).collect();
+    if !failures.is_empty() {
+        report.push_str("\n失败组合:\n");
+        for cell in failures {
+            let case = &cell.case;
+            report.push_str(&format!("  {} [{}] SNI {} Host {} {}:{} - {}\n",
+                                     case.resolve_domain, case.ip, case.sni, case.host, case.path, case.port,
+                                     cell.error_message.clone().unwrap_or_else(|| {
+                                         format!("HTTP {}", cell.response_status.unwrap_or(0))
+                                     })));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h3_test_server::{self, TestServerOptions};
+    use crate::quic_qlog::QlogDir;
+
+    fn task(resolve_domains: &[&str], sni_hosts: &[&str], host_headers: &[&str]) -> MatrixTask {
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+        MatrixTask {
+            resolve_domains: list(resolve_domains),
+            sni_hosts: list(sni_hosts),
+            host_headers: list(host_headers),
+            paths: default_paths(),
+            ports: default_ports(),
+            doh_url: None,
+            prefer_ipv6: None,
+            direct_ips: None,
+            max_ips_per_domain: None,
+            trust: None,
+        }
+    }
+
+    #[test]
+    fn test_expand_cartesian_product() {
+        let mut task: MatrixTask = serde_json::from_str(
+            r#"{"resolve_domains": ["a.example", "b.example"], "sni_hosts": ["s1", "s2"],
+                "host_headers": ["h1", "h2", "h3"], "paths": ["/", "/cdn-cgi/trace"]}"#,
+        )
+        .unwrap();
+        let ips = BTreeMap::from([
+            ("a.example".to_string(), vec!["1.1.1.1".parse().unwrap(), "2606:4700::1".parse().unwrap()]),
+            ("b.example".to_string(), vec!["1.0.0.1".parse().unwrap()]),
+        ]);
+
+        let cases = task.expand(&ips);
+        assert_eq!(cases.len(), 3 * 2 * 3 * 2);
+        assert!(cases.iter().all(|c| c.port == 443));
+        assert_eq!(cases[0].sni, "s1");
+        assert_eq!(cases[0].host, "h1");
+        assert_eq!(cases[1].path, "/cdn-cgi/trace");
+
+        task.prefer_ipv6 = Some(false);
+        assert_eq!(task.expand(&ips).len(), 2 * 2 * 3 * 2);
+        task.max_ips_per_domain = Some(1);
+        task.resolve_domains.push("missing.example".to_string());
+        assert_eq!(task.expand(&ips).len(), 2 * 2 * 3 * 2);
+    }
+
+    #[tokio::test]
+    async fn test_matrix_pivot_against_local_server() {
+        let server = h3_test_server::spawn(TestServerOptions {
+            served_authority: Some("front.example".to_string()),
+            ..Default::default()
+        })
+        .await;
+        let qlog_dir = std::env::temp_dir().join(format!("h3-test-matrix-qlog-{}", server.addr.port()));
+        let tester = H3Tester::with_root_store(server.root_store())
+            .unwrap()
+            .with_qlog(Some(QlogDir::new(&qlog_dir, false).unwrap()));
+
+        let mut task = task(&["local.example"], &["localhost", "wrong-sni.example"], &["front.example", "localhost"]);
+        task.ports = vec![server.addr.port()];
+        let ips = BTreeMap::from([("local.example".to_string(), vec![server.addr.ip()])]);
+
+        let cells = run_matrix(&tester, task.expand(&ips), 5, &QuicTransportOptions::default(), 4).await;
+        let works: Vec<(&str, &str, bool)> =
+            cells.iter().map(|c| (c.case.sni.as_str(), c.case.host.as_str(), c.works)).collect();
+
+        assert_eq!(works, vec![
+            ("localhost", "front.example", true),
+            ("localhost", "localhost", false),
+            ("wrong-sni.example", "front.example", false),
+            ("wrong-sni.example", "localhost", false),
+        ]);
+        assert_eq!(cells[1].response_status, Some(421));
+        assert!(cells[2].error_message.is_some());
+
+        // 每个组合按自身结果处理 qlog：只有失败的组合保留 trace
+        let kept: Vec<usize> = cells.iter().map(|c| c.qlog_files.len()).collect();
+        assert_eq!(kept, vec![0, 1, 1, 1]);
+        assert!(cells[1..].iter().all(|c| c.qlog_files[0].exists()));
+        std::fs::remove_dir_all(&qlog_dir).unwrap();
+
+        let report = generate_matrix_report(&cells);
+        assert!(report.contains("可用组合: 1/4"));
+        assert!(report.contains("✅ 1/1 (200)"));
+        assert!(report.contains("❌ 0/1 (421)"));
+    }
+}
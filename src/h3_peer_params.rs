@@ -0,0 +1,634 @@
+// 对端 HTTP/3 SETTINGS 与 QUIC 传输参数采集，以及写入 qlog 的流事件
+use bytes::{Buf, Bytes, BytesMut};
+use h3::proto::frame::{SettingId, Settings};
+use h3::proto::varint::VarInt;
+use h3::quic::{self, ConnectionErrorIncoming, StreamErrorIncoming, StreamId};
+use h3::stream::{UniStreamHeader, WriteBuf};
+use h3_quinn::quinn;
+use quinn::crypto::{self, ExportKeyingMaterialError, HeaderKey, KeyPair, Keys, PacketKey};
+use quinn::{ConnectError, ConnectionId, Side};
+use quinn_proto::transport_parameters::TransportParameters;
+use quinn_proto::TransportError;
+use qlog::events::quic::{StreamSide, StreamState};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::quic_qlog::QlogTrace;
+
+// --- 1. 对端 SETTINGS (RFC 9114 / RFC 9204) ---
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct H3PeerSettings {
+    pub qpack_max_table_capacity: Option<u64>, // 0x01
+    pub max_field_section_size: Option<u64>,   // 0x06
+    pub qpack_blocked_streams: Option<u64>,    // 0x07
+    pub enable_connect_protocol: Option<u64>,  // 0x08 (RFC 8441 / RFC 9220)
+    pub h3_datagram: Option<u64>,              // 0x33 (RFC 9297)
+    pub grease_settings: usize,                // 保留的 GREASE 标识符 (0x1f * N + 0x21) 个数
+    pub unknown: BTreeMap<String, u64>,        // 其他未识别的设置，键为十六进制标识符
+}
+
+impl H3PeerSettings {
+    // 解析 SETTINGS 帧负载: (标识符, 值) varint 对
+    pub fn from_payload(mut payload: &[u8]) -> Self {
+        let mut settings = Self::default();
+
+        while payload.has_remaining() {
+            let (Ok(id), Ok(value)) = (VarInt::decode(&mut payload), VarInt::decode(&mut payload)) else {
+                break;
+            };
+            let (id, value) = (id.into_inner(), value.into_inner());
+
+            match id {
+                0x01 => settings.qpack_max_table_capacity = Some(value),
+                0x06 => settings.max_field_section_size = Some(value),
+                0x07 => settings.qpack_blocked_streams = Some(value),
+                0x08 => settings.enable_connect_protocol = Some(value),
+                0x33 => settings.h3_datagram = Some(value),
+                id if id >= 0x21 && (id - 0x21) % 0x1f == 0 => settings.grease_settings += 1,
+                id => {
+                    settings.unknown.insert(format!("{:#x}", id), value);
+                }
+            }
+        }
+
+        settings
+    }
+}
+
+// 对端 SETTINGS 采集结果，在连接的各个流之间共享
+#[derive(Debug, Clone, Default)]
+pub struct SettingsCapture(Arc<CapturedSettings>);
+
+#[derive(Debug, Default)]
+struct CapturedSettings {
+    settings: Mutex<Option<H3PeerSettings>>,
+    received: Notify,
+}
+
+impl SettingsCapture {
+    pub fn get(&self) -> Option<H3PeerSettings> {
+        self.0.settings.lock().unwrap().clone()
+    }
+
+    // 等待对端控制流上的 SETTINGS，超时返回 None
+    pub async fn wait(&self, wait: Duration) -> Option<H3PeerSettings> {
+        // 先注册再检查，避免错过检查与等待之间到达的 SETTINGS
+        let received = self.0.received.notified();
+        if let Some(settings) = self.get() {
+            return Some(settings);
+        }
+        tokio::time::timeout(wait, received).await.ok()?;
+        self.get()
+    }
+
+    fn set(&self, settings: H3PeerSettings) {
+        *self.0.settings.lock().unwrap() = Some(settings);
+        self.0.received.notify_waiters();
+    }
+}
+
+enum ControlStreamPrefix {
+    Incomplete,
+    NotControl,
+    Settings(H3PeerSettings),
+}
+
+// 单向流开头: 流类型 (0x00 = 控制流)，随后第一个帧必须是 SETTINGS (0x04)
+fn parse_control_stream_prefix(mut buf: &[u8]) -> ControlStreamPrefix {
+    let Ok(stream_type) = VarInt::decode(&mut buf) else {
+        return ControlStreamPrefix::Incomplete;
+    };
+    if stream_type.into_inner() != 0x00 {
+        return ControlStreamPrefix::NotControl;
+    }
+
+    let (Ok(frame_type), Ok(length)) = (VarInt::decode(&mut buf), VarInt::decode(&mut buf)) else {
+        return ControlStreamPrefix::Incomplete;
+    };
+    if frame_type.into_inner() != 0x04 {
+        return ControlStreamPrefix::NotControl;
+    }
+
+    match buf.get(..length.into_inner() as usize) {
+        Some(payload) => ControlStreamPrefix::Settings(H3PeerSettings::from_payload(payload)),
+        None => ControlStreamPrefix::Incomplete,
+    }
+}
+
+// --- 2. 记录控制流 SETTINGS 的 QUIC 连接包装 ---
+// 设置 qlog 时同时记录本端打开与对端发起的流，以及对端单向流的结束
+pub struct CapturingConnection<C> {
+    inner: C,
+    capture: SettingsCapture,
+    qlog: Option<QlogTrace>,
+    enable_webtransport: bool, // 在本端 SETTINGS 中声明 WebTransport
+}
+
+impl<C> CapturingConnection<C> {
+    pub fn new(inner: C, capture: SettingsCapture, qlog: Option<QlogTrace>) -> Self {
+        Self { inner, capture, qlog, enable_webtransport: false }
+    }
+
+    // h3 客户端没有 WebTransport 开关，由控制流包装改写本端 SETTINGS
+    pub fn with_webtransport(self, enable_webtransport: bool) -> Self {
+        Self { enable_webtransport, ..self }
+    }
+}
+
+// 记录新打开的流；请求流通过 opener 打开，h3 的控制流与 QPACK 流通过连接本身打开
+fn poll_open_logged<S>(
+    qlog: &Option<QlogTrace>,
+    poll: Poll<Result<S, StreamErrorIncoming>>,
+    id: impl FnOnce(&S) -> StreamId,
+    state: StreamState,
+) -> Poll<Result<S, StreamErrorIncoming>> {
+    if let (Some(qlog), Poll::Ready(Ok(stream))) = (qlog, &poll) {
+        qlog.stream_state(id(stream), state, Some(StreamSide::Sending));
+    }
+    poll
+}
+
+impl<C, B> quic::OpenStreams<B> for CapturingConnection<C>
+where
+    C: quic::OpenStreams<B>,
+    B: Buf,
+{
+    type BidiStream = C::BidiStream;
+    type SendStream = LocalSettingsStream<C::SendStream>;
+
+    fn poll_open_bidi(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::BidiStream, StreamErrorIncoming>> {
+        poll_open_logged(&self.qlog, self.inner.poll_open_bidi(cx), |s| quic::SendStream::send_id(s), StreamState::Open)
+    }
+
+    // h3 的控制流、QPACK 流通过连接本身打开
+    fn poll_open_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::SendStream, StreamErrorIncoming>> {
+        let stream = ready!(poll_open_logged(
+            &self.qlog,
+            self.inner.poll_open_send(cx),
+            |s| quic::SendStream::send_id(s),
+            StreamState::Send
+        ))?;
+        Poll::Ready(Ok(LocalSettingsStream { inner: stream, enable_webtransport: self.enable_webtransport }))
+    }
+
+    fn close(&mut self, code: h3::error::Code, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+}
+
+impl<C, B> quic::Connection<B> for CapturingConnection<C>
+where
+    C: quic::Connection<B>,
+    C::OpenStreams: quic::OpenStreams<B, SendStream = C::SendStream>,
+    C::RecvStream: quic::RecvStream<Buf = Bytes>,
+    B: Buf,
+{
+    type RecvStream = CapturingRecvStream<C::RecvStream>;
+    type OpenStreams = CapturingOpenStreams<C::OpenStreams>;
+
+    fn poll_accept_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::RecvStream, ConnectionErrorIncoming>> {
+        let stream = ready!(self.inner.poll_accept_recv(cx))?;
+        if let Some(qlog) = &self.qlog {
+            qlog.stream_state(quic::RecvStream::recv_id(&stream), StreamState::Receive, Some(StreamSide::Receiving));
+        }
+        Poll::Ready(Ok(CapturingRecvStream {
+            inner: stream,
+            capture: self.capture.clone(),
+            prefix: Some(BytesMut::new()),
+            qlog: self.qlog.clone(),
+        }))
+    }
+
+    fn poll_accept_bidi(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::BidiStream, ConnectionErrorIncoming>> {
+        let stream = ready!(self.inner.poll_accept_bidi(cx))?;
+        if let Some(qlog) = &self.qlog {
+            qlog.stream_state(quic::RecvStream::recv_id(&stream), StreamState::Open, None);
+        }
+        Poll::Ready(Ok(stream))
+    }
+
+    fn opener(&self) -> Self::OpenStreams {
+        CapturingOpenStreams { inner: self.inner.opener(), qlog: self.qlog.clone() }
+    }
+}
+
+// 请求流的打开器，与连接共享同一 qlog
+pub struct CapturingOpenStreams<O> {
+    inner: O,
+    qlog: Option<QlogTrace>,
+}
+
+impl<O: Clone> Clone for CapturingOpenStreams<O> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), qlog: self.qlog.clone() }
+    }
+}
+
+impl<O, B> quic::OpenStreams<B> for CapturingOpenStreams<O>
+where
+    O: quic::OpenStreams<B>,
+    B: Buf,
+{
+    type BidiStream = O::BidiStream;
+    type SendStream = LocalSettingsStream<O::SendStream>;
+
+    fn poll_open_bidi(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::BidiStream, StreamErrorIncoming>> {
+        poll_open_logged(&self.qlog, self.inner.poll_open_bidi(cx), |s| quic::SendStream::send_id(s), StreamState::Open)
+    }
+
+    fn poll_open_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<Self::SendStream, StreamErrorIncoming>> {
+        let stream = ready!(poll_open_logged(
+            &self.qlog,
+            self.inner.poll_open_send(cx),
+            |s| quic::SendStream::send_id(s),
+            StreamState::Send
+        ))?;
+        Poll::Ready(Ok(LocalSettingsStream { inner: stream, enable_webtransport: false }))
+    }
+
+    fn close(&mut self, code: h3::error::Code, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+}
+
+// 本端单向流：启用 WebTransport 时改写控制流上的第一个 SETTINGS 帧，
+// 声明 SETTINGS_ENABLE_WEBTRANSPORT 与 WEBTRANSPORT_MAX_SESSIONS (draft-ietf-webtrans-http3 §3.1)
+pub struct LocalSettingsStream<S> {
+    inner: S,
+    enable_webtransport: bool, // 只检查流上的第一次写入
+}
+
+impl<S, B> quic::SendStream<B> for LocalSettingsStream<S>
+where
+    S: quic::SendStream<B>,
+    B: Buf,
+{
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), StreamErrorIncoming>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn send_data<T: Into<WriteBuf<B>>>(&mut self, data: T) -> Result<(), StreamErrorIncoming> {
+        let data = data.into();
+        if std::mem::take(&mut self.enable_webtransport) {
+            if let Some(settings) = webtransport_settings(data.chunk()) {
+                return self.inner.send_data(UniStreamHeader::Control(settings));
+            }
+        }
+        self.inner.send_data(data)
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), StreamErrorIncoming>> {
+        self.inner.poll_finish(cx)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        self.inner.reset(reset_code)
+    }
+
+    fn send_id(&self) -> StreamId {
+        self.inner.send_id()
+    }
+}
+
+// 控制流开头 (流类型 + SETTINGS 帧) 改为声明 WebTransport 的 SETTINGS，其余设置保持不变
+fn webtransport_settings(mut header: &[u8]) -> Option<Settings> {
+    let (Ok(stream_type), Ok(frame_type), Ok(length)) =
+        (VarInt::decode(&mut header), VarInt::decode(&mut header), VarInt::decode(&mut header))
+    else {
+        return None;
+    };
+    if stream_type.into_inner() != 0x00 || frame_type.into_inner() != 0x04 {
+        return None;
+    }
+
+    let mut payload = header.get(..length.into_inner() as usize)?;
+    let mut settings = Settings::default();
+    settings.insert(SettingId::ENABLE_WEBTRANSPORT, 1).ok()?;
+    settings.insert(SettingId::WEBTRANSPORT_MAX_SESSIONS, 1).ok()?;
+    while payload.has_remaining() {
+        let (Ok(id), Ok(value)) = (VarInt::decode(&mut payload), VarInt::decode(&mut payload)) else {
+            return None;
+        };
+        let id = SettingId(id.into_inner());
+        if settings.get(id).is_none() {
+            settings.insert(id, value.into_inner()).ok()?;
+        }
+    }
+    Some(settings)
+}
+
+pub struct CapturingRecvStream<R> {
+    inner: R,
+    capture: SettingsCapture,
+    prefix: Option<BytesMut>, // 已读取的流开头；确定流类型或拿到 SETTINGS 后置为 None
+    qlog: Option<QlogTrace>,
+}
+
+impl<R> quic::RecvStream for CapturingRecvStream<R>
+where
+    R: quic::RecvStream<Buf = Bytes>,
+{
+    type Buf = Bytes;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, StreamErrorIncoming>> {
+        let result = ready!(self.inner.poll_data(cx));
+
+        if let (Some(qlog), Ok(None)) = (&self.qlog, &result) {
+            qlog.stream_state(self.inner.recv_id(), StreamState::DataRead, Some(StreamSide::Receiving));
+        }
+        if let (Some(prefix), Ok(Some(data))) = (self.prefix.as_mut(), &result) {
+            prefix.extend_from_slice(data);
+            match parse_control_stream_prefix(prefix) {
+                ControlStreamPrefix::Incomplete => {}
+                ControlStreamPrefix::NotControl => self.prefix = None,
+                ControlStreamPrefix::Settings(settings) => {
+                    self.capture.set(settings);
+                    self.prefix = None;
+                }
+            }
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.inner.stop_sending(error_code)
+    }
+
+    fn recv_id(&self) -> StreamId {
+        self.inner.recv_id()
+    }
+}
+
+// --- 3. 对端 QUIC 传输参数 (RFC 9000 §18.2) ---
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QuicTransportParams {
+    pub max_idle_timeout_ms: u64,
+    pub max_udp_payload_size: u64,
+    pub initial_max_data: u64,
+    pub initial_max_stream_data_bidi_local: u64,
+    pub initial_max_stream_data_bidi_remote: u64,
+    pub initial_max_stream_data_uni: u64,
+    pub initial_max_streams_bidi: u64,
+    pub initial_max_streams_uni: u64,
+    pub ack_delay_exponent: u64,
+    pub max_ack_delay_ms: u64,
+    pub active_connection_id_limit: u64,
+    pub disable_active_migration: bool,
+    pub preferred_address: bool,
+    pub max_datagram_frame_size: Option<u64>, // RFC 9221
+    pub grease_quic_bit: bool,                // RFC 9287
+}
+
+// 对端未发送的参数取 RFC 9000 规定的默认值
+impl Default for QuicTransportParams {
+    fn default() -> Self {
+        Self {
+            max_idle_timeout_ms: 0,
+            max_udp_payload_size: 65527,
+            initial_max_data: 0,
+            initial_max_stream_data_bidi_local: 0,
+            initial_max_stream_data_bidi_remote: 0,
+            initial_max_stream_data_uni: 0,
+            initial_max_streams_bidi: 0,
+            initial_max_streams_uni: 0,
+            ack_delay_exponent: 3,
+            max_ack_delay_ms: 25,
+            active_connection_id_limit: 2,
+            disable_active_migration: false,
+            preferred_address: false,
+            max_datagram_frame_size: None,
+            grease_quic_bit: false,
+        }
+    }
+}
+
+impl QuicTransportParams {
+    // 解析 quic_transport_parameters 扩展的 (标识符, 长度, 值) 序列
+    pub fn from_wire(mut raw: &[u8]) -> Self {
+        let mut params = Self::default();
+
+        while raw.has_remaining() {
+            let (Ok(id), Ok(len)) = (VarInt::decode(&mut raw), VarInt::decode(&mut raw)) else {
+                break;
+            };
+            let Some(value) = raw.get(..len.into_inner() as usize) else {
+                break;
+            };
+            raw.advance(value.len());
+            let int = VarInt::decode(&mut &value[..]).map(|v| v.into_inner()).unwrap_or(0);
+
+            match id.into_inner() {
+                0x01 => params.max_idle_timeout_ms = int,
+                0x03 => params.max_udp_payload_size = int,
+                0x04 => params.initial_max_data = int,
+                0x05 => params.initial_max_stream_data_bidi_local = int,
+                0x06 => params.initial_max_stream_data_bidi_remote = int,
+                0x07 => params.initial_max_stream_data_uni = int,
+                0x08 => params.initial_max_streams_bidi = int,
+                0x09 => params.initial_max_streams_uni = int,
+                0x0a => params.ack_delay_exponent = int,
+                0x0b => params.max_ack_delay_ms = int,
+                0x0c => params.disable_active_migration = true,
+                0x0d => params.preferred_address = true,
+                0x0e => params.active_connection_id_limit = int,
+                0x20 => params.max_datagram_frame_size = Some(int),
+                0x2ab2 => params.grease_quic_bit = true,
+                _ => {}
+            }
+        }
+
+        params
+    }
+}
+
+// 对端传输参数采集结果，握手完成后可用；同时记录本端 ClientHello 的大小
+#[derive(Debug, Clone, Default)]
+pub struct TransportParamsCapture {
+    params: Arc<Mutex<Option<QuicTransportParams>>>,
+    client_hello_len: Arc<Mutex<Option<usize>>>,
+}
+
+impl TransportParamsCapture {
+    pub fn get(&self) -> Option<QuicTransportParams> {
+        self.params.lock().unwrap().clone()
+    }
+
+    // Initial 包 CRYPTO 帧中 ClientHello 的字节数，后量子密钥交换会使其超过单个 Initial 包
+    pub fn client_hello_len(&self) -> Option<usize> {
+        *self.client_hello_len.lock().unwrap()
+    }
+}
+
+// --- 4. 记录对端传输参数的 TLS 会话包装 ---
+pub struct CapturingCryptoConfig {
+    inner: Arc<dyn crypto::ClientConfig>,
+    capture: TransportParamsCapture,
+}
+
+impl CapturingCryptoConfig {
+    pub fn new(inner: Arc<dyn crypto::ClientConfig>, capture: TransportParamsCapture) -> Self {
+        Self { inner, capture }
+    }
+}
+
+impl crypto::ClientConfig for CapturingCryptoConfig {
+    fn start_session(
+        self: Arc<Self>,
+        version: u32,
+        server_name: &str,
+        params: &TransportParameters,
+    ) -> Result<Box<dyn crypto::Session>, ConnectError> {
+        let inner = self.inner.clone().start_session(version, server_name, params)?;
+        Ok(Box::new(CapturingSession {
+            inner,
+            capture: self.capture.clone(),
+        }))
+    }
+}
+
+struct CapturingSession {
+    inner: Box<dyn crypto::Session>,
+    capture: TransportParamsCapture,
+}
+
+impl crypto::Session for CapturingSession {
+    fn initial_keys(&self, dst_cid: &ConnectionId, side: Side) -> Keys {
+        self.inner.initial_keys(dst_cid, side)
+    }
+
+    fn handshake_data(&self) -> Option<Box<dyn Any>> {
+        self.inner.handshake_data()
+    }
+
+    fn peer_identity(&self) -> Option<Box<dyn Any>> {
+        self.inner.peer_identity()
+    }
+
+    fn early_crypto(&self) -> Option<(Box<dyn HeaderKey>, Box<dyn PacketKey>)> {
+        self.inner.early_crypto()
+    }
+
+    fn early_data_accepted(&self) -> Option<bool> {
+        self.inner.early_data_accepted()
+    }
+
+    fn is_handshaking(&self) -> bool {
+        self.inner.is_handshaking()
+    }
+
+    fn read_handshake(&mut self, buf: &[u8]) -> Result<bool, TransportError> {
+        self.inner.read_handshake(buf)
+    }
+
+    fn transport_parameters(&self) -> Result<Option<TransportParameters>, TransportError> {
+        let params = self.inner.transport_parameters()?;
+        if let Some(params) = &params {
+            // quinn 不公开各字段，重新编码后按线格式解析
+            let mut raw = Vec::new();
+            params.write(&mut raw);
+            *self.capture.params.lock().unwrap() = Some(QuicTransportParams::from_wire(&raw));
+        }
+        Ok(params)
+    }
+
+    fn write_handshake(&mut self, buf: &mut Vec<u8>) -> Option<Keys> {
+        let start = buf.len();
+        let keys = self.inner.write_handshake(buf);
+        // 客户端首次写出的握手数据即 ClientHello
+        let mut client_hello_len = self.capture.client_hello_len.lock().unwrap();
+        if client_hello_len.is_none() && buf.len() > start {
+            *client_hello_len = Some(buf.len() - start);
+        }
+        keys
+    }
+
+    fn next_1rtt_keys(&mut self) -> Option<KeyPair<Box<dyn PacketKey>>> {
+        self.inner.next_1rtt_keys()
+    }
+
+    fn is_valid_retry(&self, orig_dst_cid: &ConnectionId, header: &[u8], payload: &[u8]) -> bool {
+        self.inner.is_valid_retry(orig_dst_cid, header, payload)
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ExportKeyingMaterialError> {
+        self.inner.export_keying_material(output, label, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    fn put_varint(buf: &mut BytesMut, value: u64) {
+        VarInt::from_u64(value).unwrap().encode(buf);
+    }
+
+    #[test]
+    fn test_parse_settings_frame_on_control_stream() {
+        let mut payload = BytesMut::new();
+        for (id, value) in [(0x01, 4096), (0x06, 16384), (0x07, 100), (0x33, 1), (0x1f * 3 + 0x21, 7), (0x2b60_3742, 1)] {
+            put_varint(&mut payload, id);
+            put_varint(&mut payload, value);
+        }
+
+        let mut stream = BytesMut::new();
+        put_varint(&mut stream, 0x00);
+        put_varint(&mut stream, 0x04);
+        put_varint(&mut stream, payload.len() as u64);
+        stream.put_slice(&payload);
+
+        // 帧未完整到达时继续等待
+        assert!(matches!(
+            parse_control_stream_prefix(&stream[..stream.len() - 1]),
+            ControlStreamPrefix::Incomplete
+        ));
+
+        let ControlStreamPrefix::Settings(settings) = parse_control_stream_prefix(&stream) else {
+            panic!("未解析出 SETTINGS");
+        };
+        assert_eq!(settings.qpack_max_table_capacity, Some(4096));
+        assert_eq!(settings.max_field_section_size, Some(16384));
+        assert_eq!(settings.qpack_blocked_streams, Some(100));
+        assert_eq!(settings.h3_datagram, Some(1));
+        assert_eq!(settings.enable_connect_protocol, None);
+        assert_eq!(settings.grease_settings, 1);
+        assert_eq!(settings.unknown.get("0x2b603742"), Some(&1));
+
+        // QPACK 编码器流 (0x02) 不是控制流
+        assert!(matches!(parse_control_stream_prefix(&[0x02, 0x3f]), ControlStreamPrefix::NotControl));
+    }
+
+    #[test]
+    fn test_transport_params_defaults_and_overrides() {
+        let mut raw = BytesMut::new();
+        for (id, value) in [(0x01, 30_000), (0x04, 1_048_576), (0x08, 100), (0x0e, 8)] {
+            put_varint(&mut raw, id);
+            put_varint(&mut raw, VarInt::from_u64(value).unwrap().size() as u64);
+            put_varint(&mut raw, value);
+        }
+        put_varint(&mut raw, 0x0c);
+        put_varint(&mut raw, 0);
+
+        let params = QuicTransportParams::from_wire(&raw);
+        assert_eq!(params.max_idle_timeout_ms, 30_000);
+        assert_eq!(params.initial_max_data, 1_048_576);
+        assert_eq!(params.initial_max_streams_bidi, 100);
+        assert_eq!(params.active_connection_id_limit, 8);
+        assert!(params.disable_active_migration);
+        assert_eq!(params.ack_delay_exponent, 3);
+        assert_eq!(params.max_udp_payload_size, 65527);
+        assert_eq!(params.max_datagram_frame_size, None);
+    }
+}
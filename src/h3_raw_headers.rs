@@ -0,0 +1,245 @@
+// 原始 HTTP/3 请求头 - h3 的 ext::Protocol 只接受 webtransport / connect-udp，扩展 CONNECT
+// 因此直接在 QUIC 双向流上发送 HEADERS 帧，响应只解析 :status
+use anyhow::{bail, Context, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use h3::proto::varint::VarInt;
+use h3_quinn::quinn;
+
+const FRAME_DATA: u64 = 0x00;
+const FRAME_HEADERS: u64 = 0x01;
+
+// 响应 HEADERS 帧之前允许读取的最大帧长度
+const MAX_RESPONSE_FRAME: usize = 64 * 1024;
+
+// QPACK 静态表中的 :status 条目 (RFC 9204 附录 A)
+const STATIC_STATUS: [(u64, u16); 14] = [
+    (24, 103), (25, 200), (26, 304), (27, 404), (28, 503), (63, 100), (64, 204),
+    (65, 206), (66, 302), (67, 400), (68, 403), (69, 421), (70, 425), (71, 500),
+];
+
+// :status 只包含数字，只需要 RFC 7541 附录 B 中 '0'..'9' 的 Huffman 码字 (码字, 位数)
+const HUFFMAN_DIGITS: [(u32, u32); 10] = [
+    (0x00, 5), (0x01, 5), (0x02, 5), (0x19, 6), (0x1a, 6),
+    (0x1b, 6), (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6),
+];
+
+// --- 1. 请求 ---
+// 字段区不使用动态表 (Required Insert Count = 0, Delta Base = 0)，
+// 每个字段编码为不压缩的字面量名称与值 (RFC 9204 §4.5.6)
+pub fn encode_headers_frame(fields: &[(&str, &str)]) -> Bytes {
+    let mut block = BytesMut::new();
+    block.put_slice(&[0x00, 0x00]);
+    for (name, value) in fields {
+        put_prefixed_int(&mut block, 0x20, 3, name.len() as u64);
+        block.put_slice(name.as_bytes());
+        put_prefixed_int(&mut block, 0x00, 7, value.len() as u64);
+        block.put_slice(value.as_bytes());
+    }
+
+    let mut frame = BytesMut::with_capacity(2 * VarInt::MAX_SIZE + block.len());
+    for value in [FRAME_HEADERS, block.len() as u64] {
+        VarInt::from_u64(value).expect("帧长度超出 varint 范围").encode(&mut frame);
+    }
+    frame.put_slice(&block);
+    frame.freeze()
+}
+
+// --- 2. 响应 ---
+// 读取响应流上的第一个 HEADERS 帧并返回 :status，其间跳过 GREASE 等未知帧
+pub async fn read_response_status(recv: &mut quinn::RecvStream) -> Result<u16> {
+    let mut buf = BytesMut::new();
+    loop {
+        let mut view = &buf[..];
+        if let (Ok(frame_type), Ok(length)) = (VarInt::decode(&mut view), VarInt::decode(&mut view)) {
+            let (frame_type, length) = (frame_type.into_inner(), length.into_inner() as usize);
+            if length > MAX_RESPONSE_FRAME {
+                bail!("响应帧长度 {} 超过 {} 字节", length, MAX_RESPONSE_FRAME);
+            }
+            if let Some(payload) = view.get(..length) {
+                match frame_type {
+                    FRAME_HEADERS => return decode_status(payload),
+                    FRAME_DATA => bail!("响应在 HEADERS 帧之前发送了 DATA 帧"),
+                    _ => {
+                        let consumed = buf.len() - view.len() + length;
+                        buf.advance(consumed);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        match recv.read_chunk(MAX_RESPONSE_FRAME, true).await.context("读取响应流失败")? {
+            Some(chunk) => buf.extend_from_slice(&chunk.bytes),
+            None => bail!("响应流在 HEADERS 帧之前结束"),
+        }
+    }
+}
+
+// 伪头部位于所有字段之前，响应的第一个字段行即为 :status
+fn decode_status(mut block: &[u8]) -> Result<u16> {
+    let required_insert_count = read_prefixed_int(&mut block, 8)?;
+    if required_insert_count != 0 {
+        bail!("响应头引用了 QPACK 动态表");
+    }
+    read_prefixed_int(&mut block, 7)?; // Delta Base
+
+    let static_status = |index: u64| STATIC_STATUS.iter().find(|(i, _)| *i == index).map(|(_, status)| *status);
+    let first = *block.first().context("响应头为空")?;
+    let value = if first & 0x80 != 0 {
+        // 索引字段行: 1Txxxxxx
+        if first & 0x40 == 0 {
+            bail!("响应头引用了 QPACK 动态表");
+        }
+        let index = read_prefixed_int(&mut block, 6)?;
+        return static_status(index).with_context(|| format!("响应的第一个字段不是 :status (静态表索引 {})", index));
+    } else if first & 0xc0 == 0x40 {
+        // 名称引用的字面量字段行: 01NTxxxx
+        if first & 0x10 == 0 {
+            bail!("响应头引用了 QPACK 动态表");
+        }
+        let index = read_prefixed_int(&mut block, 4)?;
+        if static_status(index).is_none() {
+            bail!("响应的第一个字段不是 :status (静态表索引 {})", index);
+        }
+        read_string(&mut block, 7)?
+    } else if first & 0xe0 == 0x20 {
+        // 字面量名称的字段行: 001NHxxx
+        let name = read_string(&mut block, 3)?;
+        if name != b":status" {
+            bail!("响应的第一个字段不是 :status: {}", String::from_utf8_lossy(&name));
+        }
+        read_string(&mut block, 7)?
+    } else {
+        bail!("响应头引用了 QPACK 动态表");
+    };
+
+    std::str::from_utf8(&value)
+        .ok()
+        .and_then(|status| status.parse().ok())
+        .with_context(|| format!("无效的 :status: {}", String::from_utf8_lossy(&value)))
+}
+
+// --- 3. QPACK 基本编码 ---
+// 前缀整数 (RFC 7541 §5.1)
+fn put_prefixed_int(buf: &mut BytesMut, flags: u8, prefix_bits: u32, value: u64) {
+    let max = (1u64 << prefix_bits) - 1;
+    if value < max {
+        buf.put_u8(flags | value as u8);
+        return;
+    }
+    buf.put_u8(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        buf.put_u8(rest as u8 | 0x80);
+        rest >>= 7;
+    }
+    buf.put_u8(rest as u8);
+}
+
+fn read_prefixed_int(buf: &mut &[u8], prefix_bits: u32) -> Result<u64> {
+    let max = (1u64 << prefix_bits) - 1;
+    let mut next = || -> Result<u8> {
+        let byte = *buf.first().context("QPACK 字段区不完整")?;
+        buf.advance(1);
+        Ok(byte)
+    };
+
+    let value = u64::from(next()?) & max;
+    if value < max {
+        return Ok(value);
+    }
+    let mut extra = 0u64;
+    for shift in (0..63).step_by(7) {
+        let byte = next()?;
+        extra |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(max + extra);
+        }
+    }
+    bail!("QPACK 整数过长")
+}
+
+// 字符串字面量: H 标志位于长度前缀之前的一位
+fn read_string(buf: &mut &[u8], prefix_bits: u32) -> Result<Vec<u8>> {
+    let huffman = buf.first().is_some_and(|byte| byte & (1 << prefix_bits) != 0);
+    let length = read_prefixed_int(buf, prefix_bits)? as usize;
+    let raw = buf.get(..length).context("QPACK 字段区不完整")?;
+    buf.advance(length);
+    if huffman {
+        decode_huffman_digits(raw)
+    } else {
+        Ok(raw.to_vec())
+    }
+}
+
+// 末尾允许最多 7 位全 1 的填充 (EOS 前缀)
+fn decode_huffman_digits(raw: &[u8]) -> Result<Vec<u8>> {
+    let mut digits = Vec::new();
+    let (mut code, mut bits) = (0u32, 0u32);
+    for byte in raw {
+        for shift in (0..8).rev() {
+            code = (code << 1) | u32::from((byte >> shift) & 1);
+            bits += 1;
+            if let Some(digit) = HUFFMAN_DIGITS.iter().position(|&entry| entry == (code, bits)) {
+                digits.push(b'0' + digit as u8);
+                (code, bits) = (0, 0);
+            } else if bits >= 8 || (bits >= 6 && code != (1 << bits) - 1) {
+                bail!("Huffman 编码的 :status 包含非数字字符");
+            }
+        }
+    }
+    if code != (1 << bits) - 1 {
+        bail!("Huffman 编码的 :status 填充无效");
+    }
+    Ok(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_headers_frame() {
+        let frame = encode_headers_frame(&[(":method", "CONNECT"), (":authority", "example.com:8443")]);
+
+        let mut buf = &frame[..];
+        assert_eq!(VarInt::decode(&mut buf).unwrap().into_inner(), FRAME_HEADERS);
+        assert_eq!(VarInt::decode(&mut buf).unwrap().into_inner() as usize, buf.len());
+        assert_eq!(&buf[..2], &[0x00, 0x00]);
+        // ":method" 长度 7 恰好等于 3 位前缀的最大值，需要一个续接字节
+        assert_eq!(&buf[2..4], &[0x27, 0x00]);
+        assert_eq!(&buf[4..11], b":method");
+        assert_eq!(buf[11], 7);
+    }
+
+    #[test]
+    fn test_decode_status() {
+        // 静态表索引 25 = :status 200
+        assert_eq!(decode_status(&[0x00, 0x00, 0xd9]).unwrap(), 200);
+        // 静态表名称引用 (索引 24) + Huffman 编码的 "101"
+        assert_eq!(decode_status(&[0x00, 0x00, 0x5f, 0x09, 0x82, 0x08, 0x03]).unwrap(), 101);
+        // 名称引用 + 不压缩的 "405"
+        assert_eq!(decode_status(&[0x00, 0x00, 0x5f, 0x09, 0x03, b'4', b'0', b'5']).unwrap(), 405);
+        // 字面量名称
+        let mut block = BytesMut::from(&[0x00u8, 0x00][..]);
+        put_prefixed_int(&mut block, 0x20, 3, 7);
+        block.put_slice(b":status");
+        put_prefixed_int(&mut block, 0x00, 7, 3);
+        block.put_slice(b"426");
+        assert_eq!(decode_status(&block).unwrap(), 426);
+
+        // 动态表引用与非 :status 的首字段
+        assert!(decode_status(&[0x02, 0x00, 0x80]).is_err());
+        assert!(decode_status(&[0x00, 0x00, 0xc1]).is_err());
+    }
+
+    #[test]
+    fn test_prefixed_int_round_trip() {
+        for value in [0, 6, 7, 127, 128, 1337, u64::from(u32::MAX)] {
+            let mut buf = BytesMut::new();
+            put_prefixed_int(&mut buf, 0x20, 3, value);
+            assert_eq!(buf[0] & 0xf8, 0x20);
+            assert_eq!(read_prefixed_int(&mut &buf[..], 3).unwrap(), value);
+        }
+    }
+}
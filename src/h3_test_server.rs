@@ -0,0 +1,381 @@
+// 本地 HTTP/3 测试服务器 - 仅供单元测试使用
+use bytes::{Buf, Bytes};
+use h3::ConnectionState;
+use h3_quinn::quinn;
+use rustls::RootCertStore;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+use trust_dns_proto::op::{Message, MessageType};
+use trust_dns_proto::rr::{RData, Record};
+
+use crate::h3_direct_test::{decode_h3_datagram, encode_h3_datagram};
+use crate::tls_trust::KeyExchange;
+
+// CONNECT-UDP 隧道: 请求流 ID -> 已连接到目标的 UDP socket
+type UdpTunnels = Arc<Mutex<HashMap<u64, Arc<UdpSocket>>>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct TestServerOptions {
+    pub enable_datagram: bool, // 通告 SETTINGS_H3_DATAGRAM 并回显收到的 datagram
+    pub enable_extended_connect: bool, // 通告 SETTINGS_ENABLE_CONNECT_PROTOCOL 并接受扩展 CONNECT
+    pub enable_connect_udp: bool, // 作为 MASQUE 代理转发 CONNECT-UDP 隧道中的 UDP 负载
+    pub response_header_padding: usize, // 普通响应附加 x-padding 头的长度，用于字段区大小扫描
+    pub max_concurrent_bidi_streams: Option<u32>, // 覆盖 quinn 默认的并发双向流上限 (100)
+    pub max_idle_timeout_ms: Option<u32>, // 覆盖 quinn 默认的空闲超时 (30 秒)
+    pub served_authority: Option<String>, // 只服务该主机名与监听端口组成的 :authority，其余请求返回 421
+    pub client_auth_roots: Option<RootCertStore>, // 要求客户端出示由这些根签发的证书
+    pub bind_ip: Option<IpAddr>, // 监听地址，默认 127.0.0.1；TCP 服务使用同一地址
+}
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub cert: CertificateDer<'static>,
+    key: PrivatePkcs8KeyDer<'static>,
+    _endpoint: quinn::Endpoint,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TcpServerOptions {
+    pub alt_svc: Option<String>, // 响应中附加的 Alt-Svc 头
+    pub port: Option<u16>,       // 指定 TCP 端口，如与 h3 服务相同的端口号
+}
+
+impl TestServer {
+    pub fn root_store(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.clone()).expect("无效的测试证书");
+        roots
+    }
+
+    // 使用同一证书的 HTTP/1.1 over TLS 服务器，每个连接只处理一个请求
+    pub async fn spawn_tcp(&self, options: TcpServerOptions) -> SocketAddr {
+        let mut tls_config = server_config_builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![self.cert.clone()], PrivateKeyDer::Pkcs8(self.key.clone_key()))
+            .unwrap();
+        tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_config));
+
+        let listener = TcpListener::bind((self.addr.ip(), options.port.unwrap_or(0))).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let options = options.clone();
+                tokio::spawn(async move {
+                    if let Ok(tls) = acceptor.accept(tcp).await {
+                        let _ = handle_http1(tls, &options).await;
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+}
+
+async fn handle_http1<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, options: &TcpServerOptions) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..len]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let body = format!("hello from tcp test server: {}", path);
+    let mut response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n", body.len());
+    if let Some(alt_svc) = &options.alt_svc {
+        response.push_str(&format!("alt-svc: {}\r\n", alt_svc));
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// 启用 pq 特性时同样接受 X25519MLKEM768，否则只有经典组
+fn server_config_builder() -> rustls::ConfigBuilder<rustls::ServerConfig, rustls::WantsVerifier> {
+    let provider = KeyExchange::Pq.provider().or_else(|_| KeyExchange::Classic.provider()).unwrap();
+    rustls::ServerConfig::builder_with_provider(provider).with_safe_default_protocol_versions().unwrap()
+}
+
+pub async fn spawn(options: TestServerOptions) -> TestServer {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_der = CertificateDer::from(cert.serialize_der().unwrap());
+    let key = PrivatePkcs8KeyDer::from(cert.serialize_private_key_der());
+    let key_der = PrivateKeyDer::Pkcs8(key.clone_key());
+
+    let tls_config = server_config_builder();
+    let tls_config = match &options.client_auth_roots {
+        Some(roots) => tls_config.with_client_cert_verifier(
+            rustls::server::WebPkiClientVerifier::builder_with_provider(
+                Arc::new(roots.clone()),
+                KeyExchange::Classic.provider().unwrap(),
+            )
+            .build()
+            .unwrap(),
+        ),
+        None => tls_config.with_no_client_auth(),
+    };
+    let mut tls_config = tls_config.with_single_cert(vec![cert_der.clone()], key_der).unwrap();
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(
+        quinn::crypto::rustls::QuicServerConfig::try_from(tls_config).unwrap(),
+    ));
+    let mut transport_config = quinn::TransportConfig::default();
+    if let Some(streams) = options.max_concurrent_bidi_streams {
+        transport_config.max_concurrent_bidi_streams(streams.into());
+    }
+    if let Some(idle) = options.max_idle_timeout_ms {
+        transport_config.max_idle_timeout(Some(quinn::VarInt::from_u32(idle).into()));
+    }
+    server_config.transport_config(Arc::new(transport_config));
+    let bind_ip = options.bind_ip.unwrap_or(Ipv4Addr::LOCALHOST.into());
+    let endpoint = quinn::Endpoint::server(server_config, SocketAddr::new(bind_ip, 0)).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let accept_endpoint = endpoint.clone();
+    tokio::spawn(async move {
+        while let Some(incoming) = accept_endpoint.accept().await {
+            let options = options.clone();
+            tokio::spawn(async move {
+                if let Ok(conn) = incoming.await {
+                    handle_connection(conn, options, addr.port()).await;
+                }
+            });
+        }
+    });
+
+    TestServer {
+        addr,
+        cert: cert_der,
+        key,
+        _endpoint: endpoint,
+    }
+}
+
+async fn handle_connection(conn: quinn::Connection, options: TestServerOptions, port: u16) {
+    let tunnels = UdpTunnels::default();
+
+    if options.enable_datagram || options.enable_connect_udp {
+        // 属于 CONNECT-UDP 隧道的 datagram 转发到目标，其余的原样回显
+        let datagram_conn = conn.clone();
+        let tunnels = tunnels.clone();
+        let echo = options.enable_datagram;
+        tokio::spawn(async move {
+            while let Ok(datagram) = datagram_conn.read_datagram().await {
+                let Some((stream_id, mut payload)) = decode_h3_datagram(datagram.clone()) else {
+                    continue;
+                };
+                let tunnel = tunnels.lock().unwrap().get(&stream_id.into_inner()).cloned();
+                match tunnel {
+                    // Context ID 0 表示 UDP 负载
+                    Some(socket)
+                        if h3::proto::varint::VarInt::decode(&mut payload).map(|c| c.into_inner()) == Ok(0) =>
+                    {
+                        let _ = socket.send(&payload).await;
+                    }
+                    Some(_) => {}
+                    None if echo => {
+                        let _ = datagram_conn.send_datagram(datagram);
+                    }
+                    None => {}
+                }
+            }
+        });
+    }
+
+    let mut h3_conn: h3::server::Connection<h3_quinn::Connection, Bytes> = match h3::server::builder()
+        .enable_datagram(options.enable_datagram || options.enable_connect_udp)
+        .enable_extended_connect(options.enable_extended_connect || options.enable_connect_udp)
+        .build(h3_quinn::Connection::new(conn.clone()))
+        .await
+    {
+        Ok(h3_conn) => h3_conn,
+        Err(_) => return,
+    };
+
+    while let Ok(Some(resolver)) = h3_conn.accept().await {
+        let conn = conn.clone();
+        let tunnels = tunnels.clone();
+        let options = options.clone();
+        tokio::spawn(async move {
+            let Ok((req, mut stream)) = resolver.resolve_request().await else {
+                return;
+            };
+
+            if let Some(served) = &options.served_authority {
+                // 与 h2 / http/1.1 的 Host 相同，非 443 端口时 :authority 带端口
+                let served_port = |a: &http::uri::Authority| a.port_u16().unwrap_or(443) == port;
+                if !req.uri().authority().is_some_and(|a| a.host() == served && served_port(a)) {
+                    let resp = http::Response::builder().status(421).body(()).unwrap();
+                    let _ = stream.send_response(resp).await;
+                    let _ = stream.finish().await;
+                    return;
+                }
+            }
+
+            let protocol = req.extensions().get::<h3::ext::Protocol>().copied();
+            if req.method() == http::Method::CONNECT && protocol == Some(h3::ext::Protocol::CONNECT_UDP) {
+                let Some(target) = parse_connect_udp_path(req.uri().path()) else {
+                    let resp = http::Response::builder().status(400).body(()).unwrap();
+                    let _ = stream.send_response(resp).await;
+                    let _ = stream.finish().await;
+                    return;
+                };
+                let local: IpAddr = if target.is_ipv6() { Ipv6Addr::LOCALHOST.into() } else { Ipv4Addr::LOCALHOST.into() };
+                let socket = UdpSocket::bind((local, 0)).await.unwrap();
+                socket.connect(target).await.unwrap();
+                let socket = Arc::new(socket);
+                let stream_id = stream.id();
+                tunnels.lock().unwrap().insert(stream_id.into_inner(), socket.clone());
+
+                // 目标返回的 UDP 负载封装为 Context ID 0 的 HTTP datagram
+                let relay = tokio::spawn(async move {
+                    let mut buf = vec![0u8; 65535];
+                    while let Ok(len) = socket.recv(&mut buf).await {
+                        let mut payload = vec![0u8];
+                        payload.extend_from_slice(&buf[..len]);
+                        let _ = conn.send_datagram(encode_h3_datagram(stream_id, &payload));
+                    }
+                });
+
+                let resp = http::Response::builder()
+                    .status(200)
+                    .header("capsule-protocol", "?1")
+                    .body(())
+                    .unwrap();
+                if stream.send_response(resp).await.is_ok() {
+                    while let Ok(Some(_)) = stream.recv_data().await {}
+                    let _ = stream.finish().await;
+                }
+                tunnels.lock().unwrap().remove(&stream_id.into_inner());
+                relay.abort();
+                return;
+            }
+
+            // 扩展 CONNECT: 返回 200 并保持会话，直到客户端关闭；
+            // WebTransport 会话要求客户端 SETTINGS 声明 WebTransport 与 HTTP datagram
+            if req.method() == http::Method::CONNECT && protocol.is_some() {
+                let settings = stream.settings();
+                let status = if protocol == Some(h3::ext::Protocol::WEB_TRANSPORT)
+                    && !(settings.enable_webtransport() && settings.enable_datagram())
+                {
+                    400
+                } else {
+                    200
+                };
+                let resp = http::Response::builder().status(status).body(()).unwrap();
+                if stream.send_response(resp).await.is_ok() {
+                    while let Ok(Some(_)) = stream.recv_data().await {}
+                    let _ = stream.finish().await;
+                }
+                return;
+            }
+
+            // 下载测速: /__down?bytes=N 以 16 KiB 分块返回 N 个字节
+            if req.uri().path() == "/__down" {
+                let total = req
+                    .uri()
+                    .query()
+                    .and_then(|q| q.strip_prefix("bytes="))
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let resp = http::Response::builder().status(200).body(()).unwrap();
+                if stream.send_response(resp).await.is_err() {
+                    return;
+                }
+                let chunk = Bytes::from(vec![0u8; 16 * 1024]);
+                let mut sent = 0;
+                while sent < total {
+                    let len = chunk.len().min(total - sent);
+                    if stream.send_data(chunk.slice(..len)).await.is_err() {
+                        return;
+                    }
+                    sent += len;
+                }
+                let _ = stream.finish().await;
+                return;
+            }
+
+            // 上传测速: /__up 读完请求体，字节数与 Content-Length 一致时返回 200
+            if req.method() == http::Method::POST && req.uri().path() == "/__up" {
+                let expected = req
+                    .headers()
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<usize>().ok());
+                let mut received = 0;
+                while let Ok(Some(chunk)) = stream.recv_data().await {
+                    received += chunk.remaining();
+                }
+                let status = if expected.is_none_or(|n| n == received) { 200 } else { 400 };
+                let resp = http::Response::builder().status(status).body(()).unwrap();
+                if stream.send_response(resp).await.is_ok()
+                    && stream.send_data(Bytes::from(format!("received {}", received))).await.is_ok()
+                {
+                    let _ = stream.finish().await;
+                }
+                return;
+            }
+
+            let body = Bytes::from(format!("hello from h3 test server: {}", req.uri().path()));
+            let mut resp = http::Response::builder().status(200);
+            if options.response_header_padding > 0 {
+                resp = resp.header("x-padding", "a".repeat(options.response_header_padding));
+            }
+            let resp = resp.body(()).unwrap();
+            if stream.send_response(resp).await.is_ok() && stream.send_data(body).await.is_ok() {
+                let _ = stream.finish().await;
+            }
+        });
+    }
+}
+
+// 解析 /.well-known/masque/udp/{target_host}/{target_port}/
+fn parse_connect_udp_path(path: &str) -> Option<SocketAddr> {
+    let rest = path.strip_prefix("/.well-known/masque/udp/")?;
+    let mut parts = rest.trim_end_matches('/').split('/');
+    let host = urlencoding::decode(parts.next()?).ok()?;
+    let port: u16 = parts.next()?.parse().ok()?;
+    Some(SocketAddr::new(host.parse().ok()?, port))
+}
+
+// 本地 DNS 桩服务器：监听 bind_ip，对每个 A 查询返回 127.0.0.1
+pub async fn spawn_dns_stub(bind_ip: IpAddr) -> SocketAddr {
+    let socket = UdpSocket::bind((bind_ip, 0)).await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 4096];
+        while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+            let Ok(query) = Message::from_vec(&buf[..len]) else {
+                continue;
+            };
+            let mut response = Message::new();
+            response.set_id(query.id());
+            response.set_message_type(MessageType::Response);
+            response.set_recursion_desired(query.recursion_desired());
+            response.set_recursion_available(true);
+            for q in query.queries() {
+                response.add_query(q.clone());
+                response.add_answer(Record::from_rdata(q.name().clone(), 60, RData::A(Ipv4Addr::LOCALHOST)));
+            }
+            if let Ok(bytes) = response.to_vec() {
+                let _ = socket.send_to(&bytes, peer).await;
+            }
+        }
+    });
+
+    addr
+}
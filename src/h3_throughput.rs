@@ -0,0 +1,592 @@
+// HTTP/3 吞吐测试模块 - 在同一 IP 上分别通过 h3 与 h2 流式下载并采样带宽，以及 h3 上传
+use anyhow::{anyhow, Context};
+use bytes::Buf;
+use http::{Method, Request};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::time::timeout_at;
+
+use crate::h3_direct_test::{H3TestConfig, H3Tester};
+use crate::quic_transport::QuicTransportOptions;
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+// 按字节数下载时使用的路径模板 (与 speed.cloudflare.com 的 __down 接口一致)
+pub const DEFAULT_DOWNLOAD_PATH_TEMPLATE: &str = "/__down?bytes={bytes}";
+
+// --- 1. 吞吐测试配置 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThroughputConfig {
+    pub target_ip: IpAddr,
+    pub port: u16,
+    pub sni: String,
+    pub host: String,
+    pub path: String,
+    pub max_bytes: Option<u64>, // 读到该字节数后停止，不等待响应体结束
+    pub sample_interval_ms: u64,
+    pub stall_threshold_ms: u64, // 两次收到数据的间隔超过该值视为停顿
+    pub timeout_seconds: u64,
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub transport: QuicTransportOptions, // h3 连接的 QUIC 传输参数
+}
+
+impl ThroughputConfig {
+    fn url(&self) -> String {
+        format!("https://{}{}", self.host, self.path)
+    }
+
+    fn target_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.target_ip, self.port)
+    }
+
+    fn h3_config(&self) -> H3TestConfig {
+        H3TestConfig {
+            host_header: Some(self.host.clone()),
+            test_path: self.path.clone(),
+            timeout_seconds: self.timeout_seconds,
+            user_agent: self.user_agent.clone(),
+            transport: self.transport.clone(),
+            ..H3TestConfig::new(&self.sni, self.target_ip, self.port)
+        }
+    }
+}
+
+// --- 2. 吞吐测试结果 ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ThroughputSample {
+    pub at_ms: u64, // 采样区间结束时间（自开始连接起）
+    pub bytes: u64, // 该区间内收到的字节数
+    pub mbps: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Stall {
+    pub at_ms: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DownloadResult {
+    pub protocol: String, // "h3" 或 "h2"
+    pub url: String,
+    pub target: String,
+    pub response_status: Option<u16>,
+    pub bytes: u64,
+    pub elapsed_ms: u64,
+    pub time_to_first_byte_ms: Option<u64>,
+    pub time_to_first_mb_ms: Option<u64>,
+    pub average_mbps: f64, // 从首字节到结束
+    pub peak_mbps: f64,    // 采样区间中的最大值
+    pub samples: Vec<ThroughputSample>,
+    pub stalls: Vec<Stall>,
+    pub completed: bool,    // 响应体读完或达到 max_bytes
+    pub stopped_early: bool, // 因 max_bytes 提前停止
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UploadResult {
+    pub protocol: String,
+    pub url: String,
+    pub target: String,
+    pub response_status: Option<u16>,
+    pub payload_bytes: u64,
+    pub bytes_sent: u64, // 已被 QUIC 流接受的字节数 (受流控限制，不等于对端已确认)
+    pub elapsed_ms: u64,
+    pub send_ms: Option<u64>, // 从首块数据到请求流结束
+    pub upload_mbps: f64,
+    pub peak_mbps: f64,
+    pub ack_latency_ms: Option<u64>, // 请求流结束到收到响应头，即服务器确认收完的延迟
+    pub samples: Vec<ThroughputSample>,
+    pub stalls: Vec<Stall>,
+    pub completed: bool,
+    pub error_message: Option<String>,
+}
+
+// --- 3. 带宽采样 ---
+// 每收到一块数据记录一次，不保留数据本身
+pub struct ThroughputMeter {
+    interval: Duration,
+    stall_threshold: Duration,
+    bytes: u64,
+    first_byte: Option<Duration>,
+    first_mb: Option<Duration>,
+    last_data: Option<Duration>,
+    samples: Vec<ThroughputSample>,
+    stalls: Vec<Stall>,
+}
+
+impl ThroughputMeter {
+    pub fn new(interval: Duration, stall_threshold: Duration) -> Self {
+        Self {
+            interval: interval.max(Duration::from_millis(1)),
+            stall_threshold,
+            bytes: 0,
+            first_byte: None,
+            first_mb: None,
+            last_data: None,
+            samples: Vec::new(),
+            stalls: Vec::new(),
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn record(&mut self, at: Duration, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        if let Some(last) = self.last_data {
+            if at.saturating_sub(last) > self.stall_threshold {
+                self.stalls.push(Stall {
+                    at_ms: last.as_millis() as u64,
+                    duration_ms: (at - last).as_millis() as u64,
+                });
+            }
+        }
+        self.first_byte.get_or_insert(at);
+        self.last_data = Some(at);
+
+        self.bytes += len as u64;
+        if self.first_mb.is_none() && self.bytes >= MEGABYTE {
+            self.first_mb = Some(at);
+        }
+
+        // 补齐空区间，使采样序列能体现停顿
+        let index = (at.as_nanos() / self.interval.as_nanos()) as usize;
+        while self.samples.len() <= index {
+            let at_ms = ((self.samples.len() + 1) as u128 * self.interval.as_millis()) as u64;
+            self.samples.push(ThroughputSample { at_ms, bytes: 0, mbps: 0.0 });
+        }
+        self.samples[index].bytes += len as u64;
+    }
+
+    // 计算各采样区间的带宽，返回 (从首字节到 end 的平均带宽, 峰值带宽)
+    fn rates(&mut self, end: Duration) -> (f64, f64) {
+        let interval_secs = self.interval.as_secs_f64();
+        for sample in &mut self.samples {
+            sample.mbps = mbps(sample.bytes, interval_secs);
+        }
+        let average = self
+            .first_byte
+            .map(|first| mbps(self.bytes, end.saturating_sub(first).as_secs_f64()))
+            .unwrap_or(0.0);
+        let peak = self.samples.iter().map(|s| s.mbps).fold(0.0, f64::max);
+        (average, peak)
+    }
+
+    fn finish(mut self, result: &mut DownloadResult, elapsed: Duration) {
+        (result.average_mbps, result.peak_mbps) = self.rates(elapsed);
+        result.bytes = self.bytes;
+        result.elapsed_ms = elapsed.as_millis() as u64;
+        result.time_to_first_byte_ms = self.first_byte.map(|d| d.as_millis() as u64);
+        result.time_to_first_mb_ms = self.first_mb.map(|d| d.as_millis() as u64);
+        result.samples = self.samples;
+        result.stalls = self.stalls;
+    }
+}
+
+fn mbps(bytes: u64, secs: f64) -> f64 {
+    if secs <= 0.0 {
+        return 0.0;
+    }
+    bytes as f64 * 8.0 / secs / 1_000_000.0
+}
+
+// --- 4. h3 / h2 下载 ---
+pub async fn download_h3(tester: &H3Tester, config: &ThroughputConfig) -> DownloadResult {
+    let mut result = DownloadResult {
+        protocol: "h3".to_string(),
+        url: config.url(),
+        target: format!("{} (SNI {})", config.target_addr(), config.sni),
+        ..Default::default()
+    };
+    let mut meter = ThroughputMeter::new(
+        Duration::from_millis(config.sample_interval_ms),
+        Duration::from_millis(config.stall_threshold_ms),
+    );
+
+    // 计时包含建立连接，与 reqwest 在首个请求时建立 h2 连接的计时方式一致
+    let start_time = Instant::now();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(config.timeout_seconds);
+
+    let outcome = async {
+        let mut session = timeout_at(deadline, tester.connect(&config.h3_config()))
+            .await
+            .map_err(|_| anyhow!("HTTP/3 connect timeout"))??;
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(config.url())
+            .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-throughput/1.0"))
+            .body(())?;
+        let mut stream = session.send_request.send_request(request).await
+            .context("Failed to send HTTP/3 request")?;
+        stream.finish().await
+            .context("Failed to finish request stream")?;
+
+        let response = timeout_at(deadline, stream.recv_response())
+            .await
+            .map_err(|_| anyhow!("HTTP/3 response timeout"))?
+            .context("Failed to receive HTTP/3 response")?;
+        result.response_status = Some(response.status().as_u16());
+
+        loop {
+            let chunk = timeout_at(deadline, stream.recv_data())
+                .await
+                .map_err(|_| anyhow!("download timeout"))?
+                .context("Failed to receive response data")?;
+            let Some(chunk) = chunk else {
+                break;
+            };
+            meter.record(start_time.elapsed(), chunk.remaining());
+
+            // 读取中途不能调用 h3-quinn 的 stop_sending，丢弃流时由 quinn 发送 STOP_SENDING
+            if config.max_bytes.is_some_and(|max| meter.bytes() >= max) {
+                result.stopped_early = true;
+                break;
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match outcome {
+        Ok(()) => result.completed = true,
+        Err(e) => result.error_message = Some(format!("{:#}", e)),
+    }
+    meter.finish(&mut result, start_time.elapsed());
+    result
+}
+
+pub async fn download_h2(tester: &H3Tester, config: &ThroughputConfig) -> DownloadResult {
+    let mut result = DownloadResult {
+        protocol: "h2".to_string(),
+        url: config.url(),
+        target: format!("{} (SNI {})", config.target_addr(), config.host),
+        ..Default::default()
+    };
+    let mut meter = ThroughputMeter::new(
+        Duration::from_millis(config.sample_interval_ms),
+        Duration::from_millis(config.stall_threshold_ms),
+    );
+
+    let client = tester.h2_tls_config().and_then(|tls_config| {
+        Ok(reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .http2_prior_knowledge()
+            .resolve(&config.host, config.target_addr())
+            .user_agent(config.user_agent.as_deref().unwrap_or("rust-h3-throughput/1.0"))
+            .build()?)
+    });
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            result.error_message = Some(format!("创建 h2 客户端失败: {}", e));
+            return result;
+        }
+    };
+
+    let start_time = Instant::now();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(config.timeout_seconds);
+
+    let outcome = async {
+        let mut response = timeout_at(deadline, client.get(config.url()).send())
+            .await
+            .map_err(|_| anyhow!("HTTP/2 response timeout"))??;
+        result.response_status = Some(response.status().as_u16());
+
+        // 逐块读取，不缓存响应体
+        while let Some(chunk) = timeout_at(deadline, response.chunk())
+            .await
+            .map_err(|_| anyhow!("download timeout"))??
+        {
+            meter.record(start_time.elapsed(), chunk.len());
+
+            if config.max_bytes.is_some_and(|max| meter.bytes() >= max) {
+                result.stopped_early = true;
+                break;
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match outcome {
+        Ok(()) => result.completed = true,
+        Err(e) => result.error_message = Some(format!("{:#}", e)),
+    }
+    meter.finish(&mut result, start_time.elapsed());
+    result
+}
+
+// --- 5. h3 上传 ---
+// 上传测速时默认使用的路径 (与 speed.cloudflare.com 的 __up 接口一致)
+pub const DEFAULT_UPLOAD_PATH: &str = "/__up";
+
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+pub async fn upload_h3(tester: &H3Tester, config: &ThroughputConfig, payload_bytes: u64) -> UploadResult {
+    let mut result = UploadResult {
+        protocol: "h3".to_string(),
+        url: config.url(),
+        target: format!("{} (SNI {})", config.target_addr(), config.sni),
+        payload_bytes,
+        ..Default::default()
+    };
+    let mut meter = ThroughputMeter::new(
+        Duration::from_millis(config.sample_interval_ms),
+        Duration::from_millis(config.stall_threshold_ms),
+    );
+
+    let start_time = Instant::now();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(config.timeout_seconds);
+    let mut send_done = None;
+
+    let outcome = async {
+        let mut session = timeout_at(deadline, tester.connect(&config.h3_config()))
+            .await
+            .map_err(|_| anyhow!("HTTP/3 connect timeout"))??;
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(config.url())
+            .header("User-Agent", config.user_agent.as_deref().unwrap_or("rust-h3-throughput/1.0"))
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Length", payload_bytes)
+            .body(())?;
+        let mut stream = session.send_request.send_request(request).await
+            .context("Failed to send HTTP/3 request")?;
+
+        // 生成的负载按块发送，send_data 在流控窗口耗尽时等待，采样反映实际发送速率
+        let chunk = bytes::Bytes::from((0..UPLOAD_CHUNK_SIZE).map(|i| i as u8).collect::<Vec<u8>>());
+        while meter.bytes() < payload_bytes {
+            let len = chunk.len().min((payload_bytes - meter.bytes()) as usize);
+            timeout_at(deadline, stream.send_data(chunk.slice(..len)))
+                .await
+                .map_err(|_| anyhow!("upload timeout"))?
+                .context("Failed to send request body")?;
+            meter.record(start_time.elapsed(), len);
+        }
+        timeout_at(deadline, stream.finish())
+            .await
+            .map_err(|_| anyhow!("upload timeout"))?
+            .context("Failed to finish request stream")?;
+        let finished_at = start_time.elapsed();
+        send_done = Some(finished_at);
+
+        let response = timeout_at(deadline, stream.recv_response())
+            .await
+            .map_err(|_| anyhow!("HTTP/3 response timeout"))?
+            .context("Failed to receive HTTP/3 response")?;
+        result.response_status = Some(response.status().as_u16());
+        result.ack_latency_ms = Some(start_time.elapsed().saturating_sub(finished_at).as_millis() as u64);
+
+        while timeout_at(deadline, stream.recv_data())
+            .await
+            .map_err(|_| anyhow!("HTTP/3 response timeout"))?
+            .context("Failed to receive response data")?
+            .is_some()
+        {}
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match outcome {
+        Ok(()) => result.completed = true,
+        Err(e) => result.error_message = Some(format!("{:#}", e)),
+    }
+
+    let elapsed = start_time.elapsed();
+    let send_end = send_done.unwrap_or(elapsed);
+    (result.upload_mbps, result.peak_mbps) = meter.rates(send_end);
+    result.bytes_sent = meter.bytes;
+    result.elapsed_ms = elapsed.as_millis() as u64;
+    result.send_ms = meter.first_byte.map(|first| send_end.saturating_sub(first).as_millis() as u64);
+    result.samples = meter.samples;
+    result.stalls = meter.stalls;
+    result
+}
+
+// --- 6. 报告 ---
+pub fn generate_download_report(results: &[DownloadResult]) -> String {
+    let mut report = String::new();
+    report.push_str("=== 下载吞吐测试 ===\n\n");
+    report.push_str(&format!("{:<6} {:<8} {:<12} {:<10} {:<10} {:<10} {:<10} {:<6} {:<15}\n",
+                             "协议", "状态码", "字节", "耗时", "首字节", "首 MB", "平均", "停顿", "错误"));
+    report.push_str(&"-".repeat(100));
+    report.push('\n');
+
+    let ms = |v: Option<u64>| v.map(|v| format!("{}ms", v)).unwrap_or_else(|| "-".to_string());
+    for result in results {
+        report.push_str(&format!("{:<6} {:<8} {:<12} {:<10} {:<10} {:<10} {:<10} {:<6} {:<15}\n",
+                                 result.protocol,
+                                 result.response_status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                                 result.bytes,
+                                 format!("{}ms", result.elapsed_ms),
+                                 ms(result.time_to_first_byte_ms),
+                                 ms(result.time_to_first_mb_ms),
+                                 format!("{:.2}Mbps", result.average_mbps),
+                                 result.stalls.len(),
+                                 result.error_message.as_deref().unwrap_or("")));
+    }
+
+    for result in results {
+        report.push_str(&format!("\n{} 采样 (峰值 {:.2} Mbps):\n", result.protocol, result.peak_mbps));
+        for sample in &result.samples {
+            report.push_str(&format!("  {:>8}ms {:>10.2} Mbps\n", sample.at_ms, sample.mbps));
+        }
+        for stall in &result.stalls {
+            report.push_str(&format!("  停顿: {}ms 起 {}ms 未收到数据\n", stall.at_ms, stall.duration_ms));
+        }
+    }
+
+    report
+}
+
+pub fn generate_upload_report(result: &UploadResult) -> String {
+    let mut report = String::new();
+    let ms = |v: Option<u64>| v.map(|v| format!("{}ms", v)).unwrap_or_else(|| "-".to_string());
+    report.push_str("=== 上传吞吐测试 ===\n\n");
+    report.push_str(&format!("协议: {}\n", result.protocol));
+    report.push_str(&format!("URL: {}\n", result.url));
+    report.push_str(&format!("目标: {}\n", result.target));
+    report.push_str(&format!("状态码: {}\n",
+                             result.response_status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string())));
+    report.push_str(&format!("已发送: {} / {} 字节\n", result.bytes_sent, result.payload_bytes));
+    report.push_str(&format!("发送耗时: {}\n", ms(result.send_ms)));
+    report.push_str(&format!("上传带宽: {:.2} Mbps (峰值 {:.2} Mbps)\n", result.upload_mbps, result.peak_mbps));
+    report.push_str(&format!("服务器确认延迟: {}\n", ms(result.ack_latency_ms)));
+    report.push_str(&format!("总耗时: {}ms\n", result.elapsed_ms));
+    if let Some(error) = &result.error_message {
+        report.push_str(&format!("错误: {}\n", error));
+    }
+
+    report.push_str("\n采样:\n");
+    for sample in &result.samples {
+        report.push_str(&format!("  {:>8}ms {:>10.2} Mbps\n", sample.at_ms, sample.mbps));
+    }
+    for stall in &result.stalls {
+        report.push_str(&format!("  停顿: {}ms 起 {}ms 未能发送数据\n", stall.at_ms, stall.duration_ms));
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h3_test_server::{self, TestServerOptions};
+
+    fn local_download(addr: SocketAddr, bytes: u64) -> ThroughputConfig {
+        ThroughputConfig {
+            target_ip: addr.ip(),
+            port: addr.port(),
+            sni: "localhost".to_string(),
+            host: "localhost".to_string(),
+            path: DEFAULT_DOWNLOAD_PATH_TEMPLATE.replace("{bytes}", &bytes.to_string()),
+            max_bytes: None,
+            sample_interval_ms: 50,
+            stall_threshold_ms: 500,
+            timeout_seconds: 10,
+            user_agent: None,
+            transport: QuicTransportOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_meter_samples_and_stalls() {
+        let mut meter = ThroughputMeter::new(Duration::from_millis(100), Duration::from_millis(500));
+        meter.record(Duration::from_millis(10), 600 * 1024);
+        meter.record(Duration::from_millis(120), 600 * 1024);
+        meter.record(Duration::from_millis(900), 100);
+
+        let mut result = DownloadResult::default();
+        meter.finish(&mut result, Duration::from_millis(1000));
+
+        assert_eq!(result.bytes, 1200 * 1024 + 100);
+        assert_eq!(result.time_to_first_byte_ms, Some(10));
+        assert_eq!(result.time_to_first_mb_ms, Some(120));
+        assert_eq!(result.samples.len(), 10);
+        assert_eq!(result.samples[0].bytes, 600 * 1024);
+        assert_eq!(result.samples[5].bytes, 0);
+        assert_eq!(result.stalls.len(), 1);
+        assert_eq!(result.stalls[0].at_ms, 120);
+        assert_eq!(result.stalls[0].duration_ms, 780);
+        assert!(result.peak_mbps > result.average_mbps);
+    }
+
+    #[tokio::test]
+    async fn test_h3_download_streams_whole_body() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let result = download_h3(&tester, &local_download(server.addr, 3 * MEGABYTE)).await;
+
+        assert!(result.completed, "{:?}", result.error_message);
+        assert!(!result.stopped_early);
+        assert_eq!(result.response_status, Some(200));
+        assert_eq!(result.bytes, 3 * MEGABYTE);
+        assert!(result.time_to_first_mb_ms.is_some());
+        assert!(!result.samples.is_empty());
+        assert!(result.average_mbps > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_h3_download_stops_at_byte_limit() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_download(server.addr, 8 * MEGABYTE);
+        config.max_bytes = Some(MEGABYTE / 2);
+        let result = download_h3(&tester, &config).await;
+
+        assert!(result.completed, "{:?}", result.error_message);
+        assert!(result.stopped_early);
+        assert!(result.bytes >= MEGABYTE / 2 && result.bytes < 8 * MEGABYTE);
+        assert_eq!(result.time_to_first_mb_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_h3_download_uses_transport_options() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        // 低于 QUIC 最小值的固定 MTU 在创建端点时即被拒绝
+        let mut config = local_download(server.addr, MEGABYTE);
+        config.transport.fixed_mtu = Some(1000);
+        let result = download_h3(&tester, &config).await;
+
+        assert!(!result.completed);
+        assert!(result.error_message.is_some());
+        assert_eq!(result.response_status, None);
+    }
+
+    #[tokio::test]
+    async fn test_h3_upload_sends_whole_payload() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let tester = H3Tester::with_root_store(server.root_store()).unwrap();
+
+        let mut config = local_download(server.addr, 0);
+        config.path = DEFAULT_UPLOAD_PATH.to_string();
+        let payload = 2 * MEGABYTE + 123;
+        let result = upload_h3(&tester, &config, payload).await;
+
+        // 测试服务器在收到的字节数与 Content-Length 不符时返回 400
+        assert!(result.completed, "{:?}", result.error_message);
+        assert_eq!(result.response_status, Some(200));
+        assert_eq!(result.bytes_sent, payload);
+        assert!(result.ack_latency_ms.is_some());
+        assert!(result.send_ms.is_some());
+        assert!(result.upload_mbps > 0.0);
+        assert!(!result.samples.is_empty());
+    }
+}
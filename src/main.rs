@@ -1,5 +1,21 @@
 // 纯 HTTP/3 测试工具 - 使用 h3 库
 mod main_h3_only;
+mod h3_bench;
+mod h3_throughput;
+mod h3_matrix;
+mod alt_svc;
+mod tls_inspect;
+mod tls_trust;
+mod ech;
+// 原生 h3 探测模块，部分接口仅供 main_comprehensive_h3 使用
+#[allow(dead_code)]
+mod h3_direct_test;
+mod h3_peer_params;
+mod h3_raw_headers;
+mod quic_transport;
+mod quic_qlog;
+#[cfg(test)]
+mod h3_test_server;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(main_h3_only::run()?)
@@ -30,6 +30,12 @@ pub struct ComprehensiveTestConfig {
     pub test_paths: Vec<String>,
     pub use_fallback: bool,
     pub max_field_section_size: Option<u64>,
+    #[serde(default = "default_send_grease")]
+    pub send_grease: bool,
+}
+
+fn default_send_grease() -> bool {
+    true
 }
 
 impl Default for ComprehensiveTestConfig {
@@ -54,6 +60,7 @@ impl Default for ComprehensiveTestConfig {
             ],
             use_fallback: true,
             max_field_section_size: Some(8192),
+            send_grease: true,
         }
     }
 }
@@ -296,7 +303,7 @@ pub async fn run_native_h3_tests(config: &ComprehensiveTestConfig) -> Result<Vec
                 max_field_section_size: config.max_field_section_size,
                 enable_datagram: false,
                 enable_extended_connect: false,
-                send_grease: true,
+                send_grease: config.send_grease,
                 timeout_seconds: config.timeout_seconds,
             };
             h3_configs.push(h3_config);
@@ -2,18 +2,48 @@
 use anyhow::{anyhow, Context, Result};
 use base64::{engine::general_purpose, Engine as _};
 use bytes::Buf;
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
 use h3_quinn::quinn;
 use reqwest::Client;
-use rustls_native_certs::load_native_certs;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::net::IpAddr;
 use std::sync::Arc;
 use tracing::{error, info};
 use trust_dns_proto::op::{Message, Query};
+use trust_dns_proto::rr::rdata::svcb::{SvcParamValue, SVCB};
 use trust_dns_proto::rr::{Name, RecordType};
 use trust_dns_proto::serialize::binary::BinEncodable;
 
+use crate::ech;
+use crate::h3_bench;
+use crate::h3_direct_test;
+use crate::h3_matrix;
+use crate::h3_throughput;
+use crate::h3_peer_params::{CapturingConnection, CapturingCryptoConfig, SettingsCapture, TransportParamsCapture};
+use crate::quic_qlog::QlogDir;
+use crate::quic_transport::{CongestionController, QuicTransportOptions};
+use crate::tls_inspect::{recording_client_config, CertCapture, CertificateReport};
+use crate::tls_trust::{self, KeyExchange, TlsTrustOptions, TrustRoots};
+
+// 打印叶子证书摘要与检查警告
+fn log_certificate(report: &CertificateReport) {
+    if let Some(leaf) = report.chain.first() {
+        info!("🔒 证书: {} (签发者 {})，有效期至 {} (剩余 {} 天)，{}，SHA-256 {}",
+              leaf.subject, leaf.issuer, leaf.not_after, leaf.days_remaining, leaf.key_type, leaf.sha256_fingerprint);
+        info!("🔒 SAN: {}", leaf.sans.join(", "));
+    }
+    for warning in &report.warnings {
+        error!("⚠️ {}", warning);
+    }
+}
+
+// 打印探测结束后保留的 qlog 文件
+fn log_qlog_traces(paths: Vec<std::path::PathBuf>) {
+    for path in paths {
+        info!("📝 qlog: {}", path.display());
+    }
+}
+
 // 错误转换辅助函数
 fn h3_error_to_anyhow(e: impl std::error::Error + Send + Sync + 'static) -> anyhow::Error {
     anyhow!("{:?}", e)
@@ -23,23 +53,61 @@ static ALPN: &[u8] = b"h3";
 
 #[derive(Debug, Clone)]
 pub struct H3TestConfig {
-    pub domain: String,
+    pub domain: String,              // DNS 解析与 TLS SNI 使用的域名
+    pub host_header: Option<String>, // 请求的 :authority，默认与 domain 相同
     pub port: u16,
     pub path: String,
     pub doh_server: String,
     pub timeout_seconds: u64,
     pub prefer_ipv6: bool,
+    pub probe: String, // "request" 或 h3_direct_test 中的探测类型，如 "datagram"
+    pub connect_protocol: Option<String>,
+    pub connect_path: Option<String>,
+    pub masque_target: Option<String>,
+    pub masque_uri_template: Option<String>,
+    pub send_grease: bool,
+    pub max_field_section_size: Option<u64>,
+    pub field_section_limits: Vec<u64>, // sweep 探测使用的字段区上限列表
+    pub concurrency: usize,             // multiplex 探测的并发请求数
+    pub idle_intervals_ms: Vec<u64>, // idle 探测依次测试的空闲时长
+    pub mtu_sizes: Vec<u16>,         // mtu 探测依次固定的 MTU
+    pub transport: QuicTransportOptions, // 所有 QUIC 连接共用的传输参数
+    pub congestion_controllers: Vec<CongestionController>, // congestion 探测依次对比的算法
+    pub race_head_start_ms: u64, // race 探测中 h3 领先 TCP 尝试的时间
+    pub trust: TlsTrustOptions,  // 所有 TLS 连接共用的信任根、SPKI 固定与 insecure 设置
+    pub ech_config: Option<String>, // ech 探测使用的 base64 ECHConfigList，未设置时查询 HTTPS 记录
+    pub qlog_dir: Option<String>,   // 原生 QUIC 连接的 qlog 输出目录
+    pub qlog_keep_all: bool,        // 成功探测的 qlog 也保留
 }
 
 impl Default for H3TestConfig {
     fn default() -> Self {
         Self {
             domain: "local-aria2-webui.masx200.ddns-ip.net".to_string(),
+            host_header: None,
             port: 443,
             path: "/".to_string(),
             doh_server: "https://xget.a1u06h9fe9y5bozbmgz3.qzz.io/cloudflare-dns.com/dns-query".to_string(),
             timeout_seconds: 10,
             prefer_ipv6: false,
+            probe: "request".to_string(),
+            connect_protocol: None,
+            connect_path: None,
+            masque_target: None,
+            masque_uri_template: None,
+            send_grease: true,
+            max_field_section_size: None,
+            field_section_limits: h3_direct_test::DEFAULT_FIELD_SECTION_SWEEP.to_vec(),
+            concurrency: 10,
+            idle_intervals_ms: h3_direct_test::DEFAULT_IDLE_PROBE_INTERVALS_MS.to_vec(),
+            mtu_sizes: h3_direct_test::DEFAULT_MTU_SWEEP.to_vec(),
+            transport: QuicTransportOptions::default(),
+            congestion_controllers: CongestionController::ALL.to_vec(),
+            race_head_start_ms: h3_direct_test::DEFAULT_RACE_HEAD_START_MS,
+            trust: TlsTrustOptions::default(),
+            ech_config: None,
+            qlog_dir: None,
+            qlog_keep_all: false,
         }
     }
 }
@@ -51,6 +119,66 @@ async fn query_dns_over_https(
     record_type: RecordType,
     doh_server: &str,
 ) -> Result<Vec<IpAddr>> {
+    let dns_response = exchange_dns_over_https(client, domain, record_type, doh_server).await?;
+
+    // 提取 IP 地址
+    let mut ip_addresses = Vec::new();
+    let answers = dns_response.answers();
+
+    if !answers.is_empty() {
+        for record in answers {
+            if record.record_type() == record_type {
+                if let Some(rdata) = record.data() {
+                    match record.record_type() {
+                        RecordType::A => {
+                            if let trust_dns_proto::rr::RData::A(ipv4) = rdata {
+                                ip_addresses.push(IpAddr::V4(*ipv4));
+                            }
+                        }
+                        RecordType::AAAA => {
+                            if let trust_dns_proto::rr::RData::AAAA(ipv6) = rdata {
+                                ip_addresses.push(IpAddr::V6(*ipv6));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(ip_addresses)
+}
+
+// 查询 HTTPS 记录 (RFC 9460) 并取出第一个 ech 参数，返回可直接交给 rustls 的 ECHConfigList
+async fn query_https_ech_config(client: &Client, domain: &str, doh_server: &str) -> Result<Option<Vec<u8>>> {
+    let dns_response = exchange_dns_over_https(client, domain, RecordType::HTTPS, doh_server).await?;
+
+    let mut records: Vec<&SVCB> = dns_response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(trust_dns_proto::rr::RData::HTTPS(svcb)) => Some(svcb),
+            _ => None,
+        })
+        .collect();
+    records.sort_by_key(|svcb| svcb.svc_priority());
+
+    Ok(records.iter().find_map(|svcb| {
+        svcb.svc_params().iter().find_map(|(_, value)| match value {
+            SvcParamValue::EchConfig(ech) => Some(ech::config_list_from_svc_param(&ech.0)),
+            _ => None,
+        })
+    }))
+}
+
+// 发送 DoH GET 请求并解析响应报文
+async fn exchange_dns_over_https(
+    client: &Client,
+    domain: &str,
+    record_type: RecordType,
+    doh_server: &str,
+) -> Result<Message> {
     // 创建 DNS 查询
     let name = Name::from_ascii(domain)
         .context(format!("无效的域名: {}", domain))?;
@@ -103,33 +231,7 @@ async fn query_dns_over_https(
     let dns_response =
         Message::from_vec(&response_bytes).context("解析 DNS 响应失败")?;
 
-    // 提取 IP 地址
-    let mut ip_addresses = Vec::new();
-    let answers = dns_response.answers();
-
-    if !answers.is_empty() {
-        for record in answers {
-            if record.record_type() == record_type {
-                if let Some(rdata) = record.data() {
-                    match record.record_type() {
-                        RecordType::A => {
-                            if let trust_dns_proto::rr::RData::A(ipv4) = rdata {
-                                ip_addresses.push(IpAddr::V4(*ipv4));
-                            }
-                        }
-                        RecordType::AAAA => {
-                            if let trust_dns_proto::rr::RData::AAAA(ipv6) = rdata {
-                                ip_addresses.push(IpAddr::V6(*ipv6));
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(ip_addresses)
+    Ok(dns_response)
 }
 
 pub struct H3Tester {
@@ -141,14 +243,35 @@ impl H3Tester {
         Self { config }
     }
 
+    // 每次探测使用独立的 qlog 目录句柄，探测结束时只处理本次写入的 trace
+    fn qlog_dir(&self) -> Result<Option<QlogDir>> {
+        self.config
+            .qlog_dir
+            .as_ref()
+            .map(|dir| QlogDir::new(dir, self.config.qlog_keep_all))
+            .transpose()
+    }
+
+    fn direct_tester(&self, trust: &TlsTrustOptions) -> Result<h3_direct_test::H3Tester> {
+        Ok(h3_direct_test::H3Tester::with_trust(trust)?.with_qlog(self.qlog_dir()?))
+    }
+
+    // DoH 查询等辅助请求使用的 HTTP 客户端：reqwest 默认的 webpki 根证书，按 --keylog / SSLKEYLOGFILE 记录密钥
+    fn http_client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        let key_log = tls_trust::key_log(self.config.trust.keylog.as_deref())?;
+        Ok(Client::builder()
+            .use_preconfigured_tls(tls_trust::keylog_client_config(key_log, &[b"h2", b"http/1.1"])?)
+            .timeout(std::time::Duration::from_secs(self.config.timeout_seconds)))
+    }
+
     pub async fn test_connection(&self) -> Result<()> {
         info!("🚀 开始 HTTP/3 测试: {}:{}", self.config.domain, self.config.port);
         info!("🔧 使用 DoH 服务器: {}", self.config.doh_server);
 
         // 1. 创建 HTTP 客户端用于 DoH 查询
-        let client = Client::builder()
+        let client = self
+            .http_client_builder()?
             .user_agent("rust-http3-test-tool/1.0")
-            .timeout(std::time::Duration::from_secs(self.config.timeout_seconds))
             .build()
             .context("创建 HTTP 客户端失败")?;
 
@@ -198,12 +321,25 @@ impl H3Tester {
         let ip_count = ips.len();
         info!("✅ DNS 解析完成，共找到 {} 个 IP 地址", ip_count);
 
+        // ech 探测: 各 IP 共用同一份 ECH 配置
+        let ech_config_list = if self.config.probe == "ech" {
+            self.resolve_ech_config(&client).await?
+        } else {
+            None
+        };
+
         // 4. 为每个 IP 地址测试 HTTP/3 连接
         let mut success_count = 0;
+        let mut probe_results = Vec::new();
         for (index, ip) in ips.iter().enumerate() {
             info!("\n🔄 正在测试第 {}/{} 个 IP: {}:{}", index + 1, ip_count, ip, self.config.port);
 
-            if let Err(e) = self.test_single_connection(*ip).await {
+            let outcome = match self.config.probe.as_str() {
+                "request" => self.test_single_connection(*ip, &mut probe_results).await,
+                probe => self.run_direct_probe(*ip, probe, ech_config_list.as_deref(), &mut probe_results).await,
+            };
+
+            if let Err(e) = outcome {
                 error!("❌ IP {} 测试失败: {:?}", ip, e);
             } else {
                 success_count += 1;
@@ -213,29 +349,53 @@ impl H3Tester {
 
         info!("\n📊 测试总结: {}/{} 个 IP 测试成功", success_count, ip_count);
 
+        if !probe_results.is_empty() {
+            info!("\n{}", h3_direct_test::generate_peer_params_report(&probe_results));
+            if probe_results.iter().any(|r| r.path_mtu.is_some()) {
+                info!("\n{}", h3_direct_test::generate_mtu_report(&probe_results));
+            }
+            if probe_results.iter().any(|r| r.race.is_some()) {
+                info!("\n{}", h3_direct_test::generate_race_report(&probe_results));
+            }
+            if probe_results.iter().any(|r| r.fallback.is_some()) {
+                info!("\n{}", h3_direct_test::generate_fallback_report(&probe_results));
+            }
+            if probe_results.iter().any(|r| r.ech.is_some()) {
+                info!("\n{}", h3_direct_test::generate_ech_report(&probe_results));
+            }
+            if probe_results.iter().any(|r| r.key_exchange.is_some()) {
+                info!("\n{}", h3_direct_test::generate_key_exchange_report(&probe_results));
+            }
+            if probe_results.iter().any(|r| r.certificate.is_some()) {
+                info!("\n{}", h3_direct_test::generate_certificate_report(&probe_results));
+            }
+        }
+
         Ok(())
     }
 
-    pub async fn test_single_connection(&self, ip: IpAddr) -> Result<()> {
-        // 1. 加载证书
-        let mut roots = rustls::RootCertStore::empty();
-        match load_native_certs() {
-            Ok(certs) => {
-                for cert in certs {
-                    if let Err(e) = roots.add(cert) {
-                        error!("解析信任锚失败: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                error!("加载系统证书失败: {}", e);
-            }
+    // 成功时把对端 SETTINGS 与传输参数记入 results，参与各 IP 的对比
+    pub async fn test_single_connection(
+        &self,
+        ip: IpAddr,
+        results: &mut Vec<h3_direct_test::H3TestResult>,
+    ) -> Result<()> {
+        let qlog = self.qlog_dir()?;
+        let outcome = self.request_single_connection(ip, qlog.as_ref()).await;
+        if let Some(qlog) = &qlog {
+            log_qlog_traces(qlog.finish(outcome.is_ok()));
         }
+        results.push(outcome?);
+        Ok(())
+    }
+
+    async fn request_single_connection(&self, ip: IpAddr, qlog: Option<&QlogDir>) -> Result<h3_direct_test::H3TestResult> {
+        // 1. 加载信任根
+        let trust = self.config.trust.build().context("加载 TLS 信任配置失败")?;
 
-        // 3. 配置 TLS
-        let mut tls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(roots)
-            .with_no_client_auth();
+        // 3. 配置 TLS，验证的同时记录对端证书链与客户端证书请求
+        let certificate = CertCapture::default();
+        let mut tls_config = recording_client_config(&trust, &certificate)?;
 
         tls_config.enable_early_data = true;
         tls_config.alpn_protocols = vec![ALPN.into()];
@@ -244,10 +404,18 @@ impl H3Tester {
         let mut client_endpoint = quinn::Endpoint::client("[::]:0".parse().unwrap())
             .context("创建 QUIC 客户端端点失败")?;
 
-        let client_config = quinn::ClientConfig::new(Arc::new(
-            quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)
-                .context("创建 QUIC TLS 配置失败")?,
-        ));
+        // 包装 TLS 会话以记录对端传输参数
+        let transport_params = TransportParamsCapture::default();
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)
+            .context("创建 QUIC TLS 配置失败")?;
+        let mut client_config = quinn::ClientConfig::new(Arc::new(CapturingCryptoConfig::new(
+            Arc::new(crypto),
+            transport_params.clone(),
+        )));
+        let mut transport_config = self.config.transport.build();
+        let trace = qlog.map(|dir| dir.trace(&ip.to_string(), &self.config.domain)).transpose()?;
+        transport_config.qlog_stream(trace.as_ref().and_then(|trace| trace.stream()));
+        client_config.transport_config(Arc::new(transport_config));
         client_endpoint.set_default_client_config(client_config);
 
         // 5. 建立连接
@@ -256,26 +424,44 @@ impl H3Tester {
         let conn = client_endpoint
             .connect(socket_addr, &self.config.domain)
             .context(format!("连接建立失败: {}", socket_addr))?
-            .await
-            .context(format!("连接超时或被拒绝: {}", socket_addr))?;
+            .await;
+        if let Some(report) = certificate.report(&self.config.domain) {
+            log_certificate(&report);
+        }
+        if let Some(group) = certificate.kx_group() {
+            info!("🔑 协商的密钥交换组: {}", group);
+        }
+        let conn = conn.context(format!("连接超时或被拒绝: {}", socket_addr))?;
 
         let connect_time = start.elapsed();
         info!("✅ QUIC 连接建立成功，耗时: {:?}", connect_time);
 
         // 6. 创建 H3 客户端
-        let quinn_conn = h3_quinn::Connection::new(conn);
+        let quic_conn = conn.clone();
+        let peer_settings = SettingsCapture::default();
+        let quinn_conn = CapturingConnection::new(h3_quinn::Connection::new(conn), peer_settings.clone(), trace);
 
-        let (driver, mut send_request) = h3::client::new(quinn_conn)
+        let mut h3_builder = h3::client::builder();
+        h3_builder.send_grease(self.config.send_grease);
+        if let Some(size) = self.config.max_field_section_size {
+            h3_builder.max_field_section_size(size);
+        }
+        let (mut driver, mut send_request) = h3_builder
+            .build::<_, _, bytes::Bytes>(quinn_conn)
             .await
             .context("创建 H3 客户端失败")?;
 
+        tokio::spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
         // 7. 发送请求
-        let uri = format!("https://{}{}", self.config.domain, self.config.path);
-        info!("📡 发送 HTTP/3 请求: {}", uri);
+        // :authority 由 URI 决定，与 SNI (domain) 可以不同
+        let uri = self.request_uri(ip);
+        info!("📡 发送 HTTP/3 请求: {} (SNI {})", uri, self.config.domain);
 
         let req = http::Request::builder()
             .uri(uri)
-            .header("Host", &self.config.domain)
             .header("User-Agent", "rust-http3-test-tool/1.0")
             .body(())
             .map_err(|e| anyhow!("构建请求失败: {}", e))?;
@@ -305,12 +491,488 @@ impl H3Tester {
         }
 
         info!("✅ HTTP/3 测试成功！状态码: {}, 响应大小: {} 字节", status, total_bytes);
+        if let Some(client_auth) = certificate.client_auth(Some(true)) {
+            info!("🔑 客户端证书: 对端{}请求，本地{}出示",
+                  if client_auth.requested { "" } else { "未" },
+                  if client_auth.presented { "已" } else { "未" });
+        }
+
+        let path = quic_conn.stats().path;
+        info!("📏 路径 MTU: {} (探测 {} 次，丢失 {} 次，黑洞 {} 次)",
+              path.current_mtu, path.sent_plpmtud_probes, path.lost_plpmtud_probes, path.black_holes_detected);
+
+        // 收到响应后对端 SETTINGS 已经到达
+        let peer_settings = peer_settings.get();
+        let transport_params = transport_params.get();
+        if let Some(settings) = &peer_settings {
+            info!("⚙️ 对端 SETTINGS: {}", serde_json::to_string(settings)?);
+        }
+        if let Some(params) = &transport_params {
+            info!("⚙️ 对端传输参数: {}", serde_json::to_string(params)?);
+        }
 
         // 清理资源
         drop(client_endpoint);
 
+        Ok(h3_direct_test::H3TestResult {
+            success: true,
+            response_status: Some(status.as_u16()),
+            response_size: Some(total_bytes),
+            alpn_protocol: Some("h3".to_string()),
+            cipher_suite: certificate.cipher_suite(),
+            peer_settings,
+            transport_params,
+            ..h3_direct_test::H3TestResult::empty(&self.direct_config(ip), start.elapsed().as_millis() as u64)
+        })
+    }
+
+    // --ech-config 指定时直接使用，否则通过 DoH 查询域名的 HTTPS 记录
+    async fn resolve_ech_config(&self, client: &Client) -> Result<Option<Vec<u8>>> {
+        if let Some(encoded) = &self.config.ech_config {
+            let list = general_purpose::STANDARD.decode(encoded.trim()).context("无效的 --ech-config")?;
+            return Ok(Some(list));
+        }
+
+        let list = query_https_ech_config(client, &self.config.domain, &self.config.doh_server).await?;
+        match &list {
+            Some(list) => info!("🔐 HTTPS 记录中的 ECH 配置: {} 字节，public_name {}",
+                                list.len(), ech::public_names(list).join(", ")),
+            None => info!("🔐 HTTPS 记录中没有 ECH 配置，将不发送 ECH"),
+        }
+        Ok(list)
+    }
+
+    // 子命令的目标 IP: --ip 指定，否则通过 DoH 解析 --domain 取第一个地址
+    async fn resolve_target_ip(&self, matches: &ArgMatches) -> Result<IpAddr> {
+        if let Some(ip) = matches.get_one::<String>("ip") {
+            return ip.parse::<IpAddr>().context("无效的 --ip");
+        }
+
+        let client = self.http_client_builder()?.build()
+            .context("创建 HTTP 客户端失败")?;
+        let record_type = if self.config.prefer_ipv6 { RecordType::AAAA } else { RecordType::A };
+        query_dns_over_https(&client, &self.config.domain, record_type, &self.config.doh_server)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("未找到任何 IP 地址"))
+    }
+
+    // 下载吞吐: 在同一 IP 上分别通过 h3 与 h2 流式下载
+    pub async fn run_download(&self, matches: &ArgMatches) -> Result<()> {
+        let target_ip = self.resolve_target_ip(matches).await?;
+        let number = |name: &str| -> Result<Option<u64>> {
+            matches
+                .get_one::<String>(name)
+                .map(|s| s.parse::<u64>())
+                .transpose()
+                .with_context(|| format!("无效的 --{}", name))
+        };
+
+        let max_bytes = number("bytes")?;
+        let url = matches.get_one::<String>("url").map(|u| u.parse::<http::Uri>()).transpose().context("无效的 --url")?;
+        let host = url
+            .as_ref()
+            .and_then(|u| u.host().map(str::to_string))
+            .or_else(|| matches.get_one::<String>("host").cloned())
+            .unwrap_or_else(|| self.config.domain.clone());
+        let path = match (&url, max_bytes) {
+            (Some(url), _) => url.path_and_query().map(|p| p.to_string()).unwrap_or_else(|| "/".to_string()),
+            (None, Some(bytes)) => h3_throughput::DEFAULT_DOWNLOAD_PATH_TEMPLATE.replace("{bytes}", &bytes.to_string()),
+            (None, None) => self.config.path.clone(),
+        };
+
+        let config = h3_throughput::ThroughputConfig {
+            target_ip,
+            port: url.as_ref().and_then(|u| u.port_u16()).unwrap_or(self.config.port),
+            sni: matches.get_one::<String>("sni").cloned().unwrap_or_else(|| host.clone()),
+            host,
+            path,
+            // 指定 --url 时 --bytes 表示读取上限；否则由服务器按 bytes 参数返回
+            max_bytes: url.as_ref().and(max_bytes),
+            sample_interval_ms: number("sample-interval")?.unwrap_or(250),
+            stall_threshold_ms: number("stall-threshold")?.unwrap_or(500),
+            timeout_seconds: self.config.timeout_seconds,
+            user_agent: Some("rust-http3-test-tool/1.0".to_string()),
+            transport: self.config.transport.clone(),
+        };
+
+        info!("📥 开始下载测试: https://{}{} -> {} (SNI {})",
+              config.host, config.path, config.target_ip, config.sni);
+
+        let protocol = matches.get_one::<String>("protocol").unwrap().as_str();
+        // h3 与 h2 使用同一信任配置与客户端证书
+        let tester = self.direct_tester(&self.config.trust)?;
+        let mut results = Vec::new();
+        if matches!(protocol, "h3" | "both") {
+            let result = h3_throughput::download_h3(&tester, &config).await;
+            log_qlog_traces(tester.finish_qlog(result.completed));
+            results.push(result);
+        }
+        if matches!(protocol, "h2" | "both") {
+            results.push(h3_throughput::download_h2(&tester, &config).await);
+        }
+        if results.is_empty() {
+            return Err(anyhow!("不支持的下载协议: {}", protocol));
+        }
+
+        info!("📋 下载结果:\n{}", h3_throughput::generate_download_report(&results));
+        info!("📋 JSON:\n{}", serde_json::to_string_pretty(&results)?);
+
+        if results.iter().any(|r| r.completed) {
+            Ok(())
+        } else {
+            Err(anyhow!("所有协议的下载均失败"))
+        }
+    }
+
+    // 上传吞吐: 通过原生 h3 请求流 POST 生成的负载
+    pub async fn run_upload(&self, matches: &ArgMatches) -> Result<()> {
+        let target_ip = self.resolve_target_ip(matches).await?;
+        let number = |name: &str| -> Result<Option<u64>> {
+            matches
+                .get_one::<String>(name)
+                .map(|s| s.parse::<u64>())
+                .transpose()
+                .with_context(|| format!("无效的 --{}", name))
+        };
+
+        let payload_bytes = number("bytes")?.unwrap_or(10 * 1024 * 1024);
+        let url = matches.get_one::<String>("url").map(|u| u.parse::<http::Uri>()).transpose().context("无效的 --url")?;
+        let host = url
+            .as_ref()
+            .and_then(|u| u.host().map(str::to_string))
+            .or_else(|| matches.get_one::<String>("host").cloned())
+            .unwrap_or_else(|| self.config.domain.clone());
+        let path = url
+            .as_ref()
+            .and_then(|u| u.path_and_query().map(|p| p.to_string()))
+            .unwrap_or_else(|| h3_throughput::DEFAULT_UPLOAD_PATH.to_string());
+
+        let config = h3_throughput::ThroughputConfig {
+            target_ip,
+            port: url.as_ref().and_then(|u| u.port_u16()).unwrap_or(self.config.port),
+            sni: matches.get_one::<String>("sni").cloned().unwrap_or_else(|| host.clone()),
+            host,
+            path,
+            max_bytes: None,
+            sample_interval_ms: number("sample-interval")?.unwrap_or(250),
+            stall_threshold_ms: number("stall-threshold")?.unwrap_or(500),
+            timeout_seconds: self.config.timeout_seconds,
+            user_agent: Some("rust-http3-test-tool/1.0".to_string()),
+            transport: self.config.transport.clone(),
+        };
+
+        info!("📤 开始上传测试: {} 字节 -> https://{}{} ({}, SNI {})",
+              payload_bytes, config.host, config.path, config.target_ip, config.sni);
+
+        let tester = self.direct_tester(&self.config.trust)?;
+        let result = h3_throughput::upload_h3(&tester, &config, payload_bytes).await;
+        log_qlog_traces(tester.finish_qlog(result.completed));
+
+        info!("📋 上传结果:\n{}", h3_throughput::generate_upload_report(&result));
+        info!("📋 JSON:\n{}", serde_json::to_string_pretty(&result)?);
+
+        if result.completed {
+            Ok(())
+        } else {
+            Err(anyhow!("上传失败: {}", result.error_message.unwrap_or_default()))
+        }
+    }
+
+    // 矩阵测试: 展开各任务的组合，同一 DoH 服务器下每个解析域名只查询一次
+    pub async fn run_matrix(&self, matches: &ArgMatches) -> Result<()> {
+        let list = |name: &str| {
+            matches.get_one::<String>(name).map(|s| {
+                s.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect::<Vec<_>>()
+            })
+        };
+        let number = |name: &str| -> Result<Option<usize>> {
+            matches
+                .get_one::<String>(name)
+                .map(|s| s.parse::<usize>())
+                .transpose()
+                .with_context(|| format!("无效的 --{}", name))
+        };
+
+        let tasks: Vec<h3_matrix::MatrixTask> = match matches.get_one::<String>("config") {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).with_context(|| format!("读取矩阵配置失败: {}", path))?;
+                match serde_json::from_str::<Vec<h3_matrix::MatrixTask>>(&content) {
+                    Ok(tasks) => tasks,
+                    Err(_) => vec![serde_json::from_str(&content).context("无效的矩阵配置")?],
+                }
+            }
+            None => {
+                let domains = list("resolve-domains").unwrap_or_else(|| vec![self.config.domain.clone()]);
+                let ports = match list("ports") {
+                    Some(ports) => ports
+                        .iter()
+                        .map(|p| p.parse::<u16>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .context("无效的 --ports")?,
+                    None => vec![self.config.port],
+                };
+                vec![h3_matrix::MatrixTask {
+                    sni_hosts: list("sni-hosts").unwrap_or_else(|| domains.clone()),
+                    host_headers: list("host-headers")
+                        .or_else(|| self.config.host_header.clone().map(|h| vec![h]))
+                        .unwrap_or_else(|| domains.clone()),
+                    paths: list("paths").unwrap_or_else(|| vec![self.config.path.clone()]),
+                    ports,
+                    resolve_domains: domains,
+                    doh_url: None,
+                    prefer_ipv6: None,
+                    direct_ips: None,
+                    max_ips_per_domain: number("max-ips")?,
+                    trust: None,
+                }]
+            }
+        };
+
+        let client = self.http_client_builder()?.build()
+            .context("创建 HTTP 客户端失败")?;
+        let default_tester = self.direct_tester(&self.config.trust)?;
+        let parallel = number("parallel")?.unwrap_or(h3_matrix::DEFAULT_MATRIX_CONCURRENCY);
+
+        let mut dns_cache: BTreeMap<(String, String), Vec<IpAddr>> = BTreeMap::new();
+        let mut cells = Vec::new();
+        for task in &tasks {
+            let doh_url = task.doh_url.clone().unwrap_or_else(|| self.config.doh_server.clone());
+            let mut ips = BTreeMap::new();
+            for domain in &task.resolve_domains {
+                if let Some(direct_ips) = &task.direct_ips {
+                    ips.insert(domain.clone(), direct_ips.clone());
+                    continue;
+                }
+                let key = (doh_url.clone(), domain.clone());
+                if !dns_cache.contains_key(&key) {
+                    let mut resolved = Vec::new();
+                    for record_type in [RecordType::A, RecordType::AAAA] {
+                        match query_dns_over_https(&client, domain, record_type, &doh_url).await {
+                            Ok(found) => resolved.extend(found),
+                            Err(e) => error!("❌ {} {:?} 查询失败: {:?}", domain, record_type, e),
+                        }
+                    }
+                    info!("📍 {} -> {:?}", domain, resolved);
+                    dns_cache.insert(key.clone(), resolved);
+                }
+                ips.insert(domain.clone(), dns_cache[&key].clone());
+            }
+
+            // 任务可单独指定 trust 配置块，否则使用命令行的信任设置
+            let task_tester = task.trust.as_ref().map(|trust| self.direct_tester(trust)).transpose()?;
+            let tester = task_tester.as_ref().unwrap_or(&default_tester);
+
+            let cases = task.expand(&ips);
+            info!("🧮 {} 个解析域名 × {} SNI × {} Host × {} 路径 × {} 端口，共 {} 个组合",
+                  task.resolve_domains.len(), task.sni_hosts.len(), task.host_headers.len(),
+                  task.paths.len(), task.ports.len(), cases.len());
+            let task_cells =
+                h3_matrix::run_matrix(tester, cases, self.config.timeout_seconds, &self.config.transport, parallel).await;
+            log_qlog_traces(task_cells.iter().flat_map(|c| c.qlog_files.clone()).collect());
+            cells.extend(task_cells);
+        }
+
+        info!("📋 矩阵结果:\n{}", h3_matrix::generate_matrix_report(&cells));
+        info!("📋 JSON:\n{}", serde_json::to_string_pretty(&cells)?);
+
+        if cells.iter().any(|c| c.works) {
+            Ok(())
+        } else {
+            Err(anyhow!("矩阵中没有可用的组合"))
+        }
+    }
+
+    // 压测: 对单个 IP/SNI/Host 组合运行 h3 (可选 h2 对照) 负载
+    pub async fn run_bench(&self, matches: &ArgMatches) -> Result<()> {
+        let count = |name: &str| -> Result<usize> {
+            matches
+                .get_one::<String>(name)
+                .unwrap()
+                .parse::<usize>()
+                .with_context(|| format!("无效的 --{}", name))
+        };
+
+        let target_ip = self.resolve_target_ip(matches).await?;
+
+        let bench_config = h3_bench::BenchConfig {
+            target_ip,
+            port: self.config.port,
+            sni: matches.get_one::<String>("sni").cloned().unwrap_or_else(|| self.config.domain.clone()),
+            host: matches
+                .get_one::<String>("host")
+                .or(self.config.host_header.as_ref())
+                .cloned()
+                .unwrap_or_else(|| self.config.domain.clone()),
+            path: self.config.path.clone(),
+            connections: count("connections")?.max(1),
+            streams: count("streams")?.max(1),
+            total_requests: count("requests")?,
+            duration_seconds: matches
+                .get_one::<String>("duration")
+                .map(|s| s.parse::<u64>())
+                .transpose()
+                .context("无效的 --duration")?,
+            timeout_seconds: self.config.timeout_seconds,
+            user_agent: Some("rust-http3-test-tool/1.0".to_string()),
+            transport: self.config.transport.clone(),
+        };
+
+        info!("🏋️ 开始压测: {}:{} (SNI {}, Host {})，{} 连接 × {} 流",
+              bench_config.target_ip, bench_config.port, bench_config.sni, bench_config.host,
+              bench_config.connections, bench_config.streams);
+
+        let protocol = matches.get_one::<String>("protocol").unwrap().as_str();
+        // h3 与 h2 使用同一信任配置与客户端证书
+        let tester = self.direct_tester(&self.config.trust)?;
+        let mut reports = Vec::new();
+        if matches!(protocol, "h3" | "both") {
+            let report = h3_bench::run_h3_bench(&tester, &bench_config).await;
+            log_qlog_traces(tester.finish_qlog(report.requests_failed == 0));
+            reports.push(report);
+        }
+        if matches!(protocol, "h2" | "both") {
+            reports.push(h3_bench::run_h2_bench(&tester, &bench_config).await?);
+        }
+        if reports.is_empty() {
+            return Err(anyhow!("不支持的压测协议: {}", protocol));
+        }
+
+        info!("📋 压测结果:\n{}", h3_bench::generate_bench_report(&reports));
+        info!("📋 JSON:\n{}", serde_json::to_string_pretty(&reports)?);
         Ok(())
     }
+
+    // 原生探测与 request 共用的目标配置：SNI 为 domain，:authority 为 host_header (非 443 端口时带端口)
+    fn direct_config(&self, ip: IpAddr) -> h3_direct_test::H3TestConfig {
+        h3_direct_test::H3TestConfig {
+            host_header: self.config.host_header.clone(),
+            test_path: self.config.path.clone(),
+            timeout_seconds: self.config.timeout_seconds,
+            max_field_section_size: self.config.max_field_section_size,
+            send_grease: self.config.send_grease,
+            max_concurrent_requests: self.config.concurrency,
+            connect_protocol: self.config.connect_protocol.clone(),
+            connect_path: self.config.connect_path.clone(),
+            masque_target: self.config.masque_target.clone(),
+            masque_uri_template: self.config.masque_uri_template.clone(),
+            transport: self.config.transport.clone(),
+            ..h3_direct_test::H3TestConfig::new(&self.config.domain, ip, self.config.port)
+        }
+    }
+
+    // request 探测的请求 URI，与原生探测的 :authority 规则一致
+    fn request_uri(&self, ip: IpAddr) -> String {
+        let config = self.direct_config(ip);
+        config.request_uri(&config.test_path)
+    }
+
+    // 使用 h3_direct_test 中的原生探测
+    pub async fn run_direct_probe(
+        &self,
+        ip: IpAddr,
+        probe: &str,
+        ech_config_list: Option<&[u8]>,
+        results: &mut Vec<h3_direct_test::H3TestResult>,
+    ) -> Result<()> {
+        let mut config = self.direct_config(ip);
+
+        let tester = self.direct_tester(&self.config.trust)?;
+
+        if probe == "sweep" {
+            let steps = tester
+                .sweep_grease_and_field_section(&config, &self.config.field_section_limits)
+                .await;
+            info!("📋 扫描结果:\n{}", h3_direct_test::generate_sweep_report(&config, &steps));
+            log_qlog_traces(tester.finish_qlog(steps.iter().all(|s| s.success)));
+
+            return if steps.iter().any(|s| s.success) {
+                Ok(())
+            } else {
+                Err(anyhow!("所有扫描组合均失败"))
+            };
+        }
+
+        // 探测中途出错时同样结束 qlog，保留失败连接的 trace
+        let result = match self.direct_probe(&tester, &mut config, probe, ech_config_list).await {
+            Ok(result) => result,
+            Err(e) => {
+                log_qlog_traces(tester.finish_qlog(false));
+                return Err(e);
+            }
+        };
+        log_qlog_traces(tester.finish_qlog(result.success));
+        info!("📋 探测结果:\n{}", serde_json::to_string_pretty(&result)?);
+
+        let outcome = if result.success {
+            Ok(())
+        } else {
+            Err(anyhow!(result.error_message.clone().unwrap_or_else(|| "探测失败".to_string())))
+        };
+        results.push(result);
+        outcome
+    }
+
+    // 按探测类型运行单个原生探测
+    async fn direct_probe(
+        &self,
+        tester: &h3_direct_test::H3Tester,
+        config: &mut h3_direct_test::H3TestConfig,
+        probe: &str,
+        ech_config_list: Option<&[u8]>,
+    ) -> Result<h3_direct_test::H3TestResult> {
+        let result = match probe {
+            "settings" => tester.test_http3_connection(config).await?,
+            "datagram" => {
+                config.enable_datagram = true;
+                tester.test_http3_connection(config).await?
+            }
+            "extended-connect" => {
+                config.enable_extended_connect = true;
+                tester.test_http3_connection(config).await?
+            }
+            "connect-udp" => tester.test_connect_udp(config).await?,
+            "multiplex" => {
+                let result = tester.test_multiplexed(config).await?;
+                if let Some(multiplex) = &result.multiplex {
+                    info!("📋 多路复用对比:\n{}", h3_direct_test::generate_multiplex_report(config, multiplex));
+                }
+                result
+            }
+            "alt-svc" => {
+                let result = tester.test_alt_svc(config).await?;
+                if let Some(alt_svc) = &result.alt_svc {
+                    info!("📋 Alt-Svc: 通告 h3 {}，h3 可用 {:?}", alt_svc.advertises_h3, alt_svc.h3_works);
+                }
+                result
+            }
+            "race" => tester.test_race(config, self.config.race_head_start_ms).await?,
+            "fallback" => tester.test_fallback(config).await?,
+            "ech" => tester.test_ech(config, ech_config_list).await?,
+            "pq" => tester.test_key_exchange(config).await?,
+            "migration" => tester.test_migration(config).await?,
+            "mtu" => tester.test_mtu(config, &self.config.mtu_sizes).await?,
+            "congestion" => {
+                let result = tester
+                    .test_congestion_controllers(config, &self.config.congestion_controllers)
+                    .await?;
+                if let Some(runs) = &result.congestion {
+                    info!("📋 拥塞控制对比:\n{}", h3_direct_test::generate_congestion_report(config, runs));
+                }
+                result
+            }
+            "idle" => {
+                let result = tester.test_idle_timeout(config, &self.config.idle_intervals_ms).await?;
+                if let Some(idle) = &result.idle {
+                    info!("📋 空闲超时测试:\n{}", h3_direct_test::generate_idle_report(config, idle));
+                }
+                result
+            }
+            _ => return Err(anyhow!("不支持的探测类型: {}", probe)),
+        };
+        Ok(result)
+    }
 }
 
 // --- 主程序入口 ---
@@ -332,7 +994,15 @@ pub async fn run() -> Result<()> {
                 .long("domain")
                 .value_name("DOMAIN")
                 .help("测试域名")
-                .default_value("local-aria2-webui.masx200.ddns-ip.net"),
+                .default_value("local-aria2-webui.masx200.ddns-ip.net")
+                .global(true),
+        )
+        .arg(
+            Arg::new("host-header")
+                .long("host-header")
+                .value_name("HOST")
+                .help("HTTP/3 请求的 :authority (默认与 --domain 相同，--domain 仍用于 DNS 与 SNI)")
+                .global(true),
         )
         .arg(
             Arg::new("port")
@@ -340,7 +1010,8 @@ pub async fn run() -> Result<()> {
                 .long("port")
                 .value_name("PORT")
                 .help("端口号")
-                .default_value("443"),
+                .default_value("443")
+                .global(true),
         )
         .arg(
             Arg::new("path")
@@ -348,31 +1019,469 @@ pub async fn run() -> Result<()> {
                 .long("path")
                 .value_name("PATH")
                 .help("请求路径")
-                .default_value("/"),
+                .default_value("/")
+                .global(true),
         )
         .arg(
             Arg::new("timeout")
                 .long("timeout")
                 .value_name("SECONDS")
                 .help("超时时间（秒）")
-                .default_value("10"),
+                .default_value("10")
+                .global(true),
         )
         .arg(
             Arg::new("doh-server")
                 .long("doh-server")
                 .value_name("URL")
                 .help("DNS over HTTPS 服务器 URL")
-                .default_value("https://xget.a1u06h9fe9y5bozbmgz3.qzz.io/cloudflare-dns.com/dns-query"),
+                .default_value("https://xget.a1u06h9fe9y5bozbmgz3.qzz.io/cloudflare-dns.com/dns-query")
+                .global(true),
         )
         .arg(
             Arg::new("prefer-ipv6")
                 .long("prefer-ipv6")
                 .help("优先使用 IPv6 地址")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("probe")
+                .long("probe")
+                .value_name("KIND")
+                .help("探测类型: request, settings, datagram, extended-connect, connect-udp, sweep, multiplex, idle, migration, mtu, congestion, alt-svc, race, fallback, ech, pq")
+                .default_value("request"),
+        )
+        .arg(
+            Arg::new("connect-protocol")
+                .long("connect-protocol")
+                .value_name("PROTOCOL")
+                .help("扩展 CONNECT 使用的 :protocol (默认 webtransport)"),
+        )
+        .arg(
+            Arg::new("connect-path")
+                .long("connect-path")
+                .value_name("PATH")
+                .help("扩展 CONNECT 的请求路径 (默认与 --path 相同)"),
+        )
+        .arg(
+            Arg::new("masque-target")
+                .long("masque-target")
+                .value_name("HOST:PORT")
+                .help("CONNECT-UDP 隧道目标 DNS 服务器 (默认 1.1.1.1:53)"),
+        )
+        .arg(
+            Arg::new("masque-uri-template")
+                .long("masque-uri-template")
+                .value_name("TEMPLATE")
+                .help("CONNECT-UDP URI 模板 (默认 /.well-known/masque/udp/{target_host}/{target_port}/)"),
+        )
+        .arg(
+            Arg::new("no-grease")
+                .long("no-grease")
+                .help("不发送 GREASE 设置、帧和流")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("max-field-section-size")
+                .long("max-field-section-size")
+                .value_name("BYTES")
+                .help("通告的 SETTINGS_MAX_FIELD_SECTION_SIZE (默认不限制)"),
+        )
+        .arg(
+            Arg::new("field-section-limits")
+                .long("field-section-limits")
+                .value_name("LIST")
+                .help("sweep 探测使用的字段区上限，逗号分隔 (默认 16384,8192,4096,1024,256,64)"),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .value_name("N")
+                .help("multiplex 探测的并发请求数")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("idle-timeout")
+                .long("idle-timeout")
+                .value_name("SECONDS")
+                .help("QUIC max_idle_timeout (默认 10 秒；idle 探测会按最长空闲时长自动放大)"),
+        )
+        .arg(
+            Arg::new("keep-alive-interval")
+                .long("keep-alive-interval")
+                .value_name("SECONDS")
+                .help("PING 保活间隔 (默认不保活；idle 探测的保活组默认 5 秒)"),
+        )
+        .arg(
+            Arg::new("max-streams")
+                .long("max-streams")
+                .value_name("N")
+                .help("允许对端打开的并发双向流数 (默认 100)"),
+        )
+        .arg(
+            Arg::new("stream-window")
+                .long("stream-window")
+                .value_name("BYTES")
+                .help("单个流的接收窗口"),
+        )
+        .arg(
+            Arg::new("conn-window")
+                .long("conn-window")
+                .value_name("BYTES")
+                .help("整个连接的接收窗口"),
+        )
+        .arg(
+            Arg::new("send-window")
+                .long("send-window")
+                .value_name("BYTES")
+                .help("整个连接的发送窗口"),
+        )
+        .arg(
+            Arg::new("initial-rtt")
+                .long("initial-rtt")
+                .value_name("MS")
+                .help("尚未测得 RTT 时使用的估计值 (默认 333 毫秒)"),
+        )
+        .arg(
+            Arg::new("congestion")
+                .long("congestion")
+                .value_name("ALGO")
+                .help("拥塞控制算法: newreno, cubic (默认), bbr"),
+        )
+        .arg(
+            Arg::new("congestion-controllers")
+                .long("congestion-controllers")
+                .value_name("LIST")
+                .help("congestion 探测依次对比的算法，逗号分隔 (默认 newreno,cubic,bbr)"),
+        )
+        .arg(
+            Arg::new("initial-mtu")
+                .long("initial-mtu")
+                .value_name("BYTES")
+                .help("QUIC 初始 MTU (默认 1200)"),
+        )
+        .arg(
+            Arg::new("mtu-upper-bound")
+                .long("mtu-upper-bound")
+                .value_name("BYTES")
+                .help("MTU 探测上限 (默认 1452)"),
+        )
+        .arg(
+            Arg::new("min-mtu")
+                .long("min-mtu")
+                .value_name("BYTES")
+                .help("MTU 下限 (默认 1200)"),
+        )
+        .arg(
+            Arg::new("mtu-sizes")
+                .long("mtu-sizes")
+                .value_name("LIST")
+                .help("mtu 探测依次固定的 MTU，逗号分隔 (默认 1500,1452,1400,1350,1280,1200)"),
+        )
+        .arg(
+            Arg::new("race-head-start")
+                .long("race-head-start")
+                .value_name("MS")
+                .help("race 探测中 h3 领先 h2 / http/1.1 尝试的毫秒数 (默认 250)"),
+        )
+        .arg(
+            Arg::new("ech-config")
+                .long("ech-config")
+                .value_name("BASE64")
+                .help("ech 探测使用的 ECHConfigList (base64，与 HTTPS 记录中 ech 参数的写法相同)，默认通过 DoH 查询 HTTPS 记录"),
+        )
+        .arg(
+            Arg::new("idle-intervals")
+                .long("idle-intervals")
+                .value_name("LIST")
+                .help("idle 探测的空闲时长 (秒)，逗号分隔 (默认 1,5,15,30,60,120)"),
+        )
+        .arg(
+            Arg::new("trust")
+                .long("trust")
+                .value_name("ROOTS")
+                .help("信任根: native (系统证书库，默认), webpki (内置 Mozilla 根), pem (仅 --ca-file)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("ca-file")
+                .long("ca-file")
+                .value_name("PEM")
+                .help("PEM 证书包，追加到信任根中；--trust pem 时只信任该文件")
+                .global(true),
+        )
+        .arg(
+            Arg::new("pin-spki")
+                .long("pin-spki")
+                .value_name("LIST")
+                .help("SPKI 固定值 sha256/<base64>，逗号分隔；证书链中任一公钥匹配才接受")
+                .global(true),
+        )
+        .arg(
+            Arg::new("insecure")
+                .long("insecure")
+                .help("跳过证书链与主机名验证 (仍记录验证结果，--pin-spki 照常生效)")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("client-cert")
+                .long("client-cert")
+                .value_name("PEM")
+                .help("mTLS 客户端证书链 (PEM)，对端请求时出示；需同时指定 --client-key")
+                .global(true),
+        )
+        .arg(
+            Arg::new("client-key")
+                .long("client-key")
+                .value_name("PEM")
+                .help("mTLS 客户端私钥 (PEM)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("keylog")
+                .long("keylog")
+                .value_name("FILE")
+                .help("以 NSS Key Log 格式追加写入 TLS 密钥，用于 Wireshark 解密 (默认读取 SSLKEYLOGFILE 环境变量)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("qlog-dir")
+                .long("qlog-dir")
+                .value_name("DIR")
+                .help("为每个原生 QUIC 连接写入 qlog (JSON-SEQ，可在 qvis 中打开)；默认只保留失败探测的文件")
+                .global(true),
+        )
+        .arg(
+            Arg::new("qlog-keep-all")
+                .long("qlog-keep-all")
+                .help("成功探测的 qlog 也保留")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("key-exchange")
+                .long("key-exchange")
+                .value_name("KIND")
+                .help("ClientHello 提供的密钥交换组: classic (X25519 / P-256 / P-384，默认), pq (优先 X25519MLKEM768，需 --features pq 编译)")
+                .global(true),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("h2load 风格的 HTTP/3 压测 (连接数 × 并发流 × 总请求数或时长)")
+                .arg(
+                    Arg::new("ip")
+                        .long("ip")
+                        .value_name("IP")
+                        .help("目标 IP (默认通过 DoH 解析 --domain 取第一个地址)"),
+                )
+                .arg(
+                    Arg::new("sni")
+                        .long("sni")
+                        .value_name("HOST")
+                        .help("TLS SNI (默认与 --domain 相同)"),
+                )
+                .arg(
+                    Arg::new("host")
+                        .long("host")
+                        .value_name("HOST")
+                        .help(":authority / Host (默认与 --domain 相同)"),
+                )
+                .arg(
+                    Arg::new("connections")
+                        .short('c')
+                        .long("connections")
+                        .value_name("N")
+                        .help("连接数")
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("streams")
+                        .short('m')
+                        .long("streams")
+                        .value_name("N")
+                        .help("每个连接上的并发流数")
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("requests")
+                        .short('n')
+                        .long("requests")
+                        .value_name("N")
+                        .help("总请求数")
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::new("duration")
+                        .short('D')
+                        .long("duration")
+                        .value_name("SECONDS")
+                        .help("按时长压测，指定后忽略 --requests"),
+                )
+                .arg(
+                    Arg::new("protocol")
+                        .long("protocol")
+                        .value_name("PROTO")
+                        .help("压测协议: h3, h2 (reqwest), both")
+                        .default_value("h3"),
+                ),
+        )
+        .subcommand(
+            Command::new("download")
+                .about("下载吞吐测试: 在同一 IP 上分别通过 h3 与 h2 流式下载并采样带宽")
+                .arg(
+                    Arg::new("ip")
+                        .long("ip")
+                        .value_name("IP")
+                        .help("目标 IP (默认通过 DoH 解析 --domain 取第一个地址)"),
+                )
+                .arg(
+                    Arg::new("sni")
+                        .long("sni")
+                        .value_name("HOST")
+                        .help("TLS SNI (默认与 Host 相同)"),
+                )
+                .arg(
+                    Arg::new("host")
+                        .long("host")
+                        .value_name("HOST")
+                        .help(":authority / Host (默认与 --domain 相同)"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("下载地址，其主机名作为 Host"),
+                )
+                .arg(
+                    Arg::new("bytes")
+                        .long("bytes")
+                        .value_name("N")
+                        .help("下载字节数；未指定 --url 时请求 /__down?bytes=N，否则读到 N 字节后停止"),
+                )
+                .arg(
+                    Arg::new("sample-interval")
+                        .long("sample-interval")
+                        .value_name("MS")
+                        .help("带宽采样间隔 (默认 250 毫秒)"),
+                )
+                .arg(
+                    Arg::new("stall-threshold")
+                        .long("stall-threshold")
+                        .value_name("MS")
+                        .help("超过该间隔未收到数据视为停顿 (默认 500 毫秒)"),
+                )
+                .arg(
+                    Arg::new("protocol")
+                        .long("protocol")
+                        .value_name("PROTO")
+                        .help("下载协议: h3, h2 (reqwest), both")
+                        .default_value("both"),
+                ),
+        )
+        .subcommand(
+            Command::new("upload")
+                .about("上传吞吐测试: 通过原生 h3 请求流 POST 生成的负载，统计上传带宽与服务器确认延迟")
+                .arg(
+                    Arg::new("ip")
+                        .long("ip")
+                        .value_name("IP")
+                        .help("目标 IP (默认通过 DoH 解析 --domain 取第一个地址)"),
+                )
+                .arg(
+                    Arg::new("sni")
+                        .long("sni")
+                        .value_name("HOST")
+                        .help("TLS SNI (默认与 Host 相同)"),
+                )
+                .arg(
+                    Arg::new("host")
+                        .long("host")
+                        .value_name("HOST")
+                        .help(":authority / Host (默认与 --domain 相同)"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("上传地址，其主机名作为 Host (默认 /__up)"),
+                )
+                .arg(
+                    Arg::new("bytes")
+                        .long("bytes")
+                        .value_name("N")
+                        .help("上传负载字节数 (默认 10 MiB)"),
+                )
+                .arg(
+                    Arg::new("sample-interval")
+                        .long("sample-interval")
+                        .value_name("MS")
+                        .help("带宽采样间隔 (默认 250 毫秒)"),
+                )
+                .arg(
+                    Arg::new("stall-threshold")
+                        .long("stall-threshold")
+                        .value_name("MS")
+                        .help("超过该间隔未能发送数据视为停顿 (默认 500 毫秒)"),
+                ),
+        )
+        .subcommand(
+            Command::new("matrix")
+                .about("SNI × Host × IP 矩阵测试: 展开解析域名、SNI、Host、路径与端口的所有组合")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .value_name("FILE")
+                        .help("矩阵任务 JSON 文件 (单个任务或任务数组)，指定后忽略下面的列表参数"),
+                )
+                .arg(
+                    Arg::new("resolve-domains")
+                        .long("resolve-domains")
+                        .value_name("LIST")
+                        .help("用于 DNS 解析的域名，逗号分隔 (默认 --domain)"),
+                )
+                .arg(
+                    Arg::new("sni-hosts")
+                        .long("sni-hosts")
+                        .value_name("LIST")
+                        .help("TLS SNI，逗号分隔 (默认与解析域名相同)"),
+                )
+                .arg(
+                    Arg::new("host-headers")
+                        .long("host-headers")
+                        .value_name("LIST")
+                        .help(":authority，逗号分隔 (默认 --host-header 或解析域名)"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .long("paths")
+                        .value_name("LIST")
+                        .help("请求路径，逗号分隔 (默认 --path)"),
+                )
+                .arg(
+                    Arg::new("ports")
+                        .long("ports")
+                        .value_name("LIST")
+                        .help("端口，逗号分隔 (默认 --port)"),
+                )
+                .arg(
+                    Arg::new("max-ips")
+                        .long("max-ips")
+                        .value_name("N")
+                        .help("每个解析域名最多测试的 IP 数"),
+                )
+                .arg(
+                    Arg::new("parallel")
+                        .long("parallel")
+                        .value_name("N")
+                        .help("同时进行的组合数 (默认 8)"),
+                ),
+        )
         .get_matches();
 
     let domain = matches.get_one::<String>("domain").unwrap().clone();
+    let host_header = matches.get_one::<String>("host-header").cloned();
     let port = matches
         .get_one::<String>("port")
         .unwrap()
@@ -386,18 +1495,183 @@ pub async fn run() -> Result<()> {
         .unwrap_or(10);
     let doh_server = matches.get_one::<String>("doh-server").unwrap().clone();
     let prefer_ipv6 = matches.get_flag("prefer-ipv6");
+    let probe = matches.get_one::<String>("probe").unwrap().clone();
+    let connect_protocol = matches.get_one::<String>("connect-protocol").cloned();
+    let connect_path = matches.get_one::<String>("connect-path").cloned();
+    let masque_target = matches.get_one::<String>("masque-target").cloned();
+    let masque_uri_template = matches.get_one::<String>("masque-uri-template").cloned();
+    let send_grease = !matches.get_flag("no-grease");
+    let max_field_section_size = matches
+        .get_one::<String>("max-field-section-size")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .context("无效的 --max-field-section-size")?;
+    let field_section_limits = match matches.get_one::<String>("field-section-limits") {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("无效的 --field-section-limits")?,
+        None => h3_direct_test::DEFAULT_FIELD_SECTION_SWEEP.to_vec(),
+    };
+
+    let concurrency = matches
+        .get_one::<String>("concurrency")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap_or(10);
+
+    let seconds_to_ms = |name: &str| -> Result<Option<u64>> {
+        matches
+            .get_one::<String>(name)
+            .map(|s| s.parse::<u64>().map(|secs| secs * 1000))
+            .transpose()
+            .with_context(|| format!("无效的 --{}", name))
+    };
+    let idle_intervals_ms = match matches.get_one::<String>("idle-intervals") {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.trim().parse::<u64>().map(|secs| secs * 1000))
+            .collect::<Result<Vec<_>, _>>()
+            .context("无效的 --idle-intervals")?,
+        None => h3_direct_test::DEFAULT_IDLE_PROBE_INTERVALS_MS.to_vec(),
+    };
+
+    let mtu_sizes = match matches.get_one::<String>("mtu-sizes") {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.trim().parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("无效的 --mtu-sizes")?,
+        None => h3_direct_test::DEFAULT_MTU_SWEEP.to_vec(),
+    };
+
+    fn number<T: std::str::FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<T>>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        matches
+            .get_one::<String>(name)
+            .map(|s| s.parse::<T>())
+            .transpose()
+            .with_context(|| format!("无效的 --{}", name))
+    }
+    let transport = QuicTransportOptions {
+        idle_timeout_ms: seconds_to_ms("idle-timeout")?,
+        keep_alive_interval_ms: seconds_to_ms("keep-alive-interval")?,
+        max_concurrent_bidi_streams: number(&matches, "max-streams")?,
+        max_concurrent_uni_streams: None,
+        stream_receive_window: number(&matches, "stream-window")?,
+        receive_window: number(&matches, "conn-window")?,
+        send_window: number(&matches, "send-window")?,
+        initial_rtt_ms: number(&matches, "initial-rtt")?,
+        initial_mtu: number(&matches, "initial-mtu")?,
+        min_mtu: number(&matches, "min-mtu")?,
+        mtu_upper_bound: number(&matches, "mtu-upper-bound")?,
+        fixed_mtu: None,
+        congestion_controller: matches
+            .get_one::<String>("congestion")
+            .map(|s| s.parse::<CongestionController>())
+            .transpose()
+            .map_err(|e| anyhow!(e))?,
+    };
+    let congestion_controllers = match matches.get_one::<String>("congestion-controllers") {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.parse::<CongestionController>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?,
+        None => CongestionController::ALL.to_vec(),
+    };
+
+    let race_head_start_ms = number(&matches, "race-head-start")?.unwrap_or(h3_direct_test::DEFAULT_RACE_HEAD_START_MS);
+
+    let trust = TlsTrustOptions {
+        roots: matches
+            .get_one::<String>("trust")
+            .map(|s| s.parse::<TrustRoots>())
+            .transpose()
+            .map_err(|e| anyhow!(e))?
+            .unwrap_or_default(),
+        ca_file: matches.get_one::<String>("ca-file").cloned(),
+        spki_pins: matches
+            .get_one::<String>("pin-spki")
+            .map(|list| list.split(',').map(|pin| pin.trim().to_string()).collect())
+            .unwrap_or_default(),
+        insecure: matches.get_flag("insecure"),
+        client_cert: matches.get_one::<String>("client-cert").cloned(),
+        client_key: matches.get_one::<String>("client-key").cloned(),
+        key_exchange: matches
+            .get_one::<String>("key-exchange")
+            .map(|s| s.parse::<KeyExchange>())
+            .transpose()
+            .map_err(|e| anyhow!(e))?
+            .unwrap_or_default(),
+        keylog: matches.get_one::<String>("keylog").cloned(),
+    };
 
     let config = H3TestConfig {
         domain,
+        host_header,
         port,
         path,
         doh_server,
         timeout_seconds: timeout,
         prefer_ipv6,
+        probe,
+        connect_protocol,
+        connect_path,
+        masque_target,
+        masque_uri_template,
+        send_grease,
+        max_field_section_size,
+        field_section_limits,
+        concurrency,
+        idle_intervals_ms,
+        mtu_sizes,
+        transport,
+        congestion_controllers,
+        race_head_start_ms,
+        trust,
+        ech_config: matches.get_one::<String>("ech-config").cloned(),
+        qlog_dir: matches.get_one::<String>("qlog-dir").cloned(),
+        qlog_keep_all: matches.get_flag("qlog-keep-all"),
     };
 
     let tester = H3Tester::new(config);
 
+    if let Some(bench_matches) = matches.subcommand_matches("bench") {
+        if let Err(e) = tester.run_bench(bench_matches).await {
+            error!("❌ 压测失败: {:?}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(download_matches) = matches.subcommand_matches("download") {
+        if let Err(e) = tester.run_download(download_matches).await {
+            error!("❌ 下载测试失败: {:?}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(matrix_matches) = matches.subcommand_matches("matrix") {
+        if let Err(e) = tester.run_matrix(matrix_matches).await {
+            error!("❌ 矩阵测试失败: {:?}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(upload_matches) = matches.subcommand_matches("upload") {
+        if let Err(e) = tester.run_upload(upload_matches).await {
+            error!("❌ 上传测试失败: {:?}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     if let Err(e) = tester.test_connection().await {
         error!("❌ 测试失败: {:?}", e);
         std::process::exit(1);
@@ -406,3 +1680,51 @@ pub async fn run() -> Result<()> {
     println!("\n✅ HTTP/3 测试完成！");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h3_test_server::{self, TestServerOptions};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_request_uri_includes_non_default_port() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut config = H3TestConfig { domain: "example.com".to_string(), path: "/a".to_string(), ..Default::default() };
+        assert_eq!(H3Tester::new(config.clone()).request_uri(ip), "https://example.com/a");
+
+        config.port = 8443;
+        assert_eq!(H3Tester::new(config.clone()).request_uri(ip), "https://example.com:8443/a");
+        config.host_header = Some("front.example".to_string());
+        assert_eq!(H3Tester::new(config).request_uri(ip), "https://front.example:8443/a");
+    }
+
+    #[tokio::test]
+    async fn test_request_probe_records_peer_params() {
+        let server = h3_test_server::spawn(TestServerOptions::default()).await;
+        let ca_file = std::env::temp_dir().join(format!("h3-request-ca-{}.pem", server.addr.port()));
+        let pem = general_purpose::STANDARD.encode(&server.cert);
+        std::fs::write(&ca_file, format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", pem)).unwrap();
+        let tester = H3Tester::new(H3TestConfig {
+            domain: "localhost".to_string(),
+            port: server.addr.port(),
+            trust: TlsTrustOptions {
+                roots: TrustRoots::Pem,
+                ca_file: Some(ca_file.to_string_lossy().into_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let mut results = Vec::new();
+        let outcome = tester.test_single_connection(server.addr.ip(), &mut results).await;
+        std::fs::remove_file(&ca_file).unwrap();
+        outcome.unwrap();
+
+        let result = &results[0];
+        assert_eq!(result.response_status, Some(200));
+        assert!(result.peer_settings.is_some());
+        assert!(result.transport_params.as_ref().is_some_and(|p| p.initial_max_streams_bidi > 0));
+        assert!(h3_direct_test::generate_peer_params_report(&results).contains("已采集: 1 个 IP"));
+    }
+}
@@ -2,11 +2,12 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Instant;
 
 // 重用现有的 DNS 解析模块
 use crate::http3_test::resolve_domain_with_rfc8484;
+use crate::alt_svc::{parse_alt_svc, AltSvc};
 
 // HTTP/3 输入任务 - 重新定义为公共结构体
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -85,6 +86,7 @@ pub struct H3IntegrationResult {
     pub response_size: Option<usize>,
     pub server_header: Option<String>,
     pub alpn_protocol: Option<String>,
+    pub remote_addr: Option<String>, // 实际连接的对端地址，用于确认请求确实发往被测 IP
     pub error_message: Option<String>,
     pub dns_source: String,
     pub test_path: String,
@@ -111,6 +113,7 @@ impl H3IntegrationResult {
             response_size: Some(0),
             server_header: None,
             alpn_protocol: Some("h3".to_string()),
+            remote_addr: None,
             error_message: None,
             dns_source,
             test_path: task.test_path.as_deref().unwrap_or("/").to_string(),
@@ -138,6 +141,7 @@ impl H3IntegrationResult {
             response_size: None,
             server_header: None,
             alpn_protocol: None,
+            remote_addr: None,
             error_message: Some(error),
             dns_source,
             test_path: task.test_path.as_deref().unwrap_or("/").to_string(),
@@ -147,8 +151,37 @@ impl H3IntegrationResult {
 }
 
 // --- 2. 协议检测和回退逻辑 ---
+// 为被测 IP 创建独立的 Client：把 test_sni_host 解析到该 IP (同 main_backup::test_connectivity)，
+// 避免共享 Client 经系统解析器连到其它地址。version 为 HTTP_2 / HTTP_11 时固定 TCP 上的协议
+fn pinned_client(task: &InputTask, ip: IpAddr, version: Option<reqwest::Version>) -> Result<reqwest::Client> {
+    let socket_addr = SocketAddr::new(ip, task.port);
+    let mut builder = reqwest::Client::builder()
+        .resolve_to_addrs(&task.test_sni_host, &[socket_addr])
+        .timeout(std::time::Duration::from_secs(15))
+        .user_agent("rust-http3-test-tool/1.0")
+        .no_proxy();
+    builder = match version {
+        Some(reqwest::Version::HTTP_2) => builder.http2_prior_knowledge(),
+        Some(reqwest::Version::HTTP_11) => builder.http1_only(),
+        _ => builder,
+    };
+    builder.build().context("Failed to create pinned HTTP client")
+}
+
+// 确认响应来自被测 IP 与端口，否则该 IP 的结果没有意义
+fn check_remote_addr(response: &reqwest::Response, task: &InputTask, ip: &IpAddr) -> Result<Option<String>> {
+    let pinned = SocketAddr::new(*ip, task.port);
+    match response.remote_addr() {
+        Some(addr) if addr != pinned => Err(anyhow::anyhow!(
+            "Request went to {} instead of pinned address {}",
+            addr,
+            pinned
+        )),
+        addr => Ok(addr.map(|a| a.to_string())),
+    }
+}
+
 pub async fn test_http3_with_fallback(
-    client: &reqwest::Client,
     task: &InputTask,
     ip: IpAddr,
     dns_source: String,
@@ -160,47 +193,61 @@ pub async fn test_http3_with_fallback(
     println!("    -> 开始 HTTP/3 协议检测: {} ({})", url, ip);
 
     // 首先尝试 HTTP/3
-    match test_http3_negotiation(client, task, &ip, &url).await {
+    let h3_error = match test_http3_negotiation(task, &ip, &url).await {
         Ok(result) => {
             println!(
                 "    -> HTTP/3 成功: {} - {}ms",
                 result.protocol_detected,
                 result.latency_ms.unwrap_or(0)
             );
+            return Ok(result);
+        }
+        Err(e) => e,
+    };
+    println!("    -> HTTP/3 失败: {}, 尝试 HTTP/2 回退", h3_error);
+
+    // 依次尝试 HTTP/2、HTTP/1.1 回退，均固定到同一 IP
+    let h2_error = match test_tcp_fallback(task, &ip, &url, reqwest::Version::HTTP_2).await {
+        Ok(result) => {
+            println!(
+                "    -> HTTP/2 回退成功: {} - {}ms",
+                result.protocol_detected,
+                result.latency_ms.unwrap_or(0)
+            );
+            return Ok(result);
+        }
+        Err(e) => e,
+    };
+    println!("    -> HTTP/2 回退失败: {}, 尝试 HTTP/1.1 回退", h2_error);
+
+    match test_tcp_fallback(task, &ip, &url, reqwest::Version::HTTP_11).await {
+        Ok(result) => {
+            println!(
+                "    -> HTTP/1.1 回退成功: {} - {}ms",
+                result.protocol_detected,
+                result.latency_ms.unwrap_or(0)
+            );
             Ok(result)
         }
-        Err(e) => {
-            println!("    -> HTTP/3 失败: {}, 尝试 HTTP/2 回退", e);
-
-            // 尝试 HTTP/2 回退
-            match test_http2_fallback(client, task, &ip, &url).await {
-                Ok(result) => {
-                    println!(
-                        "    -> HTTP/2 回退成功: {} - {}ms",
-                        result.protocol_detected,
-                        result.latency_ms.unwrap_or(0)
-                    );
-                    Ok(result)
-                }
-                Err(e2) => {
-                    println!("    -> HTTP/2 回退失败: {}", e2);
-                    Ok(H3IntegrationResult::failure(
-                        task,
-                        &ip.to_string(),
-                        ip_ver,
-                        "reqwest",
-                        dns_source,
-                        format!("All protocols failed: HTTP/3({}), HTTP/2({})", e, e2),
-                    ))
-                }
-            }
+        Err(h1_error) => {
+            println!("    -> HTTP/1.1 回退失败: {}", h1_error);
+            Ok(H3IntegrationResult::failure(
+                task,
+                &ip.to_string(),
+                ip_ver,
+                "reqwest",
+                dns_source,
+                format!(
+                    "All protocols failed: HTTP/3({}), HTTP/2({}), HTTP/1.1({})",
+                    h3_error, h2_error, h1_error
+                ),
+            ))
         }
     }
 }
 
 // --- 3. HTTP/3 协议协商 ---
 async fn test_http3_negotiation(
-    client: &reqwest::Client,
     task: &InputTask,
     ip: &IpAddr,
     url: &str,
@@ -209,6 +256,7 @@ async fn test_http3_negotiation(
     let ip_ver = if ip.is_ipv6() { "IPv6" } else { "IPv4" };
     let dns_source = format!("DoH ({})", task.doh_url);
 
+    let client = pinned_client(task, *ip, None)?;
     let start_time = Instant::now();
 
     let response = client
@@ -216,7 +264,6 @@ async fn test_http3_negotiation(
         .header("Host", &task.test_host_header)
         .header("Accept", "text/plain,application/json,*/*")
         .header("User-Agent", "rust-http3-test-tool/1.0")
-        .header("Alt-Svc", "h3=\":443\"")
         .header("Connection", "keep-alive")
         .send()
         .await
@@ -224,6 +271,21 @@ async fn test_http3_negotiation(
 
     let latency = start_time.elapsed().as_millis() as u64;
     let status = response.status();
+    let remote_addr = check_remote_addr(&response, task, ip)?;
+
+    // Alt-Svc 可能出现多次，按 RFC 7838 以逗号拼接后解析
+    let alt_svc_values: Vec<&str> = response
+        .headers()
+        .get_all("alt-svc")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    let alt_svc = (!alt_svc_values.is_empty()).then(|| parse_alt_svc(&alt_svc_values.join(", ")));
+    let advertised_h3 = match &alt_svc {
+        Some(Ok(parsed)) => parsed.preferred_h3().cloned(),
+        _ => None,
+    };
+
     let server = response
         .headers()
         .get("server")
@@ -237,7 +299,7 @@ async fn test_http3_negotiation(
         reqwest::Version::HTTP_3 => "h3",
         _ => {
             // 通过响应头判断协议
-            if response.headers().get("alt-svc").is_some() {
+            if advertised_h3.is_some() {
                 "h3-detected"
             } else if response.headers().get(":status").is_some() {
                 "h2-detected"
@@ -248,8 +310,21 @@ async fn test_http3_negotiation(
     };
 
     // 检查 HTTP/3 相关响应头
+    match &alt_svc {
+        Some(Ok(AltSvc::Clear)) => println!("    -> Alt-Svc: clear"),
+        Some(Ok(parsed)) => {
+            for entry in parsed.entries() {
+                println!("    -> Alt-Svc 条目: {}=\"{}:{}\" ma={} persist={}",
+                         entry.protocol_id, entry.host.as_deref().unwrap_or(""), entry.port,
+                         entry.max_age, entry.persist);
+            }
+        }
+        Some(Err(e)) => println!("    -> Alt-Svc 解析失败: {}", e),
+        None => println!("    -> 未返回 Alt-Svc"),
+    }
+
     let h3_indicators = vec![
-        ("alt-svc", response.headers().get("alt-svc").is_some()),
+        ("alt-svc h3", advertised_h3.is_some()),
         ("h3", response.headers().get("h3").is_some()),
         (
             "x-http3-connection",
@@ -273,19 +348,11 @@ async fn test_http3_negotiation(
         }
     };
 
-    let alpn_protocol = response
-        .headers()
-        .get("alt-svc")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| {
-            if s.contains("h3") {
-                Some("h3".to_string())
-            } else if s.contains("h2") {
-                Some("h2".to_string())
-            } else {
-                None
-            }
-        });
+    // 通告的 h3 协议标识优先，否则取第一个替代服务的协议标识
+    let alpn_protocol = advertised_h3.as_ref().map(|e| e.protocol_id.clone()).or_else(|| match &alt_svc {
+        Some(Ok(parsed)) => parsed.entries().first().map(|e| e.protocol_id.clone()),
+        _ => None,
+    });
 
     let mut result = H3IntegrationResult::success(task, &ip_str, ip_ver, "reqwest", dns_source);
     result.status_code = Some(status.as_u16());
@@ -294,6 +361,7 @@ async fn test_http3_negotiation(
     result.response_size = Some(response_size);
     result.server_header = server;
     result.alpn_protocol = alpn_protocol;
+    result.remote_addr = remote_addr;
 
     if status.as_u16() >= 400 {
         result.success = false;
@@ -303,17 +371,19 @@ async fn test_http3_negotiation(
     Ok(result)
 }
 
-// --- 4. HTTP/2 回退 ---
-async fn test_http2_fallback(
-    client: &reqwest::Client,
+// --- 4. HTTP/2 与 HTTP/1.1 回退 ---
+async fn test_tcp_fallback(
     task: &InputTask,
     ip: &IpAddr,
     url: &str,
+    version: reqwest::Version,
 ) -> Result<H3IntegrationResult> {
     let ip_str = ip.to_string();
     let ip_ver = if ip.is_ipv6() { "IPv6" } else { "IPv4" };
     let dns_source = format!("DoH ({})", task.doh_url);
+    let protocol = if version == reqwest::Version::HTTP_2 { "h2" } else { "http/1.1" };
 
+    let client = pinned_client(task, *ip, Some(version))?;
     let start_time = Instant::now();
 
     let response = client
@@ -321,14 +391,14 @@ async fn test_http2_fallback(
         .header("Host", &task.test_host_header)
         .header("Accept", "text/plain,application/json,*/*")
         .header("User-Agent", "rust-http3-test-tool/1.0")
-        .header("Connection", "keep-alive")
-        .version(reqwest::Version::HTTP_2)
+        .version(version)
         .send()
         .await
-        .context("HTTP/2 fallback request failed")?;
+        .with_context(|| format!("{} fallback request failed", protocol))?;
 
     let latency = start_time.elapsed().as_millis() as u64;
     let status = response.status();
+    let remote_addr = check_remote_addr(&response, task, ip)?;
     let server = response
         .headers()
         .get("server")
@@ -346,15 +416,16 @@ async fn test_http2_fallback(
     let mut result =
         H3IntegrationResult::success(task, &ip_str, ip_ver, "reqwest-fallback", dns_source);
     result.status_code = Some(status.as_u16());
-    result.protocol_detected = "h2".to_string();
+    result.protocol_detected = protocol.to_string();
     result.latency_ms = Some(latency);
     result.response_size = Some(response_size);
     result.server_header = server;
-    result.alpn_protocol = Some("h2".to_string());
+    result.alpn_protocol = Some(protocol.to_string());
+    result.remote_addr = remote_addr;
 
     if status.as_u16() >= 400 {
         result.success = false;
-        result.error_message = Some(format!("HTTP/2 error: {}", status));
+        result.error_message = Some(format!("{} error: {}", protocol, status));
     }
 
     Ok(result)
@@ -366,12 +437,12 @@ pub async fn run_http3_integration_tests() -> Result<()> {
     println!("🚀 HTTP/3 集成测试开始");
     println!("================================");
 
+    // 共享 Client 仅用于 DoH 解析，各 IP 的测试请求使用 pinned_client
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(15))
         .user_agent("rust-http3-test-tool/1.0")
         .default_headers({
             let mut headers = reqwest::header::HeaderMap::new();
-            headers.insert("Alt-Svc", "h3=\":443\"".parse().unwrap());
             headers.insert("Connection", "keep-alive".parse().unwrap());
             headers
         })
@@ -443,7 +514,6 @@ pub async fn run_http3_integration_tests() -> Result<()> {
                     }
 
                     let task_clone = test_config.input_task.clone();
-                    let client_clone = client.clone();
                     let dns_source = if test_config.input_task.resolve_mode == "direct" {
                         "Direct Input".to_string()
                     } else {
@@ -451,8 +521,7 @@ pub async fn run_http3_integration_tests() -> Result<()> {
                     };
 
                     futures.push(tokio::spawn(async move {
-                        match test_http3_with_fallback(&client_clone, &task_clone, ip, dns_source)
-                            .await
+                        match test_http3_with_fallback(&task_clone, ip, dns_source).await
                         {
                             Ok(result) => result,
                             Err(e) => {
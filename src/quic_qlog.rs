@@ -0,0 +1,267 @@
+// qlog 事件追踪 - 为每个原生 QUIC 连接写入 JSON-SEQ 格式的 qlog (可直接在 qvis 中打开)
+use anyhow::{Context, Result};
+use h3::quic::StreamId;
+use qlog::events::quic::{CongestionStateUpdated, StreamSide, StreamState, StreamStateUpdated, StreamType};
+use qlog::events::{Event, EventData};
+use quinn_proto::{QlogConfig, QlogStream};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// JSON-SEQ (RFC 7464) 记录分隔符
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+// 同一秒内连接同一目标时区分文件名
+static TRACE_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// --- 1. trace 目录 ---
+// 默认只保留失败探测的 trace：探测结束后调用 finish，成功时删除本次写入的文件
+#[derive(Debug, Clone)]
+pub struct QlogDir {
+    dir: PathBuf,
+    keep_all: bool,
+    pending: Arc<Mutex<Vec<PathBuf>>>, // 当前探测中写入的 trace
+}
+
+impl QlogDir {
+    pub fn new(dir: impl Into<PathBuf>, keep_all: bool) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).with_context(|| format!("无法创建 qlog 目录: {}", dir.display()))?;
+        Ok(Self { dir, keep_all, pending: Arc::default() })
+    }
+
+    // 同一目录的独立句柄：并发的多个探测各自 finish，互不影响
+    pub fn scope(&self) -> Self {
+        Self { dir: self.dir.clone(), keep_all: self.keep_all, pending: Arc::default() }
+    }
+
+    // 文件名: <IP>_<SNI>_<时间>_<序号>.sqlog，IPv6 地址中的 ':' 替换为 '-'
+    pub fn trace(&self, target_ip: &str, sni: &str) -> Result<QlogTrace> {
+        let name = format!(
+            "{}_{}_{}_{}.sqlog",
+            target_ip.replace(':', "-"),
+            sni,
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            TRACE_SEQUENCE.fetch_add(1, Ordering::Relaxed),
+        );
+        let path = self.dir.join(name);
+        let trace = QlogTrace::create(&path, format!("{} ({})", sni, target_ip))?;
+        self.pending.lock().unwrap().push(path);
+        Ok(trace)
+    }
+
+    // 结束一次探测，返回保留下来的 trace 文件
+    pub fn finish(&self, success: bool) -> Vec<PathBuf> {
+        let paths = std::mem::take(&mut *self.pending.lock().unwrap());
+        if success && !self.keep_all {
+            for path in &paths {
+                let _ = std::fs::remove_file(path);
+            }
+            return Vec::new();
+        }
+        paths
+    }
+}
+
+// --- 2. 单个连接的 trace ---
+// quinn 写入数据包收发、丢包与恢复指标事件；本模块补充拥塞状态变化与流事件，
+// 两者按完整记录交错写入同一文件
+#[derive(Clone)]
+pub struct QlogTrace {
+    writer: Arc<Mutex<TraceWriter>>,
+    title: String,
+    start_time: Instant,
+}
+
+struct TraceWriter {
+    file: File,
+    partial: Vec<u8>,         // quinn 分多次写入的未完成记录
+    group_id: Option<String>, // 与 quinn 事件相同的 group_id (原始目标连接 ID)
+    congestion: CongestionTracker,
+}
+
+impl QlogTrace {
+    fn create(path: &Path, title: String) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("无法创建 qlog 文件: {}", path.display()))?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(TraceWriter {
+                file,
+                partial: Vec::new(),
+                group_id: None,
+                congestion: CongestionTracker::default(),
+            })),
+            title,
+            start_time: Instant::now(),
+        })
+    }
+
+    // 交给 TransportConfig::qlog_stream 的输出流
+    pub fn stream(&self) -> Option<QlogStream> {
+        let mut config = QlogConfig::default();
+        config
+            .writer(Box::new(QuinnRecords(self.clone())))
+            .title(Some(self.title.clone()))
+            .description(Some("rust-http3-test-tool".to_string()))
+            .start_time(self.start_time);
+        config.into_stream()
+    }
+
+    pub fn stream_state(&self, id: StreamId, new: StreamState, side: Option<StreamSide>) {
+        let id = id.into_inner();
+        // 流 ID 第二位为 1 表示单向流 (RFC 9000 §2.1)
+        let stream_type = if id & 0x2 == 0 { StreamType::Bidirectional } else { StreamType::Unidirectional };
+        self.emit(EventData::StreamStateUpdated(StreamStateUpdated {
+            stream_id: id,
+            stream_type: Some(stream_type),
+            old: None,
+            new,
+            stream_side: side,
+        }));
+    }
+
+    fn emit(&self, data: EventData) {
+        let time = self.start_time.elapsed().as_secs_f32() * 1000.0;
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_event(time, data);
+    }
+}
+
+impl TraceWriter {
+    fn write_event(&mut self, time: f32, data: EventData) {
+        let mut event = Event::with_time(time, data);
+        event.group_id = self.group_id.clone();
+        let Ok(json) = serde_json::to_vec(&event) else { return };
+        let mut record = vec![RECORD_SEPARATOR];
+        record.extend_from_slice(&json);
+        record.push(b'\n');
+        if let Err(e) = self.file.write_all(&record) {
+            eprintln!("写入 qlog 失败: {}", e);
+        }
+    }
+
+    // quinn 写出的一条完整记录：原样写入，再根据恢复指标推断拥塞状态
+    fn write_quinn_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+
+        let json = record.strip_prefix(&[RECORD_SEPARATOR]).unwrap_or(record);
+        let Ok(event) = serde_json::from_slice::<serde_json::Value>(json) else { return Ok(()) };
+        if self.group_id.is_none() {
+            self.group_id = event["group_id"].as_str().map(String::from);
+        }
+        if event["name"] == "recovery:metrics_updated" {
+            let data = &event["data"];
+            let change = self.congestion.update(data["congestion_window"].as_u64(), data["ssthresh"].as_u64());
+            if let (Some((old, new)), Some(time)) = (change, event["time"].as_f64()) {
+                self.write_event(
+                    time as f32,
+                    EventData::CongestionStateUpdated(CongestionStateUpdated {
+                        old: old.map(String::from),
+                        new: new.to_string(),
+                        trigger: None,
+                    }),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// quinn 的 QlogStreamer 分多次写入一条记录，按换行拼成完整记录后再写入文件
+struct QuinnRecords(QlogTrace);
+
+impl Write for QuinnRecords {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut writer = self.0.writer.lock().unwrap();
+        writer.partial.extend_from_slice(buf);
+        while let Some(end) = writer.partial.iter().position(|&b| b == b'\n') {
+            let record: Vec<u8> = writer.partial.drain(..=end).collect();
+            writer.write_quinn_record(&record)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.writer.lock().unwrap().file.flush()
+    }
+}
+
+// --- 3. 拥塞状态 ---
+// quinn 的 metrics_updated 只包含变化的字段；拥塞窗口缩小视为进入恢复，
+// 增长时按是否达到慢启动阈值区分慢启动与拥塞避免
+#[derive(Debug, Default)]
+struct CongestionTracker {
+    cwnd: Option<u64>,
+    ssthresh: Option<u64>,
+    state: Option<&'static str>,
+}
+
+impl CongestionTracker {
+    fn update(&mut self, cwnd: Option<u64>, ssthresh: Option<u64>) -> Option<(Option<&'static str>, &'static str)> {
+        if ssthresh.is_some() {
+            self.ssthresh = ssthresh;
+        }
+        let cwnd = cwnd?;
+        let new = match (self.cwnd.replace(cwnd), self.ssthresh) {
+            (Some(previous), _) if cwnd < previous => "recovery",
+            (_, Some(ssthresh)) if cwnd >= ssthresh => "congestion_avoidance",
+            _ => "slow_start",
+        };
+        if self.state == Some(new) {
+            return None;
+        }
+        Some((self.state.replace(new), new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_congestion_state_from_metrics() {
+        let mut tracker = CongestionTracker::default();
+
+        assert_eq!(tracker.update(Some(12_000), None), Some((None, "slow_start")));
+        assert_eq!(tracker.update(Some(24_000), None), None);
+        assert_eq!(tracker.update(None, Some(12_000)), None);
+        assert_eq!(tracker.update(Some(12_000), None), Some((Some("slow_start"), "recovery")));
+        assert_eq!(tracker.update(Some(13_200), None), Some((Some("recovery"), "congestion_avoidance")));
+    }
+
+    #[test]
+    fn test_trace_merges_quinn_records_with_own_events() {
+        let dir = QlogDir::new(std::env::temp_dir().join(format!("h3-test-qlog-{}", std::process::id())), false).unwrap();
+        let trace = dir.trace("2001:db8::1", "example.com").unwrap();
+
+        // quinn 分段写入记录
+        let mut records = QuinnRecords(trace.clone());
+        records.write_all(b"\x1e{\"time\":1.5,\"name\":\"recovery:metrics_").unwrap();
+        records
+            .write_all(b"updated\",\"data\":{\"congestion_window\":12000},\"group_id\":\"abcd\"}\n")
+            .unwrap();
+        trace.stream_state(StreamId::try_from(2).unwrap(), StreamState::Open, Some(StreamSide::Sending));
+
+        let path = dir.pending.lock().unwrap()[0].clone();
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("2001-db8--1_example.com_"));
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<serde_json::Value> = content
+            .split('\x1e')
+            .filter(|record| !record.is_empty())
+            .map(|record| serde_json::from_str(record).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1]["name"], "recovery:congestion_state_updated");
+        assert_eq!(events[1]["data"]["new"], "slow_start");
+        assert_eq!(events[2]["name"], "transport:stream_state_updated");
+        assert_eq!(events[2]["data"]["stream_type"], "unidirectional");
+        assert_eq!(events[2]["group_id"], "abcd");
+
+        // 成功的探测不保留 trace
+        assert!(dir.finish(true).is_empty());
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir.dir).unwrap();
+    }
+}
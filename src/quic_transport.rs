@@ -0,0 +1,168 @@
+// QUIC 传输参数配置 - 各测试任务可选的 transport 配置块，统一转换为 quinn TransportConfig
+use h3_quinn::quinn;
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+use quinn::{TransportConfig, VarInt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 未配置时的空闲超时，以及 quinn 的初始 MTU (QUIC 要求的最小 UDP 负载) 与默认 MTU 探测上限
+pub const DEFAULT_IDLE_TIMEOUT_MS: u64 = 10_000;
+pub const QUIC_MIN_MTU: u16 = 1200;
+pub const DEFAULT_MTU_UPPER_BOUND: u16 = 1452;
+
+// --- 1. 拥塞控制算法 ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CongestionController {
+    NewReno,
+    Cubic, // quinn 默认
+    Bbr,   // quinn 中为实验性实现
+}
+
+impl CongestionController {
+    pub const ALL: [CongestionController; 3] = [Self::NewReno, Self::Cubic, Self::Bbr];
+
+    fn apply(self, transport_config: &mut TransportConfig) {
+        match self {
+            Self::NewReno => transport_config.congestion_controller_factory(Arc::new(NewRenoConfig::default())),
+            Self::Cubic => transport_config.congestion_controller_factory(Arc::new(CubicConfig::default())),
+            Self::Bbr => transport_config.congestion_controller_factory(Arc::new(BbrConfig::default())),
+        };
+    }
+}
+
+impl fmt::Display for CongestionController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NewReno => "newreno",
+            Self::Cubic => "cubic",
+            Self::Bbr => "bbr",
+        })
+    }
+}
+
+impl FromStr for CongestionController {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "newreno" | "new-reno" | "reno" => Ok(Self::NewReno),
+            "cubic" => Ok(Self::Cubic),
+            "bbr" => Ok(Self::Bbr),
+            other => Err(format!("不支持的拥塞控制算法: {} (可选 newreno, cubic, bbr)", other)),
+        }
+    }
+}
+
+// --- 2. transport 配置块 ---
+// 所有字段可选，未设置的使用 base() 中的默认值
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QuicTransportOptions {
+    pub idle_timeout_ms: Option<u64>, // 本地 max_idle_timeout，默认 DEFAULT_IDLE_TIMEOUT_MS
+    pub keep_alive_interval_ms: Option<u64>, // 设置后按该间隔发送 PING 保活
+    pub max_concurrent_bidi_streams: Option<u32>, // 默认 100
+    pub max_concurrent_uni_streams: Option<u32>,  // 默认 100
+    pub stream_receive_window: Option<u64>, // 单个流的接收窗口
+    pub receive_window: Option<u64>,        // 整个连接的接收窗口
+    pub send_window: Option<u64>,           // 整个连接的发送窗口
+    pub initial_rtt_ms: Option<u64>,        // 尚未测得 RTT 时使用的估计值 (quinn 默认 333ms)
+    pub initial_mtu: Option<u16>, // 初始 MTU，默认 QUIC_MIN_MTU
+    pub min_mtu: Option<u16>,
+    pub mtu_upper_bound: Option<u16>, // MTU 探测 (PLPMTUD) 上限，默认 DEFAULT_MTU_UPPER_BOUND
+    pub fixed_mtu: Option<u16>, // 固定 MTU: 关闭 MTU 探测，并把通告的 max_udp_payload_size 限制为该值
+    pub congestion_controller: Option<CongestionController>, // 默认 cubic
+}
+
+impl QuicTransportOptions {
+    // 本工具的基础传输配置：10 秒空闲超时、100 个并发流
+    fn base() -> TransportConfig {
+        let mut transport_config = TransportConfig::default();
+        transport_config.max_idle_timeout(
+            quinn::IdleTimeout::try_from(Duration::from_millis(DEFAULT_IDLE_TIMEOUT_MS)).ok(),
+        );
+        transport_config.max_concurrent_uni_streams(100u32.into());
+        transport_config.max_concurrent_bidi_streams(100u32.into());
+        transport_config
+    }
+
+    pub fn build(&self) -> TransportConfig {
+        let mut transport_config = Self::base();
+
+        if let Some(idle_timeout) = self.idle_timeout_ms {
+            transport_config.max_idle_timeout(quinn::IdleTimeout::try_from(Duration::from_millis(idle_timeout)).ok());
+        }
+        transport_config.keep_alive_interval(self.keep_alive_interval_ms.map(Duration::from_millis));
+        if let Some(streams) = self.max_concurrent_bidi_streams {
+            transport_config.max_concurrent_bidi_streams(streams.into());
+        }
+        if let Some(streams) = self.max_concurrent_uni_streams {
+            transport_config.max_concurrent_uni_streams(streams.into());
+        }
+        if let Some(window) = self.stream_receive_window.and_then(|w| VarInt::from_u64(w).ok()) {
+            transport_config.stream_receive_window(window);
+        }
+        if let Some(window) = self.receive_window.and_then(|w| VarInt::from_u64(w).ok()) {
+            transport_config.receive_window(window);
+        }
+        if let Some(window) = self.send_window {
+            transport_config.send_window(window);
+        }
+        if let Some(rtt) = self.initial_rtt_ms {
+            transport_config.initial_rtt(Duration::from_millis(rtt));
+        }
+
+        if let Some(mtu) = self.fixed_mtu {
+            transport_config.initial_mtu(mtu).min_mtu(mtu).mtu_discovery_config(None);
+        } else {
+            if let Some(mtu) = self.initial_mtu {
+                transport_config.initial_mtu(mtu);
+            }
+            if let Some(mtu) = self.min_mtu {
+                transport_config.min_mtu(mtu);
+            }
+            if let Some(upper_bound) = self.mtu_upper_bound {
+                let mut discovery = quinn::MtuDiscoveryConfig::default();
+                discovery.upper_bound(upper_bound);
+                transport_config.mtu_discovery_config(Some(discovery));
+            }
+        }
+
+        if let Some(controller) = self.congestion_controller {
+            controller.apply(&mut transport_config);
+        }
+
+        transport_config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_block_from_json() {
+        let options: QuicTransportOptions = serde_json::from_str(
+            r#"{"idle_timeout_ms": 30000, "stream_receive_window": 1048576, "congestion_controller": "bbr"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(options.idle_timeout_ms, Some(30_000));
+        assert_eq!(options.stream_receive_window, Some(1_048_576));
+        assert_eq!(options.congestion_controller, Some(CongestionController::Bbr));
+        assert_eq!(options.keep_alive_interval_ms, None);
+        assert_eq!(serde_json::from_str::<QuicTransportOptions>("{}").unwrap(), QuicTransportOptions::default());
+    }
+
+    #[test]
+    fn test_congestion_controller_names() {
+        for controller in CongestionController::ALL {
+            assert_eq!(controller.to_string().parse::<CongestionController>(), Ok(controller));
+        }
+        assert_eq!("New-Reno".parse::<CongestionController>(), Ok(CongestionController::NewReno));
+        assert!("vegas".parse::<CongestionController>().is_err());
+    }
+}
@@ -1,17 +1,22 @@
 // HTTP/3 网络请求测试 - 使用QUIC库
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
-use quinn::{ClientConfig, Endpoint, TransportConfig};
+use h3_quinn::quinn::{self, ClientConfig, Endpoint};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::time::timeout;
 use trust_dns_proto::op::{Message, Query};
 use trust_dns_proto::rr::{Name, RecordType};
 use trust_dns_proto::serialize::binary::BinEncodable;
 
+use crate::quic_transport::QuicTransportOptions;
+use crate::tls_inspect::{recording_client_config, CertCapture};
+use crate::tls_trust::TlsTrustOptions;
+
 // --- 1. 输入配置 ---
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct InputTask {
@@ -24,6 +29,8 @@ struct InputTask {
     resolve_mode: String,
     direct_ips: Option<Vec<String>>,
     test_path: Option<String>,
+    #[serde(default)]
+    transport: QuicTransportOptions, // 可选的 QUIC 传输参数，未设置保活时默认每 5 秒保活
 }
 
 // --- 2. 输出结果 ---
@@ -311,14 +318,16 @@ async fn test_quic_connectivity(
     println!("    -> 测试 QUIC 连接到: {}: {}", task.test_sni_host, ip);
 
     // 配置QUIC传输
-    let mut transport_config = TransportConfig::default();
-    if let Ok(timeout) = Duration::from_secs(10).try_into() {
-        transport_config.max_idle_timeout(Some(timeout));
+    let mut transport_config = task.transport.build();
+    if task.transport.keep_alive_interval_ms.is_none() {
+        transport_config.keep_alive_interval(Some(Duration::from_secs(5)));
     }
-    transport_config.keep_alive_interval(Some(Duration::from_secs(5)));
 
-    // 创建客户端配置
-    let client_config = ClientConfig::with_native_roots();
+    // 创建客户端配置：系统证书库，设置 SSLKEYLOGFILE 时记录 TLS 密钥
+    let tls_config = recording_client_config(&TlsTrustOptions::default().build()?, &CertCapture::default())?;
+    let mut client_config =
+        ClientConfig::new(Arc::new(quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)?));
+    client_config.transport_config(Arc::new(transport_config));
 
     // 创建QUIC端点
     let endpoint = Endpoint::client("0.0.0.0:0".parse::<SocketAddr>().unwrap())
@@ -0,0 +1,517 @@
+// 对端 TLS 证书链采集与检查 - 主题、SAN、签发者、有效期、密钥类型与 SCT / OCSP，以及客户端证书请求与协商的密钥交换组
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, EchMode, ResolvesClientCert, Resumption, Tls12ClientSessionValue,
+    Tls13ClientSessionValue,
+};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::sign::CertifiedKey;
+use rustls::{CipherSuite, ClientConfig, DigitallySignedStruct, NamedGroup, SignatureScheme};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use x509_parser::prelude::*;
+use x509_parser::public_key::PublicKey;
+
+use crate::tls_trust::TrustPolicy;
+
+// 剩余有效期少于该天数时给出警告
+pub const DEFAULT_EXPIRY_WARNING_DAYS: i64 = 14;
+
+// 证书内嵌 SCT 列表扩展 (RFC 6962)
+const OID_CT_SCT_LIST: &str = "1.3.6.1.4.1.11129.2.4.2";
+
+// --- 1. 证书信息 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerCertificate {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub sans: Vec<String>, // DNS 名称与 IP 地址
+    pub not_before: String,
+    pub not_after: String,
+    pub days_remaining: i64,
+    pub key_type: String, // 如 "EC 256"、"RSA 2048"、"Ed25519"
+    pub is_ca: bool,
+    pub embedded_sct: bool,
+    pub sha256_fingerprint: String,
+    pub spki_pin: String, // "sha256/<base64>"，可直接用作 spki_pins
+}
+
+impl PeerCertificate {
+    pub fn from_der(der: &[u8], now: i64) -> Result<Self, String> {
+        let (_, cert) = X509Certificate::from_der(der).map_err(|e| format!("无法解析证书: {}", e))?;
+
+        let sans = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(bytes) => ip_from_bytes(bytes).map(|ip| ip.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let spki = cert.public_key();
+        let key_type = match spki.parsed() {
+            Ok(key @ PublicKey::RSA(_)) => format!("RSA {}", key.key_size()),
+            Ok(key @ PublicKey::EC(_)) => format!("EC {}", key.key_size()),
+            _ => match spki.algorithm.algorithm.to_id_string().as_str() {
+                "1.3.101.112" => "Ed25519".to_string(),
+                "1.3.101.113" => "Ed448".to_string(),
+                oid => oid.to_string(),
+            },
+        };
+
+        let validity = cert.validity();
+        let not_after = validity.not_after.timestamp();
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            serial: cert.raw_serial_as_string(),
+            sans,
+            not_before: rfc3339(validity.not_before.timestamp()),
+            not_after: rfc3339(not_after),
+            days_remaining: (not_after - now).div_euclid(86400),
+            key_type,
+            is_ca: cert.is_ca(),
+            embedded_sct: cert.extensions().iter().any(|e| e.oid.to_id_string() == OID_CT_SCT_LIST),
+            sha256_fingerprint: sha256_hex(der),
+            spki_pin: format!("sha256/{}", general_purpose::STANDARD.encode(sha256(spki.raw))),
+        })
+    }
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let digest = ring::digest::digest(&ring::digest::SHA256, data);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(digest.as_ref());
+    out
+}
+
+fn sha256_hex(der: &[u8]) -> String {
+    sha256(der).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 证书 SubjectPublicKeyInfo 的 SHA-256，无法解析时为 None
+pub fn spki_sha256(der: &[u8]) -> Option<[u8; 32]> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    Some(sha256(cert.public_key().raw))
+}
+
+// SAN 是否覆盖 SNI：通配符只匹配最左侧一个标签，IP 形式的 SNI 与 IP SAN 比较
+pub fn san_covers(sans: &[String], sni: &str) -> bool {
+    let sni = sni.trim_end_matches('.').to_ascii_lowercase();
+    if let Ok(ip) = sni.parse::<IpAddr>() {
+        return sans.iter().any(|san| san.parse::<IpAddr>() == Ok(ip));
+    }
+    sans.iter().any(|san| {
+        let san = san.trim_end_matches('.').to_ascii_lowercase();
+        match san.strip_prefix("*.") {
+            Some(suffix) => sni
+                .split_once('.')
+                .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+            None => san == sni,
+        }
+    })
+}
+
+// --- 2. 检查结果 ---
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CertificateReport {
+    pub sni: String,
+    pub chain: Vec<PeerCertificate>, // 对端发送的顺序，第一个为叶子证书
+    pub leaf_fingerprint: Option<String>,
+    pub san_covers_sni: bool,
+    pub embedded_sct: bool, // 叶子证书内嵌 SCT；TLS 扩展中的 SCT 无法从 rustls 获取
+    pub ocsp_stapled: bool,
+    pub verification_error: Option<String>, // 证书验证失败的原因
+    pub spki_pin_matched: Option<bool>, // 未配置 SPKI 固定时为 None
+    pub insecure: bool, // insecure 模式下验证失败不会中断握手
+    pub warnings: Vec<String>,
+}
+
+impl CertificateReport {
+    pub fn inspect(
+        sni: &str,
+        chain: &[CertificateDer<'_>],
+        ocsp_stapled: bool,
+        verification_error: Option<String>,
+        now: i64,
+    ) -> Self {
+        let mut warnings = Vec::new();
+        let parsed: Vec<PeerCertificate> = chain
+            .iter()
+            .filter_map(|der| match PeerCertificate::from_der(der, now) {
+                Ok(cert) => Some(cert),
+                Err(e) => {
+                    warnings.push(e);
+                    None
+                }
+            })
+            .collect();
+
+        let leaf = parsed.first();
+        let san_covers_sni = leaf.is_some_and(|leaf| san_covers(&leaf.sans, sni));
+        if let Some(leaf) = leaf {
+            if !san_covers_sni {
+                warnings.push(format!("SAN 不包含 SNI {}", sni));
+            }
+            if leaf.subject == leaf.issuer {
+                warnings.push("叶子证书为自签名".to_string());
+            }
+        }
+        for cert in &parsed {
+            if cert.days_remaining < 0 {
+                warnings.push(format!("证书已过期: {} ({})", cert.subject, cert.not_after));
+            } else if cert.days_remaining < DEFAULT_EXPIRY_WARNING_DAYS {
+                warnings.push(format!("证书将在 {} 天后过期: {}", cert.days_remaining, cert.subject));
+            }
+        }
+        if let Some(error) = &verification_error {
+            warnings.push(format!("证书验证失败: {}", error));
+        }
+
+        Self {
+            sni: sni.to_string(),
+            leaf_fingerprint: leaf.map(|c| c.sha256_fingerprint.clone()),
+            san_covers_sni,
+            embedded_sct: leaf.is_some_and(|c| c.embedded_sct),
+            ocsp_stapled,
+            verification_error,
+            spki_pin_matched: None,
+            insecure: false,
+            warnings,
+            chain: parsed,
+        }
+    }
+}
+
+// --- 3. 记录对端证书链的验证器 ---
+#[derive(Debug)]
+struct CapturedChain {
+    verified_name: Option<String>, // 证书验证通过时使用的名称，ECH 被拒绝时为 public_name
+    chain: Vec<CertificateDer<'static>>,
+    ocsp_stapled: bool,
+    verification_error: Option<String>,
+    spki_pin_matched: Option<bool>,
+    insecure: bool,
+}
+
+// 每个连接一份，握手失败时同样保留对端发送的证书链与客户端证书请求
+#[derive(Debug, Clone, Default)]
+pub struct CertCapture {
+    chain: Arc<Mutex<Option<CapturedChain>>>,
+    client_auth: Arc<Mutex<Option<ClientAuthResult>>>,
+    kx_group: Arc<Mutex<Option<NamedGroup>>>,
+    cipher_suite: Arc<Mutex<Option<CipherSuite>>>,
+}
+
+impl CertCapture {
+    // 根据连接是否收到响应 / 是否被对端以 TLS 告警关闭判断客户端证书是否被接受：
+    // Some(true) 已收到响应，Some(false) 被对端以 TLS 告警关闭，None 无法判断
+    pub fn client_auth(&self, accepted: Option<bool>) -> Option<ClientAuthResult> {
+        let mut result = self.client_auth.lock().unwrap().clone()?;
+        if result.requested {
+            result.accepted = accepted;
+        }
+        Some(result)
+    }
+
+    pub fn verified_name(&self) -> Option<String> {
+        self.chain.lock().unwrap().as_ref().and_then(|captured| captured.verified_name.clone())
+    }
+
+    // 对端在 ServerHello 中选择的密钥交换组 (仅 TLS 1.3)，之后握手失败时同样保留
+    pub fn kx_group(&self) -> Option<String> {
+        self.kx_group.lock().unwrap().map(|group| format!("{:?}", group))
+    }
+
+    // 本次握手协商的 TLS 1.3 密码套件，取自对端发送的会话票据；对端未发送票据时为 None
+    pub fn cipher_suite(&self) -> Option<String> {
+        self.cipher_suite.lock().unwrap().map(|suite| format!("{:?}", suite))
+    }
+
+    pub fn report(&self, sni: &str) -> Option<CertificateReport> {
+        let captured = self.chain.lock().unwrap();
+        let captured = captured.as_ref()?;
+        let mut report = CertificateReport::inspect(
+            sni,
+            &captured.chain,
+            captured.ocsp_stapled,
+            captured.verification_error.clone(),
+            UnixTime::now().as_secs() as i64,
+        );
+        report.spki_pin_matched = captured.spki_pin_matched;
+        report.insecure = captured.insecure;
+        if captured.spki_pin_matched == Some(false) {
+            report.warnings.push("证书链中没有与 SPKI 固定值匹配的公钥".to_string());
+        }
+        if captured.insecure && captured.verification_error.is_some() {
+            report.warnings.push("insecure 模式: 已忽略证书验证失败".to_string());
+        }
+        Some(report)
+    }
+}
+
+// 包装实际的验证器：先记录证书链与验证结果，再按 SPKI 固定与 insecure 设置决定是否接受
+#[derive(Debug)]
+pub struct RecordingVerifier {
+    policy: TrustPolicy,
+    capture: CertCapture,
+}
+
+impl RecordingVerifier {
+    pub fn new(policy: TrustPolicy, capture: CertCapture) -> Self {
+        Self { policy, capture }
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let mut result = self
+            .policy
+            .verifier
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now);
+        let mut verified_name = result.is_ok().then(|| server_name.to_str().into_owned());
+
+        // rustls 在 ECH 被拒绝时仍按真实 SNI 验证外层握手的证书；按 ECH 规范改用 public_name 验证，
+        // 握手随后会因 ECH 被拒绝而中止，不会在该连接上发送请求
+        if let (Err(_), Some(ech)) = (&result, &self.policy.ech) {
+            for name in &ech.public_names {
+                let Ok(outer) = ServerName::try_from(name.as_str()) else { continue };
+                if let Ok(verified) = self.policy.verifier.verify_server_cert(end_entity, intermediates, &outer, ocsp_response, now) {
+                    result = Ok(verified);
+                    verified_name = Some(name.clone());
+                    break;
+                }
+            }
+        }
+
+        let chain: Vec<CertificateDer<'static>> = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.clone().into_owned())
+            .collect();
+        let spki_pin_matched = (!self.policy.spki_pins.is_empty()).then(|| {
+            chain
+                .iter()
+                .filter_map(|cert| spki_sha256(cert))
+                .any(|digest| self.policy.spki_pins.contains(&digest))
+        });
+        *self.capture.chain.lock().unwrap() = Some(CapturedChain {
+            verified_name,
+            chain,
+            ocsp_stapled: !ocsp_response.is_empty(),
+            verification_error: result.as_ref().err().map(|e| e.to_string()),
+            spki_pin_matched,
+            insecure: self.policy.insecure,
+        });
+
+        match result {
+            _ if spki_pin_matched == Some(false) => {
+                Err(rustls::Error::General("SPKI pin mismatch".to_string()))
+            }
+            Err(_) if self.policy.insecure => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.policy.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.policy.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.policy.verifier.supported_verify_schemes()
+    }
+}
+
+// --- 4. 客户端证书 (mTLS) ---
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClientAuthResult {
+    pub configured: bool, // 本地配置了客户端证书
+    pub requested: bool,  // 对端发送了 CertificateRequest
+    pub presented: bool,  // 实际出示了客户端证书
+    pub acceptable_issuers: Vec<String>, // CertificateRequest 中的 CA 提示
+    pub accepted: Option<bool>, // 对端请求证书时，出示 (或未出示) 后连接是否可用
+}
+
+// 记录对端是否请求客户端证书，并在请求时出示配置的证书
+#[derive(Debug)]
+pub struct RecordingClientCert {
+    key: Option<Arc<CertifiedKey>>,
+    capture: CertCapture,
+}
+
+impl RecordingClientCert {
+    pub fn new(key: Option<Arc<CertifiedKey>>, capture: CertCapture) -> Self {
+        *capture.client_auth.lock().unwrap() = key.is_some().then(|| ClientAuthResult {
+            configured: true,
+            ..Default::default()
+        });
+        Self { key, capture }
+    }
+}
+
+impl ResolvesClientCert for RecordingClientCert {
+    fn resolve(&self, root_hint_subjects: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        *self.capture.client_auth.lock().unwrap() = Some(ClientAuthResult {
+            configured: self.key.is_some(),
+            requested: true,
+            presented: self.key.is_some(),
+            acceptable_issuers: root_hint_subjects
+                .iter()
+                .filter_map(|der| X509Name::from_der(der).ok().map(|(_, name)| name.to_string()))
+                .collect(),
+            accepted: None,
+        });
+        self.key.clone()
+    }
+
+    fn has_certs(&self) -> bool {
+        self.key.is_some()
+    }
+}
+
+// --- 5. 协商的密钥交换组与密码套件 ---
+// rustls 在收到 ServerHello 后通过 set_kx_hint 告知对端选择的组，收到 NewSessionTicket 时
+// 保存的票据带有协商的密码套件，其余操作交给内存会话缓存
+#[derive(Debug)]
+struct RecordingSessionStore {
+    inner: ClientSessionMemoryCache,
+    capture: CertCapture,
+}
+
+impl ClientSessionStore for RecordingSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        *self.capture.kx_group.lock().unwrap() = Some(group);
+        self.inner.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(&self, server_name: ServerName<'static>, value: Tls13ClientSessionValue) {
+        *self.capture.cipher_suite.lock().unwrap() = Some(value.suite().common.suite);
+        self.inner.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+// 按信任配置验证服务端证书、按需出示客户端证书，并把两者及协商的密钥交换组记录到 capture；
+// 使用策略中的加密后端与密钥日志，带 ECH 配置时启用 ECH (仅 TLS 1.3)
+pub fn recording_client_config(policy: &TrustPolicy, capture: &CertCapture) -> Result<ClientConfig, rustls::Error> {
+    let builder = ClientConfig::builder_with_provider(policy.provider.clone());
+    let builder = match &policy.ech {
+        Some(ech) => builder.with_ech(EchMode::Enable(ech.config.clone()))?,
+        None => builder.with_safe_default_protocol_versions()?,
+    };
+    let mut config = builder
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(RecordingVerifier::new(policy.clone(), capture.clone())))
+        .with_client_cert_resolver(Arc::new(RecordingClientCert::new(policy.client_cert.clone(), capture.clone())));
+    config.key_log = policy.key_log.clone();
+    config.resumption = Resumption::store(Arc::new(RecordingSessionStore {
+        inner: ClientSessionMemoryCache::new(256),
+        capture: capture.clone(),
+    }));
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_san_covers_sni() {
+        let sans = vec!["example.com".to_string(), "*.cdn.example.com".to_string(), "192.0.2.1".to_string()];
+
+        assert!(san_covers(&sans, "example.com"));
+        assert!(san_covers(&sans, "EXAMPLE.com."));
+        assert!(san_covers(&sans, "a.cdn.example.com"));
+        assert!(!san_covers(&sans, "cdn.example.com"));
+        assert!(!san_covers(&sans, "a.b.cdn.example.com"));
+        assert!(!san_covers(&sans, "www.example.com"));
+        assert!(san_covers(&sans, "192.0.2.1"));
+        assert!(!san_covers(&sans, "192.0.2.2"));
+    }
+
+    #[test]
+    fn test_inspect_generated_certificate() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string(), "*.example.com".to_string()]).unwrap();
+        let der = CertificateDer::from(cert.serialize_der().unwrap());
+
+        let report = CertificateReport::inspect("a.example.com", std::slice::from_ref(&der), false, None, 0);
+        let leaf = &report.chain[0];
+        assert!(report.san_covers_sni);
+        assert_eq!(leaf.sans, vec!["localhost", "*.example.com"]);
+        assert_eq!(leaf.key_type, "EC 256");
+        assert_eq!(leaf.sha256_fingerprint.len(), 64);
+        assert_eq!(leaf.spki_pin.len(), "sha256/".len() + 44);
+        assert_eq!(report.leaf_fingerprint.as_ref(), Some(&leaf.sha256_fingerprint));
+        assert!(!report.embedded_sct);
+        assert_eq!(report.warnings, vec!["叶子证书为自签名"]);
+
+        // rcgen 默认有效期到 4096 年；把当前时间放到过期之后
+        let expired = CertificateReport::inspect("other.test", &[der], false, Some("UnknownIssuer".to_string()), i64::MAX / 2);
+        assert!(!expired.san_covers_sni);
+        assert!(expired.warnings.iter().any(|w| w.starts_with("SAN 不包含")));
+        assert!(expired.warnings.iter().any(|w| w.starts_with("证书已过期")));
+        assert!(expired.warnings.iter().any(|w| w.starts_with("证书验证失败")));
+    }
+}
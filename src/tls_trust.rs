@@ -0,0 +1,322 @@
+// TLS 信任配置 - 各测试任务可选的信任根 (系统 / webpki / PEM)、SPKI 固定、insecure 模式、客户端证书、密钥交换组与密钥日志
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use rustls::client::danger::ServerCertVerifier;
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, KeyLog, KeyLogFile, RootCertStore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::ech::EchOffer;
+
+// --- 1. 信任根来源 ---
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustRoots {
+    #[default]
+    Native, // 系统证书库 (rustls-native-certs)
+    Webpki, // 内置的 Mozilla 根证书 (webpki-roots)，不受本机证书库影响
+    Pem,    // 只信任 ca_file 中的证书
+}
+
+impl fmt::Display for TrustRoots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Native => "native",
+            Self::Webpki => "webpki",
+            Self::Pem => "pem",
+        })
+    }
+}
+
+impl FromStr for TrustRoots {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" | "system" => Ok(Self::Native),
+            "webpki" | "mozilla" => Ok(Self::Webpki),
+            "pem" => Ok(Self::Pem),
+            other => Err(format!("不支持的信任根: {} (可选 native, webpki, pem)", other)),
+        }
+    }
+}
+
+// --- 2. trust 配置块 ---
+// 所有字段可选，默认只信任系统证书库
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TlsTrustOptions {
+    pub roots: TrustRoots,
+    pub ca_file: Option<String>, // PEM 证书包；roots 为 native / webpki 时追加到信任根中
+    pub spki_pins: Vec<String>,  // "sha256/<base64>"，证书链中任一证书的 SPKI 匹配即通过
+    pub insecure: bool,          // 跳过证书链与主机名验证，仍记录验证结果；SPKI 固定照常生效
+    pub client_cert: Option<String>, // mTLS 客户端证书链 (PEM)，需与 client_key 同时设置
+    pub client_key: Option<String>,  // mTLS 客户端私钥 (PEM，PKCS#8 / PKCS#1 / SEC1)
+    pub key_exchange: KeyExchange,   // ClientHello 中提供的密钥交换组
+    pub keylog: Option<String>,      // NSS 密钥日志文件，未设置时读取 SSLKEYLOGFILE 环境变量
+}
+
+impl TlsTrustOptions {
+    pub fn root_store(&self) -> Result<RootCertStore> {
+        let mut root_store = RootCertStore::empty();
+        match self.roots {
+            TrustRoots::Native => match rustls_native_certs::load_native_certs() {
+                Ok(certs) => {
+                    for cert in certs {
+                        if let Err(e) = root_store.add(cert) {
+                            eprintln!("Failed to parse trust anchor: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Couldn't load default trust roots: {}", e);
+                }
+            },
+            TrustRoots::Webpki => root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            TrustRoots::Pem if self.ca_file.is_none() => bail!("roots 为 pem 时必须指定 ca_file"),
+            TrustRoots::Pem => {}
+        }
+
+        if let Some(path) = &self.ca_file {
+            let certs = CertificateDer::pem_file_iter(path)
+                .with_context(|| format!("无法读取 CA 文件: {}", path))?
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("无法解析 CA 文件: {}", path))?;
+            let (added, _) = root_store.add_parsable_certificates(certs);
+            if added == 0 {
+                bail!("CA 文件中没有可用的证书: {}", path);
+            }
+        }
+
+        Ok(root_store)
+    }
+
+    pub fn build(&self) -> Result<TrustPolicy> {
+        let mut policy = TrustPolicy::from_root_store(self.root_store()?)?;
+        policy.spki_pins = self.spki_pins.iter().map(|pin| parse_spki_pin(pin)).collect::<Result<_>>()?;
+        policy.insecure = self.insecure;
+        policy.client_cert = self.client_certified_key()?;
+        policy.provider = self.key_exchange.provider()?;
+        policy.key_log = key_log(self.keylog.as_deref())?;
+        Ok(policy)
+    }
+
+    fn client_certified_key(&self) -> Result<Option<Arc<CertifiedKey>>> {
+        let (cert_path, key_path) = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => (cert, key),
+            (None, None) => return Ok(None),
+            _ => bail!("client_cert 与 client_key 必须同时指定"),
+        };
+
+        let chain = CertificateDer::pem_file_iter(cert_path)
+            .with_context(|| format!("无法读取客户端证书: {}", cert_path))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("无法解析客户端证书: {}", cert_path))?;
+        if chain.is_empty() {
+            bail!("客户端证书文件中没有证书: {}", cert_path);
+        }
+        let key = PrivateKeyDer::from_pem_file(key_path)
+            .with_context(|| format!("无法读取客户端私钥: {}", key_path))?;
+        let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+            .with_context(|| format!("不支持的客户端私钥类型: {}", key_path))?;
+
+        Ok(Some(Arc::new(CertifiedKey::new(chain, signing_key))))
+    }
+}
+
+// "sha256/<base64>" 或不带前缀的 base64，解码后为 32 字节的 SHA-256
+pub fn parse_spki_pin(pin: &str) -> Result<[u8; 32]> {
+    let encoded = pin.trim();
+    let encoded = encoded.strip_prefix("sha256/").unwrap_or(encoded);
+    let digest = general_purpose::STANDARD
+        .decode(encoded)
+        .with_context(|| format!("无效的 SPKI 指纹: {}", pin))?;
+    <[u8; 32]>::try_from(digest).map_err(|_| anyhow!("SPKI 指纹应为 32 字节的 SHA-256: {}", pin))
+}
+
+// --- 3. 实际使用的验证策略 ---
+#[derive(Debug, Clone)]
+pub struct TrustPolicy {
+    pub verifier: Arc<dyn ServerCertVerifier>, // 基于信任根的标准验证
+    pub spki_pins: Vec<[u8; 32]>,
+    pub insecure: bool,
+    pub client_cert: Option<Arc<CertifiedKey>>, // 对端请求客户端证书时出示
+    pub ech: Option<EchOffer>, // 设置时以 ECH 加密内层 ClientHello，并只使用 TLS 1.3
+    pub provider: Arc<CryptoProvider>, // 握手使用的加密后端，决定提供的密钥交换组
+    pub key_log: Arc<dyn KeyLog>,
+}
+
+impl TrustPolicy {
+    pub fn from_root_store(root_store: RootCertStore) -> Result<Self> {
+        let provider = KeyExchange::Classic.provider()?;
+        let verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(root_store), provider.clone())
+            .build()
+            .context("Failed to build certificate verifier")?;
+        Ok(Self {
+            verifier,
+            spki_pins: Vec::new(),
+            insecure: false,
+            client_cert: None,
+            ech: None,
+            provider,
+            key_log: key_log(None)?,
+        })
+    }
+
+    // ClientHello 中按优先顺序提供的密钥交换组
+    pub fn offered_groups(&self) -> Vec<String> {
+        self.provider.kx_groups.iter().map(|group| format!("{:?}", group.name())).collect()
+    }
+}
+
+// --- 4. 密钥交换 ---
+// 当前构建是否包含 aws-lc-rs 后端 (pq 特性)，只有它提供后量子混合密钥交换
+pub const PQ_AVAILABLE: bool = cfg!(feature = "pq");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyExchange {
+    #[default]
+    Classic, // ring: X25519、P-256、P-384
+    Pq,      // aws-lc-rs: 优先 X25519MLKEM768，同时提供经典组供对端回退
+}
+
+impl KeyExchange {
+    pub fn provider(self) -> Result<Arc<CryptoProvider>> {
+        match self {
+            Self::Classic => Ok(Arc::new(rustls::crypto::ring::default_provider())),
+            #[cfg(feature = "pq")]
+            Self::Pq => {
+                use rustls::crypto::aws_lc_rs::{default_provider, kx_group};
+                Ok(Arc::new(CryptoProvider {
+                    kx_groups: vec![kx_group::X25519MLKEM768, kx_group::X25519, kx_group::SECP256R1, kx_group::SECP384R1],
+                    ..default_provider()
+                }))
+            }
+            #[cfg(not(feature = "pq"))]
+            Self::Pq => bail!("当前构建不支持 X25519MLKEM768，请使用 --features pq 重新编译"),
+        }
+    }
+}
+
+impl fmt::Display for KeyExchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Classic => "classic",
+            Self::Pq => "pq",
+        })
+    }
+}
+
+impl FromStr for KeyExchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "classic" => Ok(Self::Classic),
+            "pq" | "x25519mlkem768" => Ok(Self::Pq),
+            other => Err(format!("不支持的密钥交换: {} (可选 classic, pq)", other)),
+        }
+    }
+}
+
+// --- 5. 密钥日志 ---
+// 按 NSS Key Log 格式追加写入 TLS 密钥，配合抓包在 Wireshark 中解密 QUIC 与 TLS 流量
+#[derive(Debug)]
+struct KeyLogWriter(Mutex<File>);
+
+impl KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+        if let Err(e) = self.0.lock().unwrap().write_all(line.as_bytes()) {
+            eprintln!("写入密钥日志失败: {}", e);
+        }
+    }
+}
+
+// 指定路径时写入该文件，否则交给 rustls 按 SSLKEYLOGFILE 环境变量处理 (未设置时不记录)
+pub fn key_log(path: Option<&str>) -> Result<Arc<dyn KeyLog>> {
+    match path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("无法打开密钥日志文件: {}", path))?;
+            Ok(Arc::new(KeyLogWriter(Mutex::new(file))))
+        }
+        None => Ok(Arc::new(KeyLogFile::new())),
+    }
+}
+
+// 不使用信任配置的 reqwest 客户端 (DoH 查询、h2 对照) 的 TLS 配置：
+// 与 reqwest 默认相同使用内置 webpki 根证书，只额外记录密钥日志
+pub fn keylog_client_config(key_log: Arc<dyn KeyLog>, alpn_protocols: &[&[u8]]) -> Result<ClientConfig> {
+    let mut root_store = RootCertStore::empty();
+    root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let mut config = ClientConfig::builder_with_provider(KeyExchange::Classic.provider()?)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+    config.key_log = key_log;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trust_block_from_json() {
+        let options: TlsTrustOptions = serde_json::from_str(
+            r#"{"roots": "webpki", "spki_pins": ["sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="], "insecure": true}"#,
+        )
+        .unwrap();
+
+        assert_eq!(options.roots, TrustRoots::Webpki);
+        assert!(options.insecure);
+        let policy = options.build().unwrap();
+        assert_eq!(policy.spki_pins, vec![*ring::digest::digest(&ring::digest::SHA256, b"").as_ref().first_chunk().unwrap()]);
+        assert_eq!(serde_json::from_str::<TlsTrustOptions>("{}").unwrap(), TlsTrustOptions::default());
+    }
+
+    #[test]
+    fn test_invalid_trust_options() {
+        assert!(parse_spki_pin("sha256/not-base64!").is_err());
+        assert!(parse_spki_pin("c2hvcnQ=").is_err());
+        assert!("pinned".parse::<TrustRoots>().is_err());
+        assert!(TlsTrustOptions { roots: TrustRoots::Pem, ..Default::default() }.build().is_err());
+        assert!(TlsTrustOptions { roots: TrustRoots::Webpki, client_cert: Some("cert.pem".to_string()), ..Default::default() }
+            .build()
+            .is_err());
+        assert!(TlsTrustOptions { ca_file: Some("/nonexistent/ca.pem".to_string()), ..Default::default() }
+            .root_store()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_exchange_provider() {
+        assert_eq!("PQ".parse::<KeyExchange>().unwrap(), KeyExchange::Pq);
+        assert!("kyber".parse::<KeyExchange>().is_err());
+
+        let classic = TlsTrustOptions::default().build().unwrap();
+        assert_eq!(classic.offered_groups(), vec!["X25519", "secp256r1", "secp384r1"]);
+        let pq = TlsTrustOptions { key_exchange: KeyExchange::Pq, ..Default::default() }.build();
+        assert_eq!(pq.is_ok(), PQ_AVAILABLE);
+        match pq {
+            Ok(pq) => assert_eq!(pq.offered_groups()[0], "X25519MLKEM768"),
+            Err(e) => assert!(e.to_string().contains("--features pq")),
+        }
+    }
+}